@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// An arbitrary-precision signed integer.
+///
+/// The magnitude is stored as little-endian limbs in base 10^9, which keeps
+/// conversion to and from decimal text cheap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt {
+            negative: false,
+            limbs: vec![],
+        }
+    }
+
+    pub fn one() -> Self {
+        BigInt::from(1)
+    }
+
+    /// Returns 10^exponent.
+    pub fn pow10(exponent: usize) -> Self {
+        BigInt::one().mul_pow10(exponent)
+    }
+
+    /// Parses an optionally signed string of decimal digits.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let bytes = digits.as_bytes();
+        let mut limbs = vec![];
+        let mut end = bytes.len();
+        while end > 0 {
+            let start = end.saturating_sub(BASE_DIGITS);
+            let chunk = std::str::from_utf8(&bytes[start..end]).ok()?;
+            limbs.push(chunk.parse().ok()?);
+            end = start;
+        }
+        Some(BigInt::from_parts(negative, limbs))
+    }
+
+    /// Converts an integral `f64` exactly, returning `None` for fractions,
+    /// infinities and NaN.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() || value.fract() != 0.0 {
+            return None;
+        }
+        BigInt::parse(&format!("{:.0}", value))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Converts to `i64` when the value fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.to_string().parse().ok()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.limbs.first().is_none_or(|limb| limb % 2 == 0)
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt {
+            negative: false,
+            limbs: self.limbs.clone(),
+        }
+    }
+
+    /// The number of decimal digits in the magnitude (zero has one digit).
+    pub fn digits(&self) -> usize {
+        match self.limbs.last() {
+            None => 1,
+            Some(top) => (self.limbs.len() - 1) * BASE_DIGITS + top.to_string().len(),
+        }
+    }
+
+    /// Multiplies by 10^exponent.
+    pub fn mul_pow10(&self, exponent: usize) -> Self {
+        if self.is_zero() {
+            return self.clone();
+        }
+        let mut limbs = vec![0; exponent / BASE_DIGITS];
+        limbs.extend_from_slice(&self.limbs);
+        let scaled = BigInt::from_parts(self.negative, limbs);
+        &scaled * &BigInt::from(10i64.pow((exponent % BASE_DIGITS) as u32))
+    }
+
+    pub fn pow(&self, mut exponent: u64) -> Self {
+        let mut base = self.clone();
+        let mut result = BigInt::one();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// Truncating division, returning the quotient and a remainder with the
+    /// sign of the dividend. Returns `None` when dividing by zero.
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = divrem_magnitude(&self.limbs, &divisor.limbs);
+        Some((
+            BigInt::from_parts(self.negative != divisor.negative, quotient),
+            BigInt::from_parts(self.negative, remainder),
+        ))
+    }
+
+    /// Truncating division by 10^exponent, cheaper than `div_rem` because
+    /// whole limbs are split off directly.
+    pub fn div_rem_pow10(&self, exponent: usize) -> (BigInt, BigInt) {
+        let whole = (exponent / BASE_DIGITS).min(self.limbs.len());
+        let (low, high) = self.limbs.split_at(whole);
+        let divisor = 10u32.pow((exponent - whole * BASE_DIGITS).min(BASE_DIGITS) as u32);
+        let (quotient, remainder) = divrem_small(high, divisor);
+
+        let mut limbs = low.to_vec();
+        limbs.push(remainder);
+        (
+            BigInt::from_parts(self.negative, quotient),
+            BigInt::from_parts(self.negative, limbs),
+        )
+    }
+
+    /// Truncating division by a small positive divisor.
+    pub fn div_small(&self, divisor: u32) -> Self {
+        let (quotient, _) = divrem_small(&self.limbs, divisor);
+        BigInt::from_parts(self.negative, quotient)
+    }
+
+    /// The remainder of the magnitude modulo a small positive divisor.
+    pub fn rem_small(&self, divisor: u32) -> u32 {
+        divrem_small(&self.limbs, divisor).1
+    }
+
+    /// The integer square root of a non-negative value.
+    pub fn isqrt(&self) -> Option<Self> {
+        if self.negative {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(BigInt::zero());
+        }
+        let mut x = BigInt::pow10(self.digits().div_ceil(2));
+        loop {
+            let (quotient, _) = self.div_rem(&x)?;
+            let y = (&x + &quotient).div_small(2);
+            if y.cmp(&x) != Ordering::Less {
+                return Some(x);
+            }
+            x = y;
+        }
+    }
+
+    fn from_parts(negative: bool, mut limbs: Vec<u32>) -> Self {
+        trim(&mut limbs);
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = vec![];
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::from_parts(value < 0, limbs)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitude(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: Self) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(self.negative, add_magnitude(&self.limbs, &rhs.limbs));
+        }
+        match cmp_magnitude(&self.limbs, &rhs.limbs) {
+            Ordering::Less => {
+                BigInt::from_parts(rhs.negative, sub_magnitude(&rhs.limbs, &self.limbs))
+            }
+            _ => BigInt::from_parts(self.negative, sub_magnitude(&self.limbs, &rhs.limbs)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: Self) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: Self) -> BigInt {
+        BigInt::from_parts(
+            self.negative != rhs.negative,
+            mul_magnitude(&self.limbs, &rhs.limbs),
+        )
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.limbs.clone())
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = String::new();
+        match self.limbs.split_last() {
+            None => text.push('0'),
+            Some((top, rest)) => {
+                text.push_str(&top.to_string());
+                for limb in rest.iter().rev() {
+                    text.push_str(&format!("{:09}", limb));
+                }
+            }
+        }
+        f.pad_integral(!self.negative, "", &text)
+    }
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Subtracts `b` from `a`, where `a` must not be smaller than `b`.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut difference = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let current = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = current % BASE;
+            carry = current / BASE;
+        }
+        result[i + b.len()] = carry;
+    }
+    let mut limbs = result.into_iter().map(|limb| limb as u32).collect();
+    trim(&mut limbs);
+    limbs
+}
+
+fn divrem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = 0u64;
+    for i in (0..a.len()).rev() {
+        let current = remainder * BASE + a[i] as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+fn divrem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = divrem_small(a, b[0]);
+        let mut remainder = vec![remainder];
+        trim(&mut remainder);
+        return (quotient, remainder);
+    }
+
+    // schoolbook long division, estimating each quotient limb from the
+    // leading limbs and correcting the (small) underestimate afterwards
+    let top = b[b.len() - 1] as u128 * BASE as u128 + b[b.len() - 2] as u128;
+    let mut quotient = vec![0; a.len()];
+    let mut remainder: Vec<u32> = vec![];
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        trim(&mut remainder);
+        if cmp_magnitude(&remainder, b) == Ordering::Less {
+            continue;
+        }
+
+        let n = remainder.len();
+        let mut leading = remainder[n - 1] as u128 * BASE as u128 + remainder[n - 2] as u128;
+        if n > b.len() {
+            leading = leading * BASE as u128 + remainder[n - 3] as u128;
+        }
+        let mut digit = (leading / (top + 1)) as u32;
+        remainder = sub_magnitude(&remainder, &mul_magnitude(b, &[digit]));
+        while cmp_magnitude(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, b);
+            digit += 1;
+        }
+        quotient[i] = digit;
+    }
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(big("0").to_string(), "0");
+        assert_eq!(big("-000123").to_string(), "-123");
+        assert_eq!(
+            big("123456789012345678901234567890").to_string(),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(BigInt::parse("12a"), None);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("99999999999999999999");
+        let b = big("-1");
+        assert_eq!((&a - &b).to_string(), "100000000000000000000");
+        assert_eq!((&b - &a).to_string(), "-100000000000000000000");
+        assert_eq!(
+            (&a * &a).to_string(),
+            "9999999999999999999800000000000000000001"
+        );
+        assert_eq!(
+            BigInt::from(2).pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let a = big("1267650600228229401496703205376");
+        let b = big("-1000000000000000000007");
+        let (quotient, remainder) = a.div_rem(&b).unwrap();
+        assert_eq!(quotient.to_string(), "-1267650600");
+        assert_eq!(&(&quotient * &b) + &remainder, a);
+        assert_eq!(a.div_rem(&BigInt::zero()), None);
+
+        let (quotient, remainder) = (-&a).div_rem_pow10(20);
+        assert_eq!(quotient.to_string(), "-12676506002");
+        assert_eq!(remainder.to_string(), "-28229401496703205376");
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(BigInt::pow10(40).isqrt(), Some(BigInt::pow10(20)));
+        assert_eq!(big("99").isqrt(), Some(big("9")));
+        assert_eq!(big("-4").isqrt(), None);
+    }
+}
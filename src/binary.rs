@@ -0,0 +1,588 @@
+use crate::bytecode::{Chunk, CompiledFunction, Deferred, Instruction, Program};
+use crate::enums::Expression;
+use crate::format::Format;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::units::Unit;
+
+/// The first bytes of every compiled file.
+pub const MAGIC: &[u8; 4] = b"RTCB";
+
+/// The version of the file format, which must change whenever the encoding
+/// or the meaning of an instruction does.
+pub const VERSION: u16 = 2;
+
+/// Encodes `program` as a compiled file: the magic bytes, the format
+/// version, a CRC-32 of the rest, then the program's tables and code.
+/// Expressions, units and formats are stored as their source text.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut payload = Writer::default();
+    payload.strings(&program.globals);
+    payload.count(program.numbers.len());
+    for &number in &program.numbers {
+        payload.bytes(&number.to_le_bytes());
+    }
+    payload.strings(&program.strings);
+    payload.count(program.units.len());
+    for unit in &program.units {
+        payload.string(&unit.name);
+    }
+    payload.count(program.formats.len());
+    for format in &program.formats {
+        payload.string(&format.to_string());
+    }
+    payload.count(program.pragmas.len());
+    for (name, argument) in &program.pragmas {
+        payload.string(name);
+        payload.string(&argument.to_string());
+    }
+    payload.count(program.functions.len());
+    for function in &program.functions {
+        payload.count(function.slot);
+        payload.strings(&function.parameters);
+        payload.string(&function.body.to_string());
+        payload.chunk(&function.chunk);
+    }
+    payload.count(program.deferred.len());
+    for deferred in &program.deferred {
+        payload.string(&deferred.expression.to_string());
+        payload.count(deferred.locals.len());
+        for (name, slot) in &deferred.locals {
+            payload.string(name);
+            payload.count(*slot);
+        }
+    }
+    payload.chunk(&program.main);
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(crc32(&payload.0).to_le_bytes());
+    bytes.extend(payload.0);
+    bytes
+}
+
+/// Decodes a compiled file, rejecting other versions of the format, files
+/// whose checksum does not match and code the virtual machine cannot run.
+pub fn decode(bytes: &[u8]) -> Result<Program, String> {
+    let rest = bytes
+        .strip_prefix(MAGIC)
+        .ok_or("Not a compiled Rustic file")?;
+    let mut header = Reader(rest);
+    let version = u16::from_le_bytes(header.array()?);
+    if version != VERSION {
+        return Err(format!(
+            "Compiled file has format version {}, but this rustic reads version {}; recompile it",
+            version, VERSION
+        ));
+    }
+    let checksum = u32::from_le_bytes(header.array()?);
+    if crc32(header.0) != checksum {
+        return Err("Compiled file is corrupt: checksum mismatch".to_owned());
+    }
+
+    let mut reader = header;
+    let globals = reader.strings()?;
+    let numbers = (0..reader.count()?)
+        .map(|_| reader.array().map(f64::from_le_bytes))
+        .collect::<Result<_, _>>()?;
+    let strings = reader.strings()?;
+    let units = (0..reader.count()?)
+        .map(|_| unit(&reader.string()?))
+        .collect::<Result<_, _>>()?;
+    let formats = (0..reader.count()?)
+        .map(|_| Format::parse(&reader.string()?))
+        .collect::<Result<_, _>>()?;
+    let pragmas = (0..reader.count()?)
+        .map(|_| {
+            let name = reader.string()?;
+            let argument = match reader.string()? {
+                argument if argument.is_empty() => Expression::None,
+                argument => expression(&argument)?,
+            };
+            Ok((name, argument))
+        })
+        .collect::<Result<_, String>>()?;
+    let functions = (0..reader.count()?)
+        .map(|_| {
+            Ok(CompiledFunction {
+                slot: reader.count()?,
+                parameters: reader.strings()?,
+                body: expression(&reader.string()?)?,
+                chunk: reader.chunk()?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    let deferred = (0..reader.count()?)
+        .map(|_| {
+            let expression = expression(&reader.string()?)?;
+            let locals = (0..reader.count()?)
+                .map(|_| Ok((reader.string()?, reader.count()?)))
+                .collect::<Result<_, String>>()?;
+            Ok(Deferred { expression, locals })
+        })
+        .collect::<Result<_, String>>()?;
+    let main = reader.chunk()?;
+    if !reader.0.is_empty() {
+        return Err("Compiled file is corrupt: trailing bytes".to_owned());
+    }
+
+    let program = Program {
+        globals,
+        numbers,
+        strings,
+        units,
+        formats,
+        pragmas,
+        functions,
+        deferred,
+        main,
+    };
+    verify(&program).map_err(|error| format!("Compiled file is corrupt: {}", error))?;
+    Ok(program)
+}
+
+/// Checks what the virtual machine relies on the compiler for: that every
+/// operand indexes its table, every jump lands in its chunk, and every
+/// instruction finds the values it pops, whichever way control reaches it.
+fn verify(program: &Program) -> Result<(), String> {
+    for function in &program.functions {
+        if function.slot >= program.globals.len() {
+            return Err(format!("function slot {} out of range", function.slot));
+        }
+        if function.chunk.locals < function.parameters.len() {
+            return Err("function with fewer locals than parameters".to_owned());
+        }
+        // a function body leaves its result on the stack
+        verify_chunk(program, &function.chunk, 1)?;
+    }
+    verify_chunk(program, &program.main, 0)
+}
+
+/// Checks one chunk, which must end with `results` values on the stack.
+fn verify_chunk(program: &Program, chunk: &Chunk, results: usize) -> Result<(), String> {
+    let check = |index: usize, length: usize, what: &str| match index < length {
+        true => Ok(()),
+        false => Err(format!("{} {} out of range", what, index)),
+    };
+    let length = chunk.code.len();
+    // the stack depth on entry to each instruction, once control reaches it
+    let mut depths: Vec<Option<usize>> = vec![None; length + 1];
+    let mut pending = vec![(0, 0)];
+    while let Some((pc, depth)) = pending.pop() {
+        match depths[pc] {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(format!("unbalanced stack at instruction {}", pc)),
+            None => depths[pc] = Some(depth),
+        }
+        let Some(&instruction) = chunk.code.get(pc) else {
+            if depth != results {
+                return Err(format!("chunk ends with {} value(s) on the stack", depth));
+            }
+            continue;
+        };
+
+        let (pops, pushes) = match instruction {
+            Instruction::Number(index) => {
+                check(index, program.numbers.len(), "number")?;
+                (0, 1)
+            }
+            Instruction::Literal(index) => {
+                check(index, program.strings.len(), "string")?;
+                (0, 1)
+            }
+            Instruction::Load(slot) => {
+                check(slot, program.globals.len(), "global")?;
+                (0, 1)
+            }
+            Instruction::Store(slot) => {
+                check(slot, program.globals.len(), "global")?;
+                (1, 0)
+            }
+            Instruction::LoadLocal(slot) => {
+                check(slot, chunk.locals, "local")?;
+                (0, 1)
+            }
+            Instruction::StoreLocal(slot) => {
+                check(slot, chunk.locals, "local")?;
+                (1, 0)
+            }
+            Instruction::Add
+            | Instruction::Subtract
+            | Instruction::Multiply
+            | Instruction::Divide
+            | Instruction::Power
+            | Instruction::Interval
+            | Instruction::PlusMinus => (2, 1),
+            Instruction::Negate | Instruction::Factorial | Instruction::Degrees => (1, 1),
+            Instruction::Quantity(index) | Instruction::Convert(index) => {
+                check(index, program.units.len(), "unit")?;
+                (1, 1)
+            }
+            Instruction::List(length) => (length, 1),
+            Instruction::Call(slot, count) => {
+                check(slot, program.globals.len(), "global")?;
+                (count, 1)
+            }
+            Instruction::Define(index) => {
+                check(index, program.functions.len(), "function")?;
+                (0, 0)
+            }
+            Instruction::Pop => (1, 0),
+            Instruction::Text(index) => {
+                check(index, program.strings.len(), "string")?;
+                (0, 0)
+            }
+            Instruction::Write(format) => {
+                if let Some(index) = format {
+                    check(index, program.formats.len(), "format")?;
+                }
+                (1, 0)
+            }
+            Instruction::PrintLine => (0, 0),
+            Instruction::SetFormat(format) => {
+                if let Some(index) = format {
+                    check(index, program.formats.len(), "format")?;
+                }
+                (0, 0)
+            }
+            Instruction::Pragma(index) => {
+                check(index, program.pragmas.len(), "pragma")?;
+                (0, 0)
+            }
+            Instruction::Bounds(index) => {
+                check(index, program.strings.len(), "string")?;
+                (2, 2)
+            }
+            Instruction::ForLoop {
+                counter,
+                last,
+                index,
+                exit,
+            } => {
+                for slot in [counter, last, index] {
+                    check(slot, chunk.locals, "local")?;
+                }
+                check(exit, length + 1, "jump target")?;
+                pending.push((exit, depth));
+                (0, 0)
+            }
+            Instruction::Next { counter, start } => {
+                check(counter, chunk.locals, "local")?;
+                check(start, length + 1, "jump target")?;
+                pending.push((start, depth));
+                continue;
+            }
+            Instruction::Evaluate(index) => {
+                check(index, program.deferred.len(), "deferred expression")?;
+                for &(_, slot) in &program.deferred[index].locals {
+                    check(slot, chunk.locals, "local")?;
+                }
+                (0, 1)
+            }
+        };
+        if pops > depth {
+            return Err(format!("stack underflow at instruction {}", pc));
+        }
+        pending.push((pc + 1, depth - pops + pushes));
+    }
+    Ok(())
+}
+
+fn expression(text: &str) -> Result<Expression, String> {
+    let mut lexer = Lexer::new(text);
+    let mut parser = Parser::new(&mut lexer);
+    parser.parse_expression()
+}
+
+fn unit(name: &str) -> Result<Unit, String> {
+    match expression(&format!("1 {}", name))? {
+        Expression::Quantity(_, unit) => Ok(unit),
+        _ => Err(format!("Unknown unit: {}", name)),
+    }
+}
+
+/// The CRC-32 checksum used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb88320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// `Option<usize>` operands store `None` as this value.
+const NONE: u32 = u32::MAX;
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn count(&mut self, count: usize) {
+        self.bytes(&(count as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, text: &str) {
+        self.count(text.len());
+        self.bytes(text.as_bytes());
+    }
+
+    fn strings(&mut self, texts: &[String]) {
+        self.count(texts.len());
+        for text in texts {
+            self.string(text);
+        }
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.count(chunk.locals);
+        self.count(chunk.code.len());
+        for &instruction in &chunk.code {
+            let option = |operand: Option<usize>| operand.unwrap_or(NONE as usize);
+            let (opcode, operands) = match instruction {
+                Instruction::Number(index) => (0, vec![index]),
+                Instruction::Literal(index) => (1, vec![index]),
+                Instruction::Load(slot) => (2, vec![slot]),
+                Instruction::Store(slot) => (3, vec![slot]),
+                Instruction::LoadLocal(slot) => (4, vec![slot]),
+                Instruction::StoreLocal(slot) => (5, vec![slot]),
+                Instruction::Add => (6, vec![]),
+                Instruction::Subtract => (7, vec![]),
+                Instruction::Multiply => (8, vec![]),
+                Instruction::Divide => (9, vec![]),
+                Instruction::Power => (10, vec![]),
+                Instruction::Negate => (11, vec![]),
+                Instruction::Factorial => (12, vec![]),
+                Instruction::Degrees => (13, vec![]),
+                Instruction::Interval => (14, vec![]),
+                Instruction::PlusMinus => (15, vec![]),
+                Instruction::Quantity(index) => (16, vec![index]),
+                Instruction::Convert(index) => (17, vec![index]),
+                Instruction::List(length) => (18, vec![length]),
+                Instruction::Call(slot, count) => (19, vec![slot, count]),
+                Instruction::Define(index) => (20, vec![index]),
+                Instruction::Pop => (21, vec![]),
+                Instruction::Text(index) => (22, vec![index]),
+                Instruction::Write(format) => (23, vec![option(format)]),
+                Instruction::PrintLine => (24, vec![]),
+                Instruction::SetFormat(format) => (25, vec![option(format)]),
+                Instruction::Pragma(index) => (26, vec![index]),
+                Instruction::Bounds(index) => (27, vec![index]),
+                Instruction::ForLoop {
+                    counter,
+                    last,
+                    index,
+                    exit,
+                } => (28, vec![counter, last, index, exit]),
+                Instruction::Next { counter, start } => (29, vec![counter, start]),
+                Instruction::Evaluate(index) => (30, vec![index]),
+            };
+            self.bytes(&[opcode]);
+            for operand in operands {
+                self.count(operand);
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (bytes, rest) = self
+            .0
+            .split_first_chunk()
+            .ok_or("Compiled file is corrupt: unexpected end")?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn option(&mut self) -> Result<Option<usize>, String> {
+        let operand = self.count()?;
+        Ok((operand != NONE as usize).then_some(operand))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.count()?;
+        if length > self.0.len() {
+            return Err("Compiled file is corrupt: unexpected end".to_owned());
+        }
+        let (text, rest) = self.0.split_at(length);
+        self.0 = rest;
+        String::from_utf8(text.to_vec())
+            .map_err(|_| "Compiled file is corrupt: bad text".to_owned())
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, String> {
+        (0..self.count()?).map(|_| self.string()).collect()
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, String> {
+        let locals = self.count()?;
+        let code = (0..self.count()?)
+            .map(|_| {
+                let [opcode] = self.array()?;
+                Ok(match opcode {
+                    0 => Instruction::Number(self.count()?),
+                    1 => Instruction::Literal(self.count()?),
+                    2 => Instruction::Load(self.count()?),
+                    3 => Instruction::Store(self.count()?),
+                    4 => Instruction::LoadLocal(self.count()?),
+                    5 => Instruction::StoreLocal(self.count()?),
+                    6 => Instruction::Add,
+                    7 => Instruction::Subtract,
+                    8 => Instruction::Multiply,
+                    9 => Instruction::Divide,
+                    10 => Instruction::Power,
+                    11 => Instruction::Negate,
+                    12 => Instruction::Factorial,
+                    13 => Instruction::Degrees,
+                    14 => Instruction::Interval,
+                    15 => Instruction::PlusMinus,
+                    16 => Instruction::Quantity(self.count()?),
+                    17 => Instruction::Convert(self.count()?),
+                    18 => Instruction::List(self.count()?),
+                    19 => Instruction::Call(self.count()?, self.count()?),
+                    20 => Instruction::Define(self.count()?),
+                    21 => Instruction::Pop,
+                    22 => Instruction::Text(self.count()?),
+                    23 => Instruction::Write(self.option()?),
+                    24 => Instruction::PrintLine,
+                    25 => Instruction::SetFormat(self.option()?),
+                    26 => Instruction::Pragma(self.count()?),
+                    27 => Instruction::Bounds(self.count()?),
+                    28 => Instruction::ForLoop {
+                        counter: self.count()?,
+                        last: self.count()?,
+                        index: self.count()?,
+                        exit: self.count()?,
+                    },
+                    29 => Instruction::Next {
+                        counter: self.count()?,
+                        start: self.count()?,
+                    },
+                    30 => Instruction::Evaluate(self.count()?),
+                    _ => {
+                        return Err(format!(
+                            "Compiled file is corrupt: unknown opcode {}",
+                            opcode
+                        ))
+                    }
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Chunk { code, locals })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::compile;
+
+    fn program(source: &str) -> Program {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        compile(&parser.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = program(
+            "#precision 20\nf(x, y) = x*(y + 2)\n#format ,.2f\nv = 90 km/h to m/s\ns = sum(f(k, 1), k, 1, 10)\nprint \"v = \", v : .1e, [1 .. 2]\ng(a) = solve(x^2 = a, x) + f'(a, 1)\ny = ode(dy/dt = -y, 1, 0, 1)",
+        );
+        assert_eq!(decode(&encode(&program)), Ok(program));
+    }
+
+    #[test]
+    fn test_rejected() {
+        let bytes = encode(&program("x = 1"));
+        assert_eq!(
+            decode(b"x = 1"),
+            Err("Not a compiled Rustic file".to_string())
+        );
+
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        assert_eq!(
+            decode(&newer),
+            Err(
+                "Compiled file has format version 3, but this rustic reads version 2; recompile it"
+                    .to_string()
+            )
+        );
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decode(&corrupt),
+            Err("Compiled file is corrupt: checksum mismatch".to_string())
+        );
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let crafted = |code: Vec<Instruction>| {
+            let mut program = program("f(x) = x\ny = f(2)");
+            program.main = Chunk { code, locals: 1 };
+            decode(&encode(&program))
+        };
+        assert!(crafted(vec![Instruction::Number(0), Instruction::Store(0)]).is_ok());
+        for (code, error) in [
+            (
+                vec![Instruction::Add, Instruction::Load(7)],
+                "stack underflow at instruction 0",
+            ),
+            (vec![Instruction::Load(7)], "global 7 out of range"),
+            (vec![Instruction::Number(5)], "number 5 out of range"),
+            (vec![Instruction::Text(9)], "string 9 out of range"),
+            (vec![Instruction::LoadLocal(1)], "local 1 out of range"),
+            (
+                vec![Instruction::Number(0)],
+                "chunk ends with 1 value(s) on the stack",
+            ),
+            (
+                vec![Instruction::Next {
+                    counter: 0,
+                    start: 9,
+                }],
+                "jump target 9 out of range",
+            ),
+            (
+                vec![
+                    Instruction::ForLoop {
+                        counter: 0,
+                        last: 0,
+                        index: 0,
+                        exit: 3,
+                    },
+                    Instruction::Number(0),
+                    Instruction::Next {
+                        counter: 0,
+                        start: 0,
+                    },
+                ],
+                "unbalanced stack at instruction 0",
+            ),
+        ] {
+            assert_eq!(
+                crafted(code),
+                Err(format!("Compiled file is corrupt: {}", error))
+            );
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::interval::Interval;
+use crate::number_theory;
+use crate::statistics::{self, Distribution};
+use crate::value::Value;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Built-ins that take an angle, in the session's angle mode.
+pub const TRIGONOMETRIC: [&str; 3] = ["sin", "cos", "tan"];
+
+/// Built-ins that return an angle, in the session's angle mode.
+pub const INVERSE_TRIGONOMETRIC: [&str; 3] = ["asin", "acos", "atan"];
+
+/// The unit in which trigonometric built-ins measure angles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Angle {
+    #[default]
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl Angle {
+    pub fn parse(name: &str) -> Option<Angle> {
+        match name {
+            "rad" | "radians" => Some(Angle::Radians),
+            "deg" | "degrees" => Some(Angle::Degrees),
+            "grad" | "gradians" => Some(Angle::Gradians),
+            _ => None,
+        }
+    }
+
+    /// The size of half a turn in this unit.
+    pub fn half_turn(self) -> f64 {
+        match self {
+            Angle::Radians => PI,
+            Angle::Degrees => 180.0,
+            Angle::Gradians => 200.0,
+        }
+    }
+}
+
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Angle::Radians => write!(f, "rad"),
+            Angle::Degrees => write!(f, "deg"),
+            Angle::Gradians => write!(f, "grad"),
+        }
+    }
+}
+
+/// The largest integer below which every integer is an exact `f64`.
+const MAX_EXACT_FLOAT: f64 = 9007199254740992.0;
+
+/// Calls the built-in function `name` with already evaluated arguments.
+pub fn call(name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+    match name {
+        "sqrt" => unary(name, arguments, f64::sqrt, Decimal::sqrt, Interval::sqrt),
+        "exp" => unary(name, arguments, f64::exp, Decimal::exp, |x| Ok(x.exp())),
+        "ln" => unary(name, arguments, f64::ln, Decimal::ln, Interval::ln),
+        "sin" => unary(name, arguments, f64::sin, |x| Ok(x.sin()), |x| Ok(x.sin())),
+        "cos" => unary(name, arguments, f64::cos, |x| Ok(x.cos()), |x| Ok(x.cos())),
+        "tan" => unary(name, arguments, f64::tan, Decimal::tan, Interval::tan),
+        "abs" => unary(name, arguments, f64::abs, |x| Ok(x.abs()), |x| Ok(x.abs())),
+        "asin" => unary(name, arguments, f64::asin, Decimal::asin, Interval::asin),
+        "acos" => unary(name, arguments, f64::acos, Decimal::acos, Interval::acos),
+        "atan" => unary(
+            name,
+            arguments,
+            f64::atan,
+            |x| Ok(x.atan()),
+            |x| Ok(x.atan()),
+        ),
+        "deg" | "rad" => {
+            let [angle] = expect_arguments(name, arguments)?;
+            let pi = match &angle {
+                Value::Decimal(angle) => Value::Decimal(Decimal::pi(angle.precision())),
+                _ => Value::Number(PI),
+            };
+            match name {
+                "deg" => (angle * Value::Number(180.0))? / pi,
+                _ => (angle * pi)? / Value::Number(180.0),
+            }
+        }
+        "mean" => sample(name, arguments, statistics::mean),
+        "median" => sample(name, arguments, statistics::median),
+        "mode" => sample(name, arguments, statistics::mode),
+        "variance" => sample(name, arguments, statistics::variance),
+        "stdev" => sample(name, arguments, statistics::stdev),
+        "percentile" => {
+            let [xs, p] = expect_arguments(name, arguments)?;
+            statistics::percentile(&numbers(name, xs)?, number(name, &p)?).map(Value::Number)
+        }
+        "covariance" => pairs(name, arguments, statistics::covariance),
+        "correlation" => pairs(name, arguments, statistics::correlation),
+        "linreg" => {
+            let [xs, ys] = expect_arguments(name, arguments)?;
+            let (slope, intercept, r_squared) =
+                statistics::linreg(&numbers(name, xs)?, &numbers(name, ys)?)?;
+            let fit = [slope, intercept, r_squared].map(Value::Number);
+            Ok(Value::List(fit.to_vec()))
+        }
+        "gcd" => {
+            let [a, b] = integers(name, arguments)?;
+            Ok(exact(number_theory::gcd(&a, &b)))
+        }
+        "lcm" => {
+            let [a, b] = integers(name, arguments)?;
+            Ok(exact(number_theory::lcm(&a, &b)))
+        }
+        "isprime" => {
+            let [n] = integers(name, arguments)?;
+            Ok(Value::Number(number_theory::is_prime(&n) as u8 as f64))
+        }
+        "nextprime" => {
+            let [n] = integers(name, arguments)?;
+            Ok(exact(number_theory::next_prime(&n)))
+        }
+        "factor" => {
+            let [n] = integers(name, arguments)?;
+            let factors = number_theory::factor(&n)?;
+            Ok(Value::List(factors.into_iter().map(exact).collect()))
+        }
+        "modpow" => {
+            let [base, exponent, modulus] = integers(name, arguments)?;
+            number_theory::modpow(&base, &exponent, &modulus).map(exact)
+        }
+        "modinv" => {
+            let [a, m] = integers(name, arguments)?;
+            number_theory::modinv(&a, &m).map(exact)
+        }
+        "totient" => {
+            let [n] = integers(name, arguments)?;
+            number_theory::totient(&n).map(exact)
+        }
+        "nCr" => {
+            let [n, k] = integers(name, arguments)?;
+            number_theory::choose(&n, &k).map(exact)
+        }
+        "nPr" => {
+            let [n, k] = integers(name, arguments)?;
+            number_theory::permutations(&n, &k).map(exact)
+        }
+        "fib" => {
+            let [n] = integers(name, arguments)?;
+            number_theory::fibonacci(&n).map(exact)
+        }
+        _ => match Distribution::split(name) {
+            Some((family, function)) => distribution(name, family, function, arguments),
+            None => Err(format!("Undefined function: {}", name)),
+        },
+    }
+}
+
+fn unary(
+    name: &str, arguments: Vec<Value>, float: fn(f64) -> f64,
+    decimal: fn(&Decimal) -> Result<Decimal, String>,
+    interval: fn(&Interval) -> Result<Interval, String>,
+) -> Result<Value, String> {
+    let [argument] = expect_arguments(name, arguments)?;
+    match argument {
+        Value::Number(x) => Ok(Value::Number(float(x))),
+        Value::Decimal(x) => decimal(&x).map(Value::Decimal),
+        Value::Interval(x) => interval(&x).map(Value::Interval),
+        Value::Quantity(x) => match name {
+            "sqrt" => x.map(0.5, |magnitude| call(name, vec![magnitude])),
+            "abs" => x.map(1.0, |magnitude| call(name, vec![magnitude])),
+            _ => Err(format!("{} expects a dimensionless argument", name)),
+        },
+        Value::Function(_) => Err(format!("{} expects a number, found a function", name)),
+        Value::List(values) => values
+            .into_iter()
+            .map(|value| call(name, vec![value]))
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+    }
+}
+
+/// Calls a statistic of a single list, such as `mean([1, 2, 3])`.
+fn sample(
+    name: &str, arguments: Vec<Value>, statistic: fn(&[f64]) -> Result<f64, String>,
+) -> Result<Value, String> {
+    let [xs] = expect_arguments(name, arguments)?;
+    statistic(&numbers(name, xs)?).map(Value::Number)
+}
+
+/// Calls a statistic of two paired lists, such as `covariance(xs, ys)`.
+fn pairs(
+    name: &str, arguments: Vec<Value>, statistic: fn(&[f64], &[f64]) -> Result<f64, String>,
+) -> Result<Value, String> {
+    let [xs, ys] = expect_arguments(name, arguments)?;
+    statistic(&numbers(name, xs)?, &numbers(name, ys)?).map(Value::Number)
+}
+
+/// Calls a distribution function such as `normcdf(x, mu, sigma)`, mapping
+/// over `x` when it is a list.
+fn distribution(
+    name: &str, family: &str, function: &str, mut arguments: Vec<Value>,
+) -> Result<Value, String> {
+    let arity = Distribution::arity(family) + 1;
+    if arguments.len() != arity {
+        return Err(format!(
+            "{} expects {} argument(s), found {}",
+            name,
+            arity,
+            arguments.len()
+        ));
+    }
+    let parameters = arguments
+        .split_off(1)
+        .iter()
+        .map(|parameter| number(name, parameter))
+        .collect::<Result<Vec<_>, _>>()?;
+    let distribution = Distribution::new(family, &parameters)?;
+    let apply = |x: &Value| {
+        let x = number(name, x)?;
+        let y = match function {
+            "pdf" => distribution.pdf(x),
+            "cdf" => distribution.cdf(x),
+            _ => distribution.quantile(x)?,
+        };
+        Ok(Value::Number(y))
+    };
+    match &arguments[0] {
+        Value::List(xs) => xs
+            .iter()
+            .map(apply)
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        x => apply(x),
+    }
+}
+
+/// Converts the arguments of a number theory function to exact integers.
+fn integers<const N: usize>(name: &str, arguments: Vec<Value>) -> Result<[BigInt; N], String> {
+    let integers = arguments
+        .iter()
+        .map(|argument| {
+            let integer = match argument {
+                // a larger float may already have been rounded, as 2^61 - 1 is
+                Value::Number(x) if x.abs() > MAX_EXACT_FLOAT => {
+                    return Err(format!(
+                        "{} expects integers up to 2^53 unless #precision is set, found {}",
+                        name, argument
+                    ));
+                }
+                Value::Number(x) => BigInt::from_f64(*x),
+                Value::Decimal(x) if x.is_integer() => Some(x.to_integer()),
+                _ => None,
+            };
+            integer.ok_or_else(|| format!("{} expects integers, found {}", name, argument))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    expect_arguments(name, integers)
+}
+
+/// Wraps an exact integer result as a number when `f64` holds it exactly, and
+/// as a decimal with every digit otherwise.
+fn exact(integer: BigInt) -> Value {
+    match integer.abs() <= BigInt::from(MAX_EXACT_FLOAT as i64) {
+        true => Value::Number(integer.to_f64()),
+        false => {
+            let digits = integer.digits();
+            Value::Decimal(Decimal::new(integer, 0, digits))
+        }
+    }
+}
+
+fn number(name: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Function(_) => Err(format!("{} expects a number, found a function", name)),
+        Value::List(_) => Err(format!("{} expects a number, found a list", name)),
+        // to_f64 would silently take the midpoint or drop the unit
+        Value::Interval(_) => Err(format!("{} expects a number, found an interval", name)),
+        Value::Quantity(_) => Err(format!("{} expects a number, found a quantity", name)),
+        value => Ok(value.to_f64()),
+    }
+}
+
+fn numbers(name: &str, value: Value) -> Result<Vec<f64>, String> {
+    match value {
+        Value::List(values) => values.iter().map(|value| number(name, value)).collect(),
+        value => Err(format!("{} expects a list, found {}", name, value)),
+    }
+}
+
+pub fn expect_arguments<T, const N: usize>(
+    name: &str, arguments: Vec<T>,
+) -> Result<[T; N], String> {
+    arguments.try_into().map_err(|arguments: Vec<T>| {
+        format!(
+            "{} expects {} argument(s), found {}",
+            name,
+            N,
+            arguments.len()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Quantity, Unit};
+
+    #[test]
+    fn test_call() {
+        assert_eq!(
+            call("sqrt", vec![Value::Number(9.0)]),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            call("sqrt", vec![Value::Decimal(Decimal::from_integer(2, 20))])
+                .unwrap()
+                .to_string(),
+            "1.4142135623730950488"
+        );
+    }
+
+    #[test]
+    fn test_call_interval() {
+        let interval = Value::Interval(Interval::new(4.0, 9.0).unwrap());
+        assert_eq!(
+            call("sqrt", vec![interval]),
+            Ok(Value::Interval(Interval::new(2.0, 3.0).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_call_errors() {
+        assert!(call("sqrt", vec![]).is_err());
+        assert!(call("nope", vec![Value::Number(1.0)]).is_err());
+    }
+
+    #[test]
+    fn test_call_statistics() {
+        let list = |xs: &[f64]| Value::List(xs.iter().copied().map(Value::Number).collect());
+        assert_eq!(
+            call("median", vec![list(&[3.0, 1.0, 2.0])]),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            call("linreg", vec![list(&[0.0, 1.0]), list(&[1.0, 3.0])]),
+            Ok(list(&[2.0, 1.0, 1.0]))
+        );
+        assert_eq!(
+            call(
+                "unifcdf",
+                vec![list(&[0.5, 2.0]), Value::Number(0.0), Value::Number(1.0)]
+            ),
+            Ok(list(&[0.5, 1.0]))
+        );
+        assert!(call("mean", vec![Value::Number(1.0)]).is_err());
+        assert!(call("normpdf", vec![Value::Number(1.0)]).is_err());
+
+        let interval = Value::Interval(Interval::new(1.0, 2.0).unwrap());
+        assert_eq!(
+            call(
+                "mean",
+                vec![Value::List(vec![interval, Value::Number(3.0)])]
+            ),
+            Err("mean expects a number, found an interval".to_string())
+        );
+        let metre = Unit::lookup("m").unwrap();
+        let length = Quantity::with_unit(Value::Number(1.0), &metre).unwrap();
+        assert_eq!(
+            call("mean", vec![Value::List(vec![length])]),
+            Err("mean expects a number, found a quantity".to_string())
+        );
+    }
+
+    #[test]
+    fn test_call_number_theory() {
+        let fib = call("fib", vec![Value::Number(100.0)]).unwrap();
+        assert!(matches!(fib, Value::Decimal(_)));
+        assert_eq!(fib.to_string(), "354224848179261915075");
+        assert_eq!(
+            call("gcd", vec![fib, Value::Number(5.0)]),
+            Ok(Value::Number(5.0))
+        );
+        assert_eq!(
+            call("factor", vec![Value::Number(12.0)]),
+            Ok(Value::List(vec![
+                Value::Number(2.0),
+                Value::Number(2.0),
+                Value::Number(3.0)
+            ]))
+        );
+        assert!(call("isprime", vec![Value::Number(2.5)]).is_err());
+
+        // 2^53 + 1 is not a float, so the result must stay a decimal
+        let odd = Value::Decimal(Decimal::new(BigInt::from(9007199254740993), 0, 16));
+        assert_eq!(
+            call("gcd", vec![odd.clone(), odd]).unwrap().to_string(),
+            "9007199254740993"
+        );
+        assert_eq!(
+            call("factor", vec![Value::Number(2f64.powi(61) - 1.0)]),
+            Err("factor expects integers up to 2^53 unless #precision is set, found 2305843009213694000".to_string())
+        );
+    }
+}
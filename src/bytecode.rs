@@ -0,0 +1,404 @@
+use crate::enums::{Expression, PrintItem, Statement};
+use crate::format::Format;
+use crate::interpreter::SPECIAL_FORMS;
+use crate::units::Unit;
+use std::collections::HashSet;
+
+/// One step of the virtual machine, which works on a stack of values.
+/// Operands index the tables of the `Program`, global slots or the local
+/// slots of the running chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Pushes `numbers[i]` at the session's precision.
+    Number(usize),
+    /// Pushes the number whose source text is `strings[i]`.
+    Literal(usize),
+    Load(usize),
+    Store(usize),
+    LoadLocal(usize),
+    StoreLocal(usize),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Negate,
+    Factorial,
+    Degrees,
+    /// Pops the upper and lower bound of an interval.
+    Interval,
+    /// Pops the radius and midpoint of an interval.
+    PlusMinus,
+    Quantity(usize),
+    Convert(usize),
+    /// Pops that many values into a list.
+    List(usize),
+    /// Calls the function in a global slot, or the built-in of that name
+    /// if the slot is empty, on that many arguments.
+    Call(usize, usize),
+    /// Binds `functions[i]` to its global slot.
+    Define(usize),
+    Pop,
+    /// Appends `strings[i]` to the line being printed.
+    Text(usize),
+    /// Pops a value and appends it to the line, in `formats[i]` if given.
+    Write(Option<usize>),
+    PrintLine,
+    SetFormat(Option<usize>),
+    Pragma(usize),
+    /// Checks that the two values on top of the stack are integer bounds
+    /// for the `sum` or `prod` named by `strings[i]`.
+    Bounds(usize),
+    /// Jumps to `exit` once the `counter` local passes the `last` one, and
+    /// otherwise binds the `index` local to the counter.
+    ForLoop {
+        counter: usize,
+        last: usize,
+        index: usize,
+        exit: usize,
+    },
+    /// Increments the `counter` local and jumps back to `start`.
+    Next {
+        counter: usize,
+        start: usize,
+    },
+    /// Pushes the value of `deferred[i]`, computed by the tree walker.
+    Evaluate(usize),
+}
+
+/// A sequence of instructions and the number of local slots it needs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub locals: usize,
+}
+
+/// A user function: its definition, kept for symbolic built-ins and
+/// printing, and its compiled body, whose first locals are the parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub slot: usize,
+    pub parameters: Vec<String>,
+    pub body: Expression,
+    pub chunk: Chunk,
+}
+
+/// An expression left to the tree-walking interpreter, such as a call to
+/// `diff`, with the local slot of each name in scope where it appears.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deferred {
+    pub expression: Expression,
+    pub locals: Vec<(String, usize)>,
+}
+
+/// A compiled program with the tables its instructions refer to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    /// The name of each global slot.
+    pub globals: Vec<String>,
+    pub numbers: Vec<f64>,
+    pub strings: Vec<String>,
+    pub units: Vec<Unit>,
+    pub formats: Vec<Format>,
+    pub pragmas: Vec<(String, Expression)>,
+    pub functions: Vec<CompiledFunction>,
+    pub deferred: Vec<Deferred>,
+    pub main: Chunk,
+}
+
+/// Compiles `statements`, resolving every variable to a slot.
+///
+/// Built-ins that work on expressions, such as `diff` and `solve`, and
+/// derivatives such as `f'` are left to the tree-walking interpreter, except
+/// for `sum` and `prod`, which compile to loops.
+pub fn compile(statements: &[Statement]) -> Result<Program, String> {
+    let mut defined = HashSet::new();
+    for statement in statements {
+        match statement {
+            Statement::Function(name, ..) | Statement::Assignment(name, _) => {
+                defined.insert(name.clone());
+            }
+            Statement::Assignments(names, _) => defined.extend(names.iter().cloned()),
+            _ => {}
+        }
+    }
+
+    let mut compiler = Compiler {
+        program: Program::default(),
+        defined,
+        chunk: Chunk::default(),
+        scope: vec![],
+    };
+    for statement in statements {
+        compiler.statement(statement)?;
+    }
+    let mut program = compiler.program;
+    program.main = compiler.chunk;
+    Ok(program)
+}
+
+fn unsupported(what: impl std::fmt::Display) -> String {
+    format!("compile::{} cannot be compiled", what)
+}
+
+struct Compiler {
+    program: Program,
+    /// Names the program assigns or defines, which shadow built-ins.
+    defined: HashSet<String>,
+    /// The chunk being compiled.
+    chunk: Chunk,
+    /// The local slot of each name in scope, innermost last.
+    scope: Vec<(String, usize)>,
+}
+
+impl Compiler {
+    fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Expression(expression) => {
+                self.expression(expression)?;
+                self.emit(Instruction::Pop);
+            }
+            Statement::Assignment(name, expression) => {
+                self.expression(expression)?;
+                let slot = self.global(name)?;
+                self.emit(Instruction::Store(slot));
+            }
+            Statement::Assignments(names, expressions) => {
+                for expression in expressions {
+                    self.expression(expression)?;
+                }
+                for name in names.iter().rev() {
+                    let slot = self.global(name)?;
+                    self.emit(Instruction::Store(slot));
+                }
+            }
+            Statement::Function(name, parameters, body) => {
+                let slot = self.global(name)?;
+                let outer = std::mem::take(&mut self.chunk);
+                self.scope = parameters.iter().cloned().zip(0..).collect();
+                self.chunk.locals = parameters.len();
+                let compiled = self.expression(body);
+                self.scope.clear();
+                let chunk = std::mem::replace(&mut self.chunk, outer);
+                compiled?;
+
+                self.program.functions.push(CompiledFunction {
+                    slot,
+                    parameters: parameters.clone(),
+                    body: body.clone(),
+                    chunk,
+                });
+                self.emit(Instruction::Define(self.program.functions.len() - 1));
+            }
+            Statement::Print(items) => {
+                for item in items {
+                    match item {
+                        PrintItem::Text(text) => {
+                            let index = self.string(text);
+                            self.emit(Instruction::Text(index));
+                        }
+                        PrintItem::Value(expression, format) => {
+                            self.expression(expression)?;
+                            let format = format.as_ref().map(|format| self.format(format));
+                            self.emit(Instruction::Write(format));
+                        }
+                    }
+                }
+                self.emit(Instruction::PrintLine);
+            }
+            Statement::Pragma(name, argument) => {
+                self.program.pragmas.push((name.clone(), argument.clone()));
+                self.emit(Instruction::Pragma(self.program.pragmas.len() - 1));
+            }
+            Statement::Format(format) => {
+                let format = format.as_ref().map(|format| self.format(format));
+                self.emit(Instruction::SetFormat(format));
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<(), String> {
+        let instruction = match expression {
+            Expression::Number(value) => {
+                self.program.numbers.push(*value);
+                Instruction::Number(self.program.numbers.len() - 1)
+            }
+            Expression::Literal(text) => Instruction::Literal(self.string(text)),
+            Expression::Variable(name) if name.ends_with('\'') && self.local(name).is_none() => {
+                return self.defer(expression);
+            }
+            Expression::Variable(name) => match self.local(name) {
+                Some(slot) => Instruction::LoadLocal(slot),
+                None => Instruction::Load(self.global(name)?),
+            },
+            Expression::Group(operand) => return self.expression(operand),
+            Expression::Plus(left, right) => self.binary(left, right, Instruction::Add)?,
+            Expression::Minus(left, right) => self.binary(left, right, Instruction::Subtract)?,
+            Expression::Asterisk(left, right) => self.binary(left, right, Instruction::Multiply)?,
+            Expression::Slash(left, right) => self.binary(left, right, Instruction::Divide)?,
+            Expression::Power(left, right) => self.binary(left, right, Instruction::Power)?,
+            Expression::Interval(left, right) => self.binary(left, right, Instruction::Interval)?,
+            Expression::PlusMinus(left, right) => {
+                self.binary(left, right, Instruction::PlusMinus)?
+            }
+            Expression::Negative(operand) => self.unary(operand, Instruction::Negate)?,
+            Expression::Factorial(operand) => self.unary(operand, Instruction::Factorial)?,
+            Expression::Degrees(operand) => self.unary(operand, Instruction::Degrees)?,
+            Expression::Quantity(operand, unit) => {
+                self.expression(operand)?;
+                self.program.units.push(unit.clone());
+                Instruction::Quantity(self.program.units.len() - 1)
+            }
+            Expression::Convert(operand, unit) => {
+                self.expression(operand)?;
+                self.program.units.push(unit.clone());
+                Instruction::Convert(self.program.units.len() - 1)
+            }
+            Expression::List(items) => {
+                for item in items {
+                    self.expression(item)?;
+                }
+                Instruction::List(items.len())
+            }
+            Expression::Call(name, arguments) => return self.call(name, arguments),
+            Expression::Equation(..) | Expression::None => return Err(unsupported(expression)),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn binary(
+        &mut self, left: &Expression, right: &Expression, instruction: Instruction,
+    ) -> Result<Instruction, String> {
+        self.expression(left)?;
+        self.expression(right)?;
+        Ok(instruction)
+    }
+
+    fn unary(
+        &mut self, operand: &Expression, instruction: Instruction,
+    ) -> Result<Instruction, String> {
+        self.expression(operand)?;
+        Ok(instruction)
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<(), String> {
+        if self.local(name).is_some() {
+            return Err(unsupported(format!("Calling the parameter {}", name)));
+        }
+        let is_special = SPECIAL_FORMS.contains(&name) && !self.defined.contains(name);
+        match name {
+            "sum" | "prod" if is_special => return self.iterate(name, arguments),
+            _ if is_special || name.ends_with('\'') => {
+                return self.defer(&Expression::Call(name.to_string(), arguments.to_vec()));
+            }
+            _ => {}
+        }
+
+        for argument in arguments {
+            self.expression(argument)?;
+        }
+        let slot = self.global(name)?;
+        self.emit(Instruction::Call(slot, arguments.len()));
+        Ok(())
+    }
+
+    /// Compiles `sum(expression, index, first, last)` or `prod` to a loop
+    /// that keeps the running total on the stack.
+    fn iterate(&mut self, name: &str, arguments: &[Expression]) -> Result<(), String> {
+        let [expression, Expression::Variable(index), first, last] = arguments else {
+            // the tree walker reports what is wrong with the call
+            return self.defer(&Expression::Call(name.to_string(), arguments.to_vec()));
+        };
+        let (counter, end, index_slot) = (
+            self.chunk.locals,
+            self.chunk.locals + 1,
+            self.chunk.locals + 2,
+        );
+        self.chunk.locals += 3;
+
+        self.expression(&Expression::Number(if name == "sum" { 0.0 } else { 1.0 }))?;
+        self.expression(first)?;
+        self.expression(last)?;
+        let name_index = self.string(name);
+        self.emit(Instruction::Bounds(name_index));
+        self.emit(Instruction::StoreLocal(end));
+        self.emit(Instruction::StoreLocal(counter));
+
+        let start = self.chunk.code.len();
+        self.emit(Instruction::ForLoop {
+            counter,
+            last: end,
+            index: index_slot,
+            exit: 0,
+        });
+        self.scope.push((index.clone(), index_slot));
+        let body = self.expression(expression);
+        self.scope.pop();
+        body?;
+        self.emit(match name {
+            "sum" => Instruction::Add,
+            _ => Instruction::Multiply,
+        });
+        self.emit(Instruction::Next { counter, start });
+
+        let exit = self.chunk.code.len();
+        if let Instruction::ForLoop { exit: target, .. } = &mut self.chunk.code[start] {
+            *target = exit;
+        }
+        Ok(())
+    }
+
+    fn defer(&mut self, expression: &Expression) -> Result<(), String> {
+        self.program.deferred.push(Deferred {
+            expression: expression.clone(),
+            locals: self.scope.clone(),
+        });
+        self.emit(Instruction::Evaluate(self.program.deferred.len() - 1));
+        Ok(())
+    }
+
+    fn local(&self, name: &str) -> Option<usize> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map(|&(_, slot)| slot)
+    }
+
+    fn global(&mut self, name: &str) -> Result<usize, String> {
+        // `f'` is differentiated when it is looked up
+        if name.ends_with('\'') {
+            return Err(unsupported(name));
+        }
+        let globals = &mut self.program.globals;
+        Ok(match globals.iter().position(|global| global == name) {
+            Some(slot) => slot,
+            None => {
+                globals.push(name.to_string());
+                globals.len() - 1
+            }
+        })
+    }
+
+    fn string(&mut self, text: &str) -> usize {
+        let strings = &mut self.program.strings;
+        match strings.iter().position(|string| string == text) {
+            Some(index) => index,
+            None => {
+                strings.push(text.to_string());
+                strings.len() - 1
+            }
+        }
+    }
+
+    fn format(&mut self, format: &Format) -> usize {
+        self.program.formats.push(format.clone());
+        self.program.formats.len() - 1
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.chunk.code.push(instruction);
+    }
+}
@@ -0,0 +1,627 @@
+use crate::enums::{Expression, PrintItem, Statement};
+use crate::interpreter::SPECIAL_FORMS;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// Built-ins that `<math.h>` provides, by their Rustic and C names.
+const MATH_FUNCTIONS: [(&str, &str); 10] = [
+    ("sqrt", "sqrt"),
+    ("exp", "exp"),
+    ("ln", "log"),
+    ("sin", "sin"),
+    ("cos", "cos"),
+    ("tan", "tan"),
+    ("abs", "fabs"),
+    ("asin", "asin"),
+    ("acos", "acos"),
+    ("atan", "atan"),
+];
+
+/// The runtime every generated program starts with. `show` prints a double
+/// the way Rust's `Display` does: the shortest digits that read back as the
+/// same number, without an exponent.
+const RUNTIME: &str = r#"#include <limits.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static void show(double x) {
+    char buffer[32], digits[32], exact[800];
+    int length = 0, exponent, i;
+    char *e;
+    if (isnan(x)) {
+        fputs("NaN", stdout);
+        return;
+    }
+    if (signbit(x)) {
+        putchar('-');
+        x = -x;
+    }
+    if (isinf(x)) {
+        fputs("inf", stdout);
+        return;
+    }
+    if (x == 0) {
+        putchar('0');
+        return;
+    }
+    for (i = 1; i <= 17; i++) {
+        snprintf(buffer, sizeof buffer, "%.*e", i - 1, x);
+        if (strtod(buffer, NULL) == x) {
+            break;
+        }
+    }
+    e = strchr(buffer, 'e');
+    exponent = atoi(e + 1);
+    for (i = 0; buffer + i < e; i++) {
+        if (buffer[i] != '.') {
+            digits[length++] = buffer[i];
+        }
+    }
+    /* printf rounds ties to even, where Rust rounds them up */
+    snprintf(exact, sizeof exact, "%.766e", x);
+    memmove(exact + 1, exact + 2, strlen(exact + 2) + 1);
+    if (exact[length] == '5' && exact[length - 1] == digits[length - 1]
+        && strspn(exact + length + 1, "0") == strcspn(exact + length + 1, "e")) {
+        for (i = length - 1; i >= 0 && digits[i] == '9'; i--) {
+            digits[i] = '0';
+        }
+        if (i < 0) {
+            digits[0] = '1';
+            exponent++;
+        } else {
+            digits[i]++;
+        }
+    }
+    while (length > 1 && digits[length - 1] == '0') {
+        length--;
+    }
+    if (exponent < 0) {
+        fputs("0.", stdout);
+        for (i = -1; i > exponent; i--) {
+            putchar('0');
+        }
+        fwrite(digits, 1, length, stdout);
+    } else if (exponent + 1 >= length) {
+        fwrite(digits, 1, length, stdout);
+        for (i = length; i <= exponent; i++) {
+            putchar('0');
+        }
+    } else {
+        fwrite(digits, 1, exponent + 1, stdout);
+        putchar('.');
+        fwrite(digits + exponent + 1, 1, length - exponent - 1, stdout);
+    }
+}
+
+static double undefined(const char *name) {
+    printf("Error: Undefined variable: %s\n", name);
+    exit(0);
+}
+
+static long long bound(const char *name, double x) {
+    if (x != floor(x) || !isfinite(x)) {
+        printf("Error: %s expects integer bounds, found ", name);
+        show(x);
+        putchar('\n');
+        exit(0);
+    }
+    if (x >= 9223372036854775808.0) {
+        return LLONG_MAX;
+    }
+    return x < -9223372036854775808.0 ? LLONG_MIN : (long long)x;
+}
+
+static double factorial(double x) {
+    double result = 1, i;
+    if (x < 0 || x != floor(x)) {
+        printf("Error: factorial of a negative or fractional number\n");
+        exit(0);
+    }
+    if (x > 170) {
+        return INFINITY;
+    }
+    for (i = 1; i <= x; i++) {
+        result *= i;
+    }
+    return result;
+}
+"#;
+
+/// Translates `statements` into a C program that prints what the
+/// interpreter would, computing with `double` as the interpreter does
+/// without `#precision`.
+///
+/// Only the floating point core of the language is supported: decimals,
+/// intervals, units, lists, formats, pragmas and the symbolic and random
+/// built-ins are rejected, as are calls whose arguments do not match.
+pub fn generate(statements: &[Statement]) -> Result<String, String> {
+    let mut generator = Generator::default();
+    for statement in statements {
+        match statement {
+            Statement::Function(name, ..) if generator.functions.contains_key(name) => {
+                return Err(unsupported(format!("Redefining the function {}", name)));
+            }
+            Statement::Function(name, parameters, _) => {
+                generator.functions.insert(name.clone(), parameters.len());
+            }
+            Statement::Assignment(name, _) => {
+                generator.assigned.insert(name.clone());
+            }
+            Statement::Assignments(names, _) => generator.assigned.extend(names.iter().cloned()),
+            _ => {}
+        }
+    }
+
+    let mut definitions = String::new();
+    for statement in statements {
+        if let Statement::Function(name, parameters, body) = statement {
+            generator.scope = parameters
+                .iter()
+                .enumerate()
+                .map(|(index, parameter)| (parameter.clone(), local(index, parameter)))
+                .collect();
+            let body = generator.expression(body);
+            let parameters: Vec<String> = generator.scope.drain(..).map(|(_, c)| c).collect();
+            let body = body?;
+            let calls = std::mem::take(&mut generator.calls);
+            generator.callees.insert(name.clone(), calls);
+
+            let signature = signature(&format!("f_{}", mangle(name)), &parameters);
+            generator.prototypes.push(format!("{};", signature));
+            writeln!(definitions, "{} {{\n    return {};\n}}\n", signature, body).unwrap();
+        }
+    }
+
+    let mut main = String::new();
+    let mut defined = HashSet::new();
+    for statement in statements {
+        generator.statement(statement, &mut main)?;
+        for name in generator.reachable() {
+            if !defined.contains(&name) {
+                return Err(unsupported(format!(
+                    "Calling {} before it is defined",
+                    name
+                )));
+            }
+        }
+        if let Statement::Function(name, ..) = statement {
+            defined.insert(name.clone());
+        }
+    }
+
+    let mut c = RUNTIME.to_string();
+    c.push('\n');
+    for name in &generator.globals {
+        writeln!(c, "static double g_{0};\nstatic int d_{0};", mangle(name)).unwrap();
+    }
+    c.push('\n');
+    for prototype in &generator.prototypes {
+        writeln!(c, "{}", prototype).unwrap();
+    }
+    c.push('\n');
+    c.push_str(&definitions);
+    for helper in &generator.helpers {
+        writeln!(c, "{}", helper).unwrap();
+    }
+    c.push_str("int main(void) {\n");
+    if generator.globals.contains("pi") {
+        writeln!(c, "    g_pi = {};\n    d_pi = 1;", number(PI)).unwrap();
+    }
+    c.push_str(&main);
+    c.push_str("    return 0;\n}\n");
+    Ok(c)
+}
+
+/// Compiles the C program `source` into the executable `output` with the
+/// system C compiler, `cc` unless the `CC` environment variable names another.
+pub fn build(source: &str, output: &Path) -> Result<(), String> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let file = std::env::temp_dir().join(format!("rustic-{}.c", std::process::id()));
+    std::fs::write(&file, source)
+        .map_err(|e| format!("Could not write {}: {}", file.display(), e))?;
+    let status = Command::new(&compiler)
+        .arg("-O2")
+        .arg("-o")
+        .arg(output)
+        .arg(&file)
+        .arg("-lm")
+        .status();
+    let _ = std::fs::remove_file(&file);
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} failed with {}", compiler, status)),
+        Err(e) => Err(format!("Could not run {}: {}", compiler, e)),
+    }
+}
+
+fn unsupported(what: impl std::fmt::Display) -> String {
+    format!("build::{} cannot be built", what)
+}
+
+#[derive(Default)]
+struct Generator {
+    /// The number of parameters of each user function.
+    functions: HashMap<String, usize>,
+    /// Names the program assigns to, which cannot also be called.
+    assigned: HashSet<String>,
+    /// The globals the program reads or writes, in the order C declares them.
+    globals: BTreeSet<String>,
+    /// The user functions each function body calls.
+    callees: HashMap<String, Vec<String>>,
+    /// The user functions called since the last statement or body.
+    calls: Vec<String>,
+    /// The C name of each name in scope, innermost last.
+    scope: Vec<(String, String)>,
+    prototypes: Vec<String>,
+    /// The functions `sum` and `prod` loops are lifted into.
+    helpers: Vec<String>,
+}
+
+impl Generator {
+    fn statement(&mut self, statement: &Statement, main: &mut String) -> Result<(), String> {
+        match statement {
+            Statement::Expression(expression) => {
+                writeln!(main, "    (void){};", self.expression(expression)?).unwrap();
+            }
+            Statement::Assignment(name, expression) => {
+                let value = self.expression(expression)?;
+                let target = self.target(name)?;
+                writeln!(main, "    g_{0} = {1};\n    d_{0} = 1;", target, value).unwrap();
+            }
+            // every value is computed before any is assigned
+            Statement::Assignments(names, expressions) => {
+                main.push_str("    {\n");
+                for (index, expression) in expressions.iter().enumerate() {
+                    let value = self.expression(expression)?;
+                    writeln!(main, "        double t{} = {};", index, value).unwrap();
+                }
+                for (index, name) in names.iter().enumerate() {
+                    let target = self.target(name)?;
+                    writeln!(
+                        main,
+                        "        g_{0} = t{1};\n        d_{0} = 1;",
+                        target, index
+                    )
+                    .unwrap();
+                }
+                main.push_str("    }\n");
+            }
+            Statement::Function(..) => {}
+            // values are computed first, so that a failing one prints nothing
+            Statement::Print(items) => {
+                let mut line = String::new();
+                main.push_str("    {\n");
+                for (index, item) in items.iter().enumerate() {
+                    match item {
+                        PrintItem::Text(text) => {
+                            writeln!(line, "        fputs({}, stdout);", c_string(text)).unwrap();
+                        }
+                        PrintItem::Value(expression, None) => {
+                            let value = self.expression(expression)?;
+                            writeln!(main, "        double t{} = {};", index, value).unwrap();
+                            writeln!(line, "        show(t{});", index).unwrap();
+                        }
+                        PrintItem::Value(_, Some(format)) => {
+                            return Err(unsupported(format!("The format {}", format)));
+                        }
+                    }
+                }
+                main.push_str(&line);
+                main.push_str("        putchar('\\n');\n    }\n");
+            }
+            Statement::Pragma(name, _) => return Err(unsupported(format!("#{}", name))),
+            Statement::Format(_) => return Err(unsupported("#format")),
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<String, String> {
+        Ok(match expression {
+            Expression::Number(value) => number(*value),
+            Expression::Variable(name) => match self.local(name) {
+                Some(local) => local.to_string(),
+                None => self.global(name)?,
+            },
+            Expression::Group(operand) => self.expression(operand)?,
+            Expression::Plus(left, right) => self.binary(left, "+", right)?,
+            Expression::Minus(left, right) => self.binary(left, "-", right)?,
+            Expression::Asterisk(left, right) => self.binary(left, "*", right)?,
+            Expression::Slash(left, right) => self.binary(left, "/", right)?,
+            Expression::Power(left, right) => {
+                let left = self.expression(left)?;
+                format!("pow({}, {})", left, self.expression(right)?)
+            }
+            Expression::Negative(operand) => format!("(-{})", self.expression(operand)?),
+            Expression::Factorial(operand) => format!("factorial({})", self.expression(operand)?),
+            Expression::Degrees(operand) => {
+                format!("({} * {} / 180.0)", self.expression(operand)?, number(PI))
+            }
+            Expression::Call(name, arguments) => self.call(name, arguments)?,
+            Expression::Literal(_)
+            | Expression::Interval(..)
+            | Expression::PlusMinus(..)
+            | Expression::Quantity(..)
+            | Expression::Convert(..)
+            | Expression::List(_)
+            | Expression::Equation(..)
+            | Expression::None => return Err(unsupported(expression)),
+        })
+    }
+
+    fn binary(
+        &mut self, left: &Expression, operator: &str, right: &Expression,
+    ) -> Result<String, String> {
+        let left = self.expression(left)?;
+        Ok(format!(
+            "({} {} {})",
+            left,
+            operator,
+            self.expression(right)?
+        ))
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<String, String> {
+        if self.local(name).is_some() {
+            return Err(unsupported(format!("Calling the parameter {}", name)));
+        }
+        if self.assigned.contains(name) {
+            return Err(unsupported(format!("Calling the variable {}", name)));
+        }
+        let function = match self.functions.get(name) {
+            Some(&count) => {
+                self.calls.push(name.to_string());
+                Some((format!("f_{}", mangle(name)), count))
+            }
+            None => match name {
+                "sum" | "prod" => return self.iterate(name, arguments),
+                _ if SPECIAL_FORMS.contains(&name) => return Err(unsupported(name)),
+                "deg" | "rad" => None,
+                _ => match MATH_FUNCTIONS.iter().find(|(rustic, _)| *rustic == name) {
+                    Some(&(_, c)) => Some((c.to_string(), 1)),
+                    None => return Err(unsupported(name)),
+                },
+            },
+        };
+
+        let count = function.as_ref().map_or(1, |&(_, count)| count);
+        if arguments.len() != count {
+            return Err(format!(
+                "build::{} expects {} argument(s), found {}",
+                name,
+                count,
+                arguments.len()
+            ));
+        }
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(match function {
+            Some((function, _)) => format!("{}({})", function, arguments.join(", ")),
+            None if name == "deg" => format!("({} * 180.0 / {})", arguments[0], number(PI)),
+            None => format!("({} * {} / 180.0)", arguments[0], number(PI)),
+        })
+    }
+
+    /// Lifts `sum(expression, index, first, last)` or `prod` into a C
+    /// function of the locals in scope, and returns a call to it.
+    fn iterate(&mut self, name: &str, arguments: &[Expression]) -> Result<String, String> {
+        let [term, Expression::Variable(index), first, last] = arguments else {
+            return Err(unsupported(format!("This call to {}", name)));
+        };
+        let helper = format!("iterate_{}", self.helpers.len());
+        self.helpers.push(String::new());
+        let parameters: Vec<String> = self.scope.iter().map(|(_, c)| c.clone()).collect();
+
+        let first = self.expression(first)?;
+        let last = self.expression(last)?;
+        let counter = local(self.scope.len(), index);
+        self.scope.push((index.clone(), counter.clone()));
+        let term = self.expression(term);
+        self.scope.pop();
+        let term = term?;
+
+        let (total, operator) = match name {
+            "sum" => ("0.0", "+"),
+            _ => ("1.0", "*"),
+        };
+        let signature = signature(&helper, &parameters);
+        self.prototypes.push(format!("{};", signature));
+        let name = c_string(name);
+        let index = self.helpers.len() - 1;
+        self.helpers[index] = format!(
+            "{} {{\n    long long first = bound({name}, {first});\n    long long last = bound({name}, {last});\n    double total = {total};\n    long long i;\n    for (i = first; i <= last; i++) {{\n        double {counter} = (double)i;\n        total = total {operator} {term};\n    }}\n    return total;\n}}\n",
+            signature,
+        );
+        Ok(format!("{}({})", helper, parameters.join(", ")))
+    }
+
+    /// The user functions the last statement calls, directly or through
+    /// other functions.
+    fn reachable(&mut self) -> HashSet<String> {
+        let mut reached = HashSet::new();
+        let mut pending = std::mem::take(&mut self.calls);
+        while let Some(name) = pending.pop() {
+            if reached.insert(name.clone()) {
+                pending.extend(self.callees.get(&name).into_iter().flatten().cloned());
+            }
+        }
+        reached
+    }
+
+    fn local(&self, name: &str) -> Option<&str> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map(|(_, c)| c.as_str())
+    }
+
+    /// Reads a global, failing as the interpreter does if it is unassigned.
+    fn global(&mut self, name: &str) -> Result<String, String> {
+        // `f'` is differentiated when it is looked up
+        if name.ends_with('\'') {
+            return Err(unsupported(name));
+        }
+        if self.functions.contains_key(name) {
+            return Err(unsupported(format!(
+                "Using the function {} as a value",
+                name
+            )));
+        }
+        self.globals.insert(name.to_string());
+        Ok(format!(
+            "(d_{0} ? g_{0} : undefined({1}))",
+            mangle(name),
+            c_string(name)
+        ))
+    }
+
+    fn target(&mut self, name: &str) -> Result<String, String> {
+        if self.functions.contains_key(name) {
+            return Err(unsupported(format!("Reassigning the function {}", name)));
+        }
+        self.globals.insert(name.to_string());
+        Ok(mangle(name))
+    }
+}
+
+fn signature(name: &str, parameters: &[String]) -> String {
+    let parameters: Vec<String> = parameters.iter().map(|p| format!("double {}", p)).collect();
+    match parameters.is_empty() {
+        true => format!("static double {}(void)", name),
+        false => format!("static double {}({})", name, parameters.join(", ")),
+    }
+}
+
+/// The C name of the local `name` bound at `position` in the scope.
+fn local(position: usize, name: &str) -> String {
+    format!("l{}_{}", position, mangle(name))
+}
+
+/// Turns a Rustic name, which may hold `_` and letters in any script, into
+/// the part of a C identifier that follows a prefix.
+fn mangle(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_string(),
+            false => format!("_{:x}_", c as u32),
+        })
+        .collect()
+}
+
+/// A C literal that reads back as exactly `value`.
+fn number(value: f64) -> String {
+    match value.is_sign_negative() {
+        true => format!("({:?})", value),
+        false => format!("{:?}", value),
+    }
+}
+
+/// A C string literal of `text`, escaping everything but printable ASCII.
+fn c_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => write!(literal, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn generated(source: &str) -> Result<String, String> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        generate(&parser.parse().unwrap())
+    }
+
+    /// Builds and runs `source`, or returns `None` if there is no C compiler.
+    /// The program must exit successfully, as the interpreter does even after
+    /// a runtime error.
+    fn output(source: &str) -> Option<String> {
+        let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        if Command::new(&compiler).arg("--version").output().is_err() {
+            eprintln!("skipping: {} is not installed", compiler);
+            return None;
+        }
+        let directory = std::env::temp_dir();
+        let executable = directory.join(format!("rustic-test-{}", std::process::id()));
+        build(&generated(source).unwrap(), &executable).unwrap();
+        let output = Command::new(&executable).output().unwrap();
+        let _ = std::fs::remove_file(&executable);
+        assert!(output.status.success(), "{}", output.status);
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn test_build() {
+        let numbers = [
+            0.1 + 0.2,
+            1.0 / 3.0,
+            2f64.powf(70.0),
+            1e-7,
+            -0.0,
+            (2.0f64).sqrt() * 1e15,
+            120.0,
+        ];
+        let expected: String = numbers.iter().map(|x| format!("{}\n", x)).collect();
+        let source = "print 0.1 + 0.2\nprint 1/3\nprint 2^70\nprint 10^-7\nprint -0\nprint sqrt(2)*10^15\nprint 5!";
+        let Some(printed) = output(source) else {
+            return;
+        };
+        assert_eq!(printed, expected);
+
+        let printed = output(
+            "f(x, n) = sum(x^k/k!, k, 0, n)\nr_1, θ = 2, 90°\nprint \"e ≈ \", f(1, 20), \"\\t\", sin(θ)*r_1\nprint y",
+        );
+        assert_eq!(
+            printed.unwrap(),
+            format!(
+                "e ≈ {}\t{}\nError: Undefined variable: y\n",
+                (0..=20).fold(0.0, |total, k| total
+                    + 1f64.powf(k as f64) / (1..=k).map(|i| i as f64).product::<f64>()),
+                (90.0 * PI / 180.0).sin() * 2.0
+            )
+        );
+
+        assert_eq!(
+            output("print 171!\nprint (-1)!").unwrap(),
+            "inf\nError: factorial of a negative or fractional number\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        for source in [
+            "x = 2 km",
+            "print [1 .. 2]",
+            "d = diff(x^2, x)",
+            "x = rand()",
+            "#precision 20",
+            "print 1 : .2f",
+            "f(x) = x\nf(x) = 2*x",
+            "y = f(2)\nf(x) = x",
+            "f(x) = g(x)\ny = f(1)\ng(x) = x",
+        ] {
+            assert!(generated(source).is_err(), "{}", source);
+        }
+        assert_eq!(
+            generated("f(x) = x\ny = f(1, 2)"),
+            Err("build::f expects 1 argument(s), found 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::enums::{Expression, Statement};
+use crate::lexer::Lexer;
+use crate::numeric;
+use crate::optimizer::{self, DEFAULT_LEVEL};
+use crate::parser::Parser;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+type MathFunction = fn(f64) -> f64;
+
+/// Built-ins that take and return a single float.
+const MATH_FUNCTIONS: [(&str, MathFunction); 12] = [
+    ("sqrt", f64::sqrt),
+    ("exp", f64::exp),
+    ("ln", f64::ln),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+    ("abs", f64::abs),
+    ("asin", f64::asin),
+    ("acos", f64::acos),
+    ("atan", f64::atan),
+    ("deg", |x| x * 180.0 / PI),
+    ("rad", |x| x * PI / 180.0),
+];
+
+/// An expression tree whose variables are resolved to slots.
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Variable(usize),
+    Index(usize),
+    Add(Box<Node>, Box<Node>),
+    Subtract(Box<Node>, Box<Node>),
+    Multiply(Box<Node>, Box<Node>),
+    Divide(Box<Node>, Box<Node>),
+    Power(Box<Node>, Box<Node>),
+    Negative(Box<Node>),
+    Factorial(Box<Node>),
+    Call(MathFunction, Box<Node>),
+    Iterate {
+        sum: bool,
+        depth: usize,
+        first: Box<Node>,
+        last: Box<Node>,
+        term: Box<Node>,
+    },
+}
+
+/// An expression parsed and resolved once, for evaluating many times with
+/// different variable bindings. It covers numbers, arithmetic, factorials,
+/// degrees, `sum`, `prod`, `pi` and the single argument math built-ins.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    node: Node,
+    variables: Vec<String>,
+    depth: usize,
+}
+
+impl CompiledExpr {
+    pub fn new(source: &str) -> Result<Self, String> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        // there are no units to compile, so `2t` is `2*t`
+        parser.units = false;
+        let statements = optimizer::optimize(parser.parse()?, DEFAULT_LEVEL);
+        let expression = match <[Statement; 1]>::try_from(statements) {
+            Ok([Statement::Expression(expression)]) => expression,
+            _ => return Err("compile::Expected a single expression".to_owned()),
+        };
+
+        let mut resolver = Resolver {
+            variables: vec![],
+            indices: vec![],
+            depth: 0,
+        };
+        let node = resolver.resolve(&expression)?;
+        Ok(CompiledExpr {
+            node,
+            variables: resolver.variables,
+            depth: resolver.depth,
+        })
+    }
+
+    /// The free variables, in order of first appearance.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn eval(&self, bindings: &HashMap<&str, f64>) -> Result<f64, String> {
+        let variables = self
+            .variables
+            .iter()
+            .map(|name| match bindings.get(name.as_str()) {
+                Some(&value) => Ok(value),
+                None => Err(format!("Undefined variable: {}", name)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        evaluate(&self.node, &variables, &mut vec![0.0; self.depth])
+    }
+
+    /// Evaluates the expression once per row of `inputs`, where a row holds
+    /// one value for each of `variables()` in order; a single variable
+    /// expression takes one value per row. An expression without variables
+    /// is evaluated once per input. Fails on the first row that does not
+    /// evaluate.
+    pub fn eval_many(&self, inputs: &[f64]) -> Result<Vec<f64>, String> {
+        let width = self.variables.len().max(1);
+        if !inputs.len().is_multiple_of(width) {
+            return Err(format!(
+                "eval_many expects {} value(s) per row, found {} in total",
+                width,
+                inputs.len()
+            ));
+        }
+
+        let mut indices = vec![0.0; self.depth];
+        inputs
+            .chunks_exact(width)
+            .map(|row| {
+                let variables = &row[..self.variables.len()];
+                evaluate(&self.node, variables, &mut indices)
+            })
+            .collect()
+    }
+}
+
+/// Numbers the free variables in order of first appearance, and the `sum`
+/// and `prod` indices by how deeply they are nested.
+struct Resolver {
+    variables: Vec<String>,
+    indices: Vec<String>,
+    depth: usize,
+}
+
+impl Resolver {
+    fn resolve(&mut self, expression: &Expression) -> Result<Node, String> {
+        let binary = |resolver: &mut Self, left, right| -> Result<_, String> {
+            Ok((
+                Box::new(resolver.resolve(left)?),
+                Box::new(resolver.resolve(right)?),
+            ))
+        };
+
+        Ok(match expression {
+            Expression::Number(value) => Node::Number(*value),
+            Expression::Variable(name) => self.variable(name),
+            Expression::Group(operand) => self.resolve(operand)?,
+            Expression::Negative(operand) => Node::Negative(Box::new(self.resolve(operand)?)),
+            Expression::Factorial(operand) => Node::Factorial(Box::new(self.resolve(operand)?)),
+            Expression::Degrees(operand) => {
+                Node::Call(|x| x * PI / 180.0, Box::new(self.resolve(operand)?))
+            }
+            Expression::Plus(left, right) => {
+                let (left, right) = binary(self, left, right)?;
+                Node::Add(left, right)
+            }
+            Expression::Minus(left, right) => {
+                let (left, right) = binary(self, left, right)?;
+                Node::Subtract(left, right)
+            }
+            Expression::Asterisk(left, right) => {
+                let (left, right) = binary(self, left, right)?;
+                Node::Multiply(left, right)
+            }
+            Expression::Slash(left, right) => {
+                let (left, right) = binary(self, left, right)?;
+                Node::Divide(left, right)
+            }
+            Expression::Power(left, right) => {
+                let (left, right) = binary(self, left, right)?;
+                Node::Power(left, right)
+            }
+            Expression::Call(name, arguments) => self.call(name, arguments)?,
+            _ => return Err(unsupported(&expression.to_string())),
+        })
+    }
+
+    fn variable(&mut self, name: &str) -> Node {
+        if let Some(depth) = self.indices.iter().rposition(|index| index == name) {
+            return Node::Index(depth);
+        }
+        if name == "pi" {
+            return Node::Number(PI);
+        }
+        match self.variables.iter().position(|variable| variable == name) {
+            Some(slot) => Node::Variable(slot),
+            None => {
+                self.variables.push(name.to_owned());
+                Node::Variable(self.variables.len() - 1)
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<Node, String> {
+        if name == "sum" || name == "prod" {
+            let [term, Expression::Variable(index), first, last] = arguments else {
+                return Err(format!(
+                    "{} expects an expression, an index and two bounds",
+                    name
+                ));
+            };
+            let first = Box::new(self.resolve(first)?);
+            let last = Box::new(self.resolve(last)?);
+            let depth = self.indices.len();
+            self.indices.push(index.clone());
+            self.depth = self.depth.max(self.indices.len());
+            let term = Box::new(self.resolve(term)?);
+            self.indices.pop();
+            return Ok(Node::Iterate {
+                sum: name == "sum",
+                depth,
+                first,
+                last,
+                term,
+            });
+        }
+
+        let Some((_, function)) = MATH_FUNCTIONS.iter().find(|(builtin, _)| *builtin == name)
+        else {
+            return Err(unsupported(&format!("{}()", name)));
+        };
+        let [argument] = arguments else {
+            return Err(format!(
+                "{} expects 1 argument(s), found {}",
+                name,
+                arguments.len()
+            ));
+        };
+        Ok(Node::Call(*function, Box::new(self.resolve(argument)?)))
+    }
+}
+
+fn unsupported(what: &str) -> String {
+    format!("compile::{} cannot be compiled", what)
+}
+
+fn evaluate(node: &Node, variables: &[f64], indices: &mut [f64]) -> Result<f64, String> {
+    let mut operand = |node| evaluate(node, variables, indices);
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Variable(slot) => variables[*slot],
+        Node::Index(depth) => indices[*depth],
+        Node::Add(left, right) => operand(left)? + operand(right)?,
+        Node::Subtract(left, right) => operand(left)? - operand(right)?,
+        Node::Multiply(left, right) => operand(left)? * operand(right)?,
+        Node::Divide(left, right) => operand(left)? / operand(right)?,
+        Node::Power(left, right) => operand(left)?.powf(operand(right)?),
+        Node::Negative(value) => -operand(value)?,
+        Node::Factorial(value) => numeric::factorial(operand(value)?)?,
+        Node::Call(function, argument) => function(operand(argument)?),
+        Node::Iterate {
+            sum,
+            depth,
+            first,
+            last,
+            term,
+        } => {
+            let name = if *sum { "sum" } else { "prod" };
+            let first = bound(name, operand(first)?)?;
+            let last = bound(name, operand(last)?)?;
+            let mut total = if *sum { 0.0 } else { 1.0 };
+            for i in first..=last {
+                indices[*depth] = i as f64;
+                let term = evaluate(term, variables, indices)?;
+                total = if *sum { total + term } else { total * term };
+            }
+            total
+        }
+    })
+}
+
+fn bound(name: &str, bound: f64) -> Result<i64, String> {
+    if bound.fract() != 0.0 || !bound.is_finite() {
+        return Err(format!("{} expects integer bounds, found {}", name, bound));
+    }
+    Ok(bound as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let expression = CompiledExpr::new("x^2 + a").unwrap();
+        assert_eq!(expression.variables(), ["x", "a"]);
+        assert_eq!(
+            expression.eval(&HashMap::from([("x", 3.0), ("a", 1.0)])),
+            Ok(10.0)
+        );
+        assert_eq!(
+            expression.eval(&HashMap::from([("x", 3.0)])),
+            Err("Undefined variable: a".to_string())
+        );
+        assert_eq!(
+            expression.eval_many(&[1.0, 0.0, 2.0, 0.0, 3.0, 1.0]),
+            Ok(vec![1.0, 4.0, 10.0])
+        );
+        assert_eq!(
+            expression.eval_many(&[1.0, 0.0, 2.0]),
+            Err("eval_many expects 2 value(s) per row, found 3 in total".to_string())
+        );
+
+        let expression = CompiledExpr::new("2t + x/2h - 2 s").unwrap();
+        assert_eq!(expression.variables(), ["t", "x", "h", "s"]);
+        assert_eq!(expression.eval_many(&[1.0, 8.0, 2.0, 0.5]), Ok(vec![3.0]));
+
+        let expression = CompiledExpr::new("sqrt(x) * 2 + sin(pi/2) - 3!").unwrap();
+        assert_eq!(expression.eval_many(&[4.0, 9.0]), Ok(vec![-1.0, 1.0]));
+        assert_eq!(
+            CompiledExpr::new("2^10").unwrap().eval_many(&[0.0; 2]),
+            Ok(vec![1024.0; 2])
+        );
+
+        let expression = CompiledExpr::new("n!").unwrap();
+        assert_eq!(
+            expression.eval_many(&[5.0, 171.0]),
+            Ok(vec![120.0, f64::INFINITY])
+        );
+        assert_eq!(
+            expression.eval_many(&[-1.0]),
+            Err("factorial of a negative or fractional number".to_string())
+        );
+    }
+
+    #[test]
+    fn test_iterate() {
+        let expression = CompiledExpr::new("sum(prod(i + x, x, 1, 2), i, 1, n)").unwrap();
+        assert_eq!(expression.variables(), ["n"]);
+        assert_eq!(expression.eval_many(&[1.0, 2.0]), Ok(vec![6.0, 18.0]));
+
+        let expression = CompiledExpr::new("sum(k, k, 1, n)").unwrap();
+        assert_eq!(
+            expression.eval_many(&[3.0, 1.5]),
+            Err("sum expects integer bounds, found 1.5".to_string())
+        );
+        assert_eq!(
+            expression.eval(&HashMap::from([("n", 1.5)])),
+            Err("sum expects integer bounds, found 1.5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rejected() {
+        assert_eq!(
+            CompiledExpr::new("x = 1").unwrap_err(),
+            "compile::Expected a single expression"
+        );
+        assert_eq!(
+            CompiledExpr::new("f(x)").unwrap_err(),
+            "compile::f() cannot be compiled"
+        );
+        assert!(CompiledExpr::new("[1, x]").is_err());
+    }
+}
@@ -0,0 +1,653 @@
+use crate::bigint::BigInt;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Extra digits carried through intermediate steps so the final rounding is
+/// accurate to the requested precision.
+const GUARD_DIGITS: usize = 10;
+
+/// An arbitrary-precision decimal floating point number.
+///
+/// The value is `mantissa * 10^exponent`, rounded to `precision` significant
+/// digits. Trailing zeros are always stripped from the mantissa so equal
+/// values have equal representations.
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    mantissa: BigInt,
+    exponent: i64,
+    precision: usize,
+}
+
+impl Decimal {
+    pub fn new(mantissa: BigInt, exponent: i64, precision: usize) -> Self {
+        let digits = mantissa.digits();
+        let (mut mantissa, mut exponent) = if digits > precision {
+            let dropped = digits - precision;
+            (
+                divide_pow10_rounded(&mantissa, dropped),
+                exponent + dropped as i64,
+            )
+        } else {
+            (mantissa, exponent)
+        };
+
+        if mantissa.is_zero() {
+            exponent = 0;
+        }
+        while !mantissa.is_zero() && mantissa.rem_small(10) == 0 {
+            mantissa = mantissa.div_small(10);
+            exponent += 1;
+        }
+        Decimal {
+            mantissa,
+            exponent,
+            precision,
+        }
+    }
+
+    pub fn from_integer(value: i64, precision: usize) -> Self {
+        Decimal::new(BigInt::from(value), 0, precision)
+    }
+
+    /// Parses plain decimal notation such as `-12.5`.
+    pub fn parse(text: &str, precision: usize) -> Option<Self> {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mantissa = BigInt::parse(&format!("{}{}", whole, fraction))?;
+        Some(Decimal::new(mantissa, -(fraction.len() as i64), precision))
+    }
+
+    /// Converts through the shortest decimal representation of the float, so
+    /// literals such as `0.1` stay exact.
+    pub fn from_f64(value: f64, precision: usize) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        Decimal::parse(&value.to_string(), precision)
+    }
+
+    /// Returns pi to the given number of significant digits.
+    pub fn pi(precision: usize) -> Self {
+        let working = precision + GUARD_DIGITS;
+        // Machin's formula: pi = 16 atan(1/5) - 4 atan(1/239)
+        let fifth = arctan_series(&Decimal::from_integer(1, working).div_small(5), false);
+        let inverse = arctan_series(&Decimal::from_integer(1, working).div_small(239), false);
+        let pi = &(&fifth * &Decimal::from_integer(16, working))
+            - &(&inverse * &Decimal::from_integer(4, working));
+        pi.with_precision(precision)
+    }
+
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    pub fn with_precision(&self, precision: usize) -> Self {
+        Decimal::new(self.mantissa.clone(), self.exponent, precision)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e{}", self.mantissa, self.exponent)
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exponent >= 0
+    }
+
+    /// Rounds to the nearest integer, with halves rounded away from zero.
+    pub fn to_integer(&self) -> BigInt {
+        if self.exponent >= 0 {
+            self.mantissa.mul_pow10(self.exponent as usize)
+        } else {
+            divide_pow10_rounded(&self.mantissa, self.exponent.unsigned_abs() as usize)
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Decimal {
+            mantissa: self.mantissa.abs(),
+            exponent: self.exponent,
+            precision: self.precision,
+        }
+    }
+
+    pub fn checked_div(&self, rhs: &Decimal) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let precision = self.precision.max(rhs.precision);
+        // scale the dividend so the quotient carries two digits beyond the
+        // precision, plus a sticky digit so the final rounding is exact
+        let shift = (precision + 2 + rhs.mantissa.digits()).saturating_sub(self.mantissa.digits());
+        let (quotient, remainder) = self.mantissa.mul_pow10(shift).div_rem(&rhs.mantissa)?;
+        let mut quotient = quotient.mul_pow10(1);
+        if !remainder.is_zero() {
+            let sticky = if self.is_negative() != rhs.is_negative() {
+                -1
+            } else {
+                1
+            };
+            quotient = &quotient + &BigInt::from(sticky);
+        }
+        let exponent = self.exponent - rhs.exponent - shift as i64 - 1;
+        Some(Decimal::new(quotient, exponent, precision))
+    }
+
+    pub fn sqrt(&self) -> Result<Self, String> {
+        if self.is_negative() {
+            return Err("sqrt of a negative number".to_owned());
+        }
+        if self.is_zero() {
+            return Ok(self.clone());
+        }
+        // take the integer root of the mantissa, scaled so the root has two
+        // extra digits and the remaining exponent is even
+        let mut shift = (2 * (self.precision + 2)).saturating_sub(self.mantissa.digits()) as i64;
+        if (self.exponent - shift).rem_euclid(2) != 0 {
+            shift += 1;
+        }
+        let root = self
+            .mantissa
+            .mul_pow10(shift as usize)
+            .isqrt()
+            .unwrap_or_default();
+        Ok(Decimal::new(
+            root,
+            (self.exponent - shift) / 2,
+            self.precision,
+        ))
+    }
+
+    pub fn exp(&self) -> Result<Self, String> {
+        let precision = self.precision;
+        if self.is_zero() {
+            return Ok(Decimal::from_integer(1, precision));
+        }
+        if self.adjusted() > 15 {
+            return match self.is_negative() {
+                true => Ok(Decimal::from_integer(0, precision)),
+                false => Err("exp argument out of range".to_owned()),
+            };
+        }
+
+        // halve the argument until the series converges quickly, then square
+        // the result back up
+        let halvings = self.to_f64().abs().log2().ceil().max(0.0) as usize + 10;
+        let working = precision + GUARD_DIGITS + halvings / 3;
+        let scale = Decimal::new(BigInt::from(2).pow(halvings as u64), 0, working);
+        let x = self
+            .with_precision(working)
+            .checked_div(&scale)
+            .unwrap_or_default();
+
+        let mut sum = Decimal::from_integer(1, working);
+        let mut term = sum.clone();
+        let mut n = 1;
+        loop {
+            term = (&term * &x).div_small(n);
+            if term.is_negligible(&sum) {
+                break;
+            }
+            sum = &sum + &term;
+            n += 1;
+        }
+        for _ in 0..halvings {
+            sum = &sum * &sum;
+        }
+        Ok(sum.with_precision(precision))
+    }
+
+    pub fn ln(&self) -> Result<Self, String> {
+        if self.is_negative() || self.is_zero() {
+            return Err("ln of a non-positive number".to_owned());
+        }
+        let working = self.precision + GUARD_DIGITS;
+
+        // write x = y * 2^halvings * 10^decades with y in [1, 2)
+        let decades = self.adjusted();
+        let mut y = Decimal::new(self.mantissa.clone(), self.exponent - decades, working);
+        let two = Decimal::from_integer(2, working);
+        let mut halvings = 0;
+        while y >= two {
+            y = y.div_small(2);
+            halvings += 1;
+        }
+
+        let one = Decimal::from_integer(1, working);
+        let ln2 = &arctan_series(&one.div_small(3), true) * &two;
+        let ln10 = &(&ln2 * &Decimal::from_integer(3, working))
+            + &(&arctan_series(&one.div_small(9), true) * &two);
+        let z = (&y - &one).checked_div(&(&y + &one)).unwrap_or_default();
+        let ln_y = &arctan_series(&z, true) * &two;
+
+        let result = &(&ln_y + &(&ln2 * &Decimal::from_integer(halvings, working)))
+            + &(&ln10 * &Decimal::from_integer(decades, working));
+        Ok(result.with_precision(self.precision))
+    }
+
+    pub fn sin(&self) -> Self {
+        let (x, working) = self.reduce_angle();
+        let square = &x * &x;
+        let mut sum = x.clone();
+        let mut term = x;
+        let mut k = 1;
+        loop {
+            term = -&(&term * &square).div_small((2 * k) * (2 * k + 1));
+            if term.is_negligible(&sum) {
+                break;
+            }
+            sum = &sum + &term;
+            k += 1;
+        }
+        sum.with_precision(working - GUARD_DIGITS)
+    }
+
+    pub fn cos(&self) -> Self {
+        let (x, working) = self.reduce_angle();
+        let square = &x * &x;
+        let mut sum = Decimal::from_integer(1, working);
+        let mut term = sum.clone();
+        let mut k = 1;
+        loop {
+            term = -&(&term * &square).div_small((2 * k - 1) * (2 * k));
+            if term.is_negligible(&sum) {
+                break;
+            }
+            sum = &sum + &term;
+            k += 1;
+        }
+        sum.with_precision(working - GUARD_DIGITS)
+    }
+
+    pub fn tan(&self) -> Result<Self, String> {
+        let working = self.with_precision(self.precision + GUARD_DIGITS);
+        working
+            .sin()
+            .checked_div(&working.cos())
+            .map(|tan| tan.with_precision(self.precision))
+            .ok_or_else(|| "tan is undefined for this angle".to_owned())
+    }
+
+    pub fn atan(&self) -> Self {
+        let working = self.precision + GUARD_DIGITS;
+        let one = Decimal::from_integer(1, working);
+        let tenth = Decimal::new(BigInt::one(), -1, working);
+
+        // halve the angle with atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))) until
+        // the series converges quickly, then double the result back up
+        let mut x = self.with_precision(working);
+        let mut halvings = 0;
+        while x.abs() > tenth {
+            let hypotenuse = (&one + &(&x * &x)).sqrt().unwrap_or_default();
+            x = x.checked_div(&(&one + &hypotenuse)).unwrap_or_default();
+            halvings += 1;
+        }
+        let scale = Decimal::new(BigInt::from(2).pow(halvings), 0, working);
+        (&arctan_series(&x, false) * &scale).with_precision(self.precision)
+    }
+
+    pub fn asin(&self) -> Result<Self, String> {
+        let working = self.with_precision(self.precision + GUARD_DIGITS);
+        let one = Decimal::from_integer(1, working.precision);
+        let cosine = &one - &(&working * &working);
+        if cosine.is_negative() {
+            return Err("asin of a number outside [-1, 1]".to_owned());
+        }
+        let result = match working.checked_div(&cosine.sqrt()?) {
+            Some(tangent) => tangent.atan(),
+            // asin(±1) = ±pi/2, where the tangent is infinite
+            None if self.is_negative() => -&Decimal::pi(working.precision).div_small(2),
+            None => Decimal::pi(working.precision).div_small(2),
+        };
+        Ok(result.with_precision(self.precision))
+    }
+
+    pub fn acos(&self) -> Result<Self, String> {
+        let working = self.with_precision(self.precision + GUARD_DIGITS);
+        let quarter_turn = Decimal::pi(working.precision).div_small(2);
+        let result = &quarter_turn - &working.asin()?;
+        Ok(result.with_precision(self.precision))
+    }
+
+    pub fn pow(&self, exponent: &Decimal) -> Result<Self, String> {
+        let precision = self.precision.max(exponent.precision);
+        if exponent.is_integer() && exponent.adjusted() < 9 {
+            let n = exponent.to_integer().to_i64().unwrap_or_default();
+            let working = precision + GUARD_DIGITS + n.unsigned_abs().to_string().len();
+            let mut base = self.with_precision(working);
+            let mut result = Decimal::from_integer(1, working);
+            let mut remaining = n.unsigned_abs();
+            while remaining > 0 {
+                if remaining & 1 == 1 {
+                    result = &result * &base;
+                }
+                remaining >>= 1;
+                base = &base * &base;
+            }
+            if n < 0 {
+                result = Decimal::from_integer(1, working)
+                    .checked_div(&result)
+                    .ok_or_else(|| "Division by zero".to_owned())?;
+            }
+            return Ok(result.with_precision(precision));
+        }
+
+        if self.is_zero() {
+            return match exponent.is_negative() {
+                true => Err("Division by zero".to_owned()),
+                false => Ok(self.clone()),
+            };
+        }
+        if self.is_negative() {
+            return Err("Negative base with a fractional exponent".to_owned());
+        }
+        let working = precision + GUARD_DIGITS;
+        let logarithm = self.with_precision(working).ln()?;
+        let result = (&exponent.with_precision(working) * &logarithm).exp()?;
+        Ok(result.with_precision(precision))
+    }
+
+    pub fn factorial(&self) -> Result<Self, String> {
+        if self.is_negative() || !self.is_integer() {
+            return Err("factorial of a negative or fractional number".to_owned());
+        }
+        let n = match self.to_integer().to_i64() {
+            Some(n) if n <= 1_000_000 => n,
+            _ => return Err("factorial argument too large".to_owned()),
+        };
+        let working = self.precision + GUARD_DIGITS;
+        let mut result = Decimal::from_integer(1, working);
+        for i in 2..=n {
+            result = &result * &Decimal::from_integer(i, working);
+        }
+        Ok(result.with_precision(self.precision))
+    }
+
+    /// The decimal exponent of the leading digit.
+    fn adjusted(&self) -> i64 {
+        self.exponent + self.mantissa.digits() as i64 - 1
+    }
+
+    /// Whether adding `self` to `sum` can no longer change the rounded sum.
+    fn is_negligible(&self, sum: &Decimal) -> bool {
+        self.is_zero() || self.adjusted() < sum.adjusted() - sum.precision as i64 - 1
+    }
+
+    fn div_small(&self, divisor: u32) -> Self {
+        let shift = (self.precision + 2).saturating_sub(self.mantissa.digits()) + 10;
+        let quotient = self.mantissa.mul_pow10(shift).div_small(divisor);
+        Decimal::new(quotient, self.exponent - shift as i64, self.precision)
+    }
+
+    /// Reduces an angle into [-pi, pi], returning it with the working
+    /// precision needed to keep the requested digits.
+    fn reduce_angle(&self) -> (Self, usize) {
+        let working = self.precision + GUARD_DIGITS;
+        let extra = self.adjusted().max(0) as usize;
+        let x = self.with_precision(working + extra);
+        let two_pi = &Decimal::pi(working + extra) * &Decimal::from_integer(2, working + extra);
+        let turns = x.checked_div(&two_pi).unwrap_or_default().to_integer();
+        let reduced = &x - &(&two_pi * &Decimal::new(turns, 0, working + extra));
+        (reduced.with_precision(working), working)
+    }
+}
+
+impl Default for Decimal {
+    fn default() -> Self {
+        Decimal::from_integer(0, 1)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.mantissa == other.mantissa && self.exponent == other.exponent
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let difference = self - other;
+        Some(match (difference.is_zero(), difference.is_negative()) {
+            (true, _) => Ordering::Equal,
+            (false, true) => Ordering::Less,
+            (false, false) => Ordering::Greater,
+        })
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    fn add(self, rhs: Self) -> Decimal {
+        let precision = self.precision.max(rhs.precision);
+        if rhs.is_zero() {
+            return self.with_precision(precision);
+        }
+        if self.is_zero() {
+            return rhs.with_precision(precision);
+        }
+        // an operand far below the last significant digit of the other
+        // cannot change the rounded sum
+        let gap = self.adjusted() - rhs.adjusted();
+        if gap > precision as i64 + 1 {
+            return self.with_precision(precision);
+        }
+        if -gap > precision as i64 + 1 {
+            return rhs.with_precision(precision);
+        }
+
+        let exponent = self.exponent.min(rhs.exponent);
+        let left = self.mantissa.mul_pow10((self.exponent - exponent) as usize);
+        let right = rhs.mantissa.mul_pow10((rhs.exponent - exponent) as usize);
+        Decimal::new(&left + &right, exponent, precision)
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    fn sub(self, rhs: Self) -> Decimal {
+        self + &-rhs
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    fn mul(self, rhs: Self) -> Decimal {
+        let precision = self.precision.max(rhs.precision);
+        Decimal::new(
+            &self.mantissa * &rhs.mantissa,
+            self.exponent + rhs.exponent,
+            precision,
+        )
+    }
+}
+
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal {
+            mantissa: -&self.mantissa,
+            exponent: self.exponent,
+            precision: self.precision,
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.abs().to_string();
+        let sign = if self.is_negative() { "-" } else { "" };
+        let adjusted = self.adjusted();
+
+        if adjusted < -7 || adjusted >= self.precision.max(21) as i64 {
+            let (first, rest) = digits.split_at(1);
+            return match rest.is_empty() {
+                true => write!(f, "{}{}e{}", sign, first, adjusted),
+                false => write!(f, "{}{}.{}e{}", sign, first, rest, adjusted),
+            };
+        }
+        if self.exponent >= 0 {
+            return write!(
+                f,
+                "{}{}{}",
+                sign,
+                digits,
+                "0".repeat(self.exponent as usize)
+            );
+        }
+        let point = digits.len() as i64 + self.exponent;
+        if point > 0 {
+            let (whole, fraction) = digits.split_at(point as usize);
+            write!(f, "{}{}.{}", sign, whole, fraction)
+        } else {
+            write!(
+                f,
+                "{}0.{}{}",
+                sign,
+                "0".repeat(point.unsigned_abs() as usize),
+                digits
+            )
+        }
+    }
+}
+
+/// Divides by 10^dropped, rounding halves away from zero.
+fn divide_pow10_rounded(value: &BigInt, dropped: usize) -> BigInt {
+    let (quotient, remainder) = value.div_rem_pow10(dropped);
+    if &remainder.abs() * &BigInt::from(2) < BigInt::pow10(dropped) {
+        return quotient;
+    }
+    let step = if value.is_negative() { -1 } else { 1 };
+    &quotient + &BigInt::from(step)
+}
+
+/// Sums the series z - z^3/3 + z^5/5 - ... (or with all signs positive for
+/// the hyperbolic variant) at the precision of `z`.
+fn arctan_series(z: &Decimal, hyperbolic: bool) -> Decimal {
+    let square = z * z;
+    let mut power = z.clone();
+    let mut sum = z.clone();
+    let mut k = 1;
+    loop {
+        power = &power * &square;
+        if !hyperbolic {
+            power = -&power;
+        }
+        let term = power.div_small(2 * k + 1);
+        if term.is_negligible(&sum) {
+            return sum;
+        }
+        sum = &sum + &term;
+        k += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str, precision: usize) -> Decimal {
+        Decimal::parse(text, precision).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = decimal("0.1", 30);
+        let b = decimal("0.2", 30);
+        assert_eq!((&a + &b).to_string(), "0.3");
+        assert_eq!((&a - &b).to_string(), "-0.1");
+        assert_eq!((&a * &b).to_string(), "0.02");
+        assert_eq!(
+            decimal("1", 30)
+                .checked_div(&decimal("3", 30))
+                .unwrap()
+                .to_string(),
+            "0.333333333333333333333333333333"
+        );
+        assert_eq!(decimal("1", 30).checked_div(&decimal("0", 30)), None);
+    }
+
+    #[test]
+    fn test_pi() {
+        assert_eq!(
+            Decimal::pi(50).to_string(),
+            "3.1415926535897932384626433832795028841971693993751"
+        );
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(
+            decimal("2", 40).sqrt().unwrap().to_string(),
+            "1.41421356237309504880168872420969807857"
+        );
+        assert!(decimal("-2", 40).sqrt().is_err());
+    }
+
+    #[test]
+    fn test_exp_and_ln() {
+        assert_eq!(
+            decimal("1", 40).exp().unwrap().to_string(),
+            "2.718281828459045235360287471352662497757"
+        );
+        assert_eq!(
+            decimal("10", 40).ln().unwrap().to_string(),
+            "2.302585092994045684017991454684364207601"
+        );
+        assert_eq!(decimal("1", 40).ln().unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_sin_and_cos() {
+        assert_eq!(
+            decimal("1", 40).sin().to_string(),
+            "0.8414709848078965066525023216302989996226"
+        );
+        assert_eq!(
+            decimal("1", 40).cos().to_string(),
+            "0.5403023058681397174009366074429766037323"
+        );
+    }
+
+    #[test]
+    fn test_inverse_trigonometry() {
+        let pi = Decimal::pi(40).to_string();
+        let four = decimal("4", 40);
+        assert_eq!((&decimal("1", 40).atan() * &four).to_string(), pi);
+        assert_eq!(decimal("-1", 40).acos().unwrap().to_string(), pi);
+        assert_eq!(
+            decimal("0.5", 40).asin().unwrap().to_string(),
+            "0.5235987755982988730771072305465838140329"
+        );
+        assert!(decimal("1.5", 40).asin().is_err());
+    }
+
+    #[test]
+    fn test_pow() {
+        let two = decimal("2", 40);
+        assert_eq!(
+            two.pow(&decimal("100", 40)).unwrap().to_string(),
+            "1267650600228229401496703205376"
+        );
+        assert_eq!(two.pow(&decimal("-2", 40)).unwrap().to_string(), "0.25");
+        assert_eq!(
+            two.pow(&decimal("0.5", 40)).unwrap().to_string(),
+            "1.41421356237309504880168872420969807857"
+        );
+    }
+}
@@ -1,25 +1,43 @@
+use crate::format::Format;
+use crate::units::Unit;
+use std::fmt;
+
 #[derive(Debug, PartialEq)]
 pub enum Token {
     Char(char),
     Number(f64),
+    Literal(String),
     Identifier(String),
+    Pragma(String),
     Plus,
     Minus,
+    PlusMinus,
     Star,
     Power,
     Slash,
     LParen,
     RParen,
+    LBracket,
+    RBracket,
+    Range,
+    Comma,
     Equal,
     End,
     Print,
     Dot,
     Factorial,
+    Degree,
+    /// `√`, a prefix square root.
+    Sqrt,
+    Format(String),
+    /// The source text of a string literal, with escapes still in place.
+    String(String),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     Number(f64),
+    Literal(String),
     Plus(Box<Expression>, Box<Expression>),
     Minus(Box<Expression>, Box<Expression>),
     Asterisk(Box<Expression>, Box<Expression>),
@@ -27,7 +45,16 @@ pub enum Expression {
     Variable(String),
     Power(Box<Expression>, Box<Expression>),
     Factorial(Box<Expression>),
+    Degrees(Box<Expression>),
+    Negative(Box<Expression>),
     Group(Box<Expression>),
+    Interval(Box<Expression>, Box<Expression>),
+    PlusMinus(Box<Expression>, Box<Expression>),
+    Quantity(Box<Expression>, Unit),
+    Convert(Box<Expression>, Unit),
+    Equation(Box<Expression>, Box<Expression>),
+    List(Vec<Expression>),
+    Call(String, Vec<Expression>),
     None,
 }
 
@@ -35,5 +62,113 @@ pub enum Expression {
 pub enum Statement {
     Expression(Expression),
     Assignment(String, Expression),
-    Print(Expression),
+    /// `a, b = b, a + b`, which evaluates every value before assigning any.
+    Assignments(Vec<String>, Vec<Expression>),
+    Function(String, Vec<String>, Expression),
+    Print(Vec<PrintItem>),
+    Pragma(String, Expression),
+    /// `#format spec`, or `#format` alone to restore the default.
+    Format(Option<Format>),
+}
+
+/// One item of a `print` statement; strings are split into text and the
+/// values interpolated into it.
+#[derive(Debug, PartialEq)]
+pub enum PrintItem {
+    Text(String),
+    Value(Expression, Option<Format>),
+}
+
+impl Expression {
+    /// How tightly the expression binds when printed in infix form.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expression::PlusMinus(..) | Expression::Convert(..) | Expression::Equation(..) => 0,
+            Expression::Plus(..) | Expression::Minus(..) => 1,
+            Expression::Asterisk(..) | Expression::Slash(..) => 2,
+            Expression::Negative(_) => 3,
+            Expression::Number(value) if value.is_sign_negative() => 3,
+            Expression::Power(..) => 4,
+            Expression::Factorial(_) | Expression::Degrees(_) | Expression::Quantity(..) => 5,
+            Expression::Group(operand) => operand.precedence(),
+            _ => 6,
+        }
+    }
+
+    /// Writes `operand`, parenthesized if it binds looser than `precedence`.
+    fn fmt_operand(&self, f: &mut fmt::Formatter, precedence: u8) -> fmt::Result {
+        match self.precedence() < precedence {
+            true => write!(f, "({})", self),
+            false => write!(f, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Number(value) => write!(f, "{}", value),
+            Expression::Literal(text) | Expression::Variable(text) => write!(f, "{}", text),
+            Expression::Plus(left, right) => {
+                left.fmt_operand(f, 1)?;
+                write!(f, " + ")?;
+                right.fmt_operand(f, 1)
+            }
+            Expression::Minus(left, right) => {
+                left.fmt_operand(f, 1)?;
+                write!(f, " - ")?;
+                right.fmt_operand(f, 2)
+            }
+            Expression::Asterisk(left, right) => {
+                left.fmt_operand(f, 2)?;
+                write!(f, "*")?;
+                right.fmt_operand(f, 3)
+            }
+            Expression::Slash(left, right) => {
+                left.fmt_operand(f, 2)?;
+                write!(f, "/")?;
+                right.fmt_operand(f, 4)
+            }
+            Expression::Power(base, exponent) => {
+                base.fmt_operand(f, 5)?;
+                write!(f, "^")?;
+                exponent.fmt_operand(f, 4)
+            }
+            Expression::Negative(operand) => {
+                // `-(a*b)` and `(-a)*b` are equal, so products need no parentheses
+                write!(f, "-")?;
+                operand.fmt_operand(f, 2)
+            }
+            Expression::Factorial(operand) => {
+                operand.fmt_operand(f, 6)?;
+                write!(f, "!")
+            }
+            Expression::Degrees(operand) => {
+                operand.fmt_operand(f, 6)?;
+                write!(f, "°")
+            }
+            Expression::Group(operand) => write!(f, "{}", operand),
+            Expression::Interval(lower, upper) => write!(f, "[{} .. {}]", lower, upper),
+            Expression::PlusMinus(midpoint, radius) => {
+                midpoint.fmt_operand(f, 1)?;
+                write!(f, " ± ")?;
+                radius.fmt_operand(f, 1)
+            }
+            Expression::Quantity(magnitude, unit) => {
+                magnitude.fmt_operand(f, 6)?;
+                write!(f, " {}", unit.name)
+            }
+            Expression::Convert(value, unit) => write!(f, "{} to {}", value, unit.name),
+            Expression::Equation(left, right) => write!(f, "{} = {}", left, right),
+            Expression::Call(name, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, arguments.join(", "))
+            }
+            Expression::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Expression::None => Ok(()),
+        }
+    }
 }
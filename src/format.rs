@@ -0,0 +1,468 @@
+use crate::bigint::BigInt;
+use crate::value::Value;
+use std::fmt;
+
+/// The precision used when a format such as `f` does not give one.
+const DEFAULT_PRECISION: usize = 6;
+
+/// The largest precision a format accepts, which bounds how long the text
+/// it writes can be.
+const MAX_PRECISION: usize = 1000;
+
+/// Numbers whose decimal exponent falls outside this range are written in
+/// scientific notation by the `s` format, as `Decimal` does by default.
+const PLAIN_EXPONENTS: std::ops::Range<i64> = -7..21;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    /// The shortest text that reads back as the same number.
+    General,
+    /// A fixed number of digits after the point.
+    Fixed,
+    /// A fixed number of significant digits.
+    Significant,
+    /// One digit before the point and an exponent.
+    Scientific,
+    /// An exponent that is a multiple of three.
+    Engineering,
+    /// An integer in base 2, 8 or 16.
+    Radix(u32),
+}
+
+/// How printed numbers are written, parsed from a spec such as `.2f`.
+///
+/// A spec is an optional `,` for thousands separators, an optional `.N`
+/// precision and a notation: `f` (fixed), `s` (significant digits), `e`
+/// (scientific), `eng` (engineering), or `x`, `o` and `b` for hexadecimal,
+/// octal and binary integers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    pub notation: Notation,
+    pub precision: Option<usize>,
+    pub separators: bool,
+}
+
+impl Format {
+    pub fn parse(spec: &str) -> Result<Format, String> {
+        let invalid = || format!("Invalid format: {}", spec);
+        let (separators, rest) = match spec.strip_prefix(',') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (precision, kind) = match rest.strip_prefix('.') {
+            Some(rest) => {
+                let digits = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let precision: usize = rest[..digits].parse().map_err(|_| invalid())?;
+                if precision > MAX_PRECISION {
+                    return Err(format!(
+                        "Format precision is at most {}, found {}",
+                        MAX_PRECISION, precision
+                    ));
+                }
+                (Some(precision), &rest[digits..])
+            }
+            None => (None, rest),
+        };
+        let notation = match kind {
+            "" if precision.is_none() && separators => Notation::General,
+            "f" => Notation::Fixed,
+            "s" => Notation::Significant,
+            "e" => Notation::Scientific,
+            "eng" => Notation::Engineering,
+            "x" => Notation::Radix(16),
+            "o" => Notation::Radix(8),
+            "b" => Notation::Radix(2),
+            _ => return Err(invalid()),
+        };
+        if matches!(notation, Notation::Radix(_)) && precision.is_some() {
+            return Err(invalid());
+        }
+        Ok(Format {
+            notation,
+            precision,
+            separators,
+        })
+    }
+
+    /// Writes `value`, formatting each number inside it.
+    pub fn value(&self, value: &Value, midpoint_notation: bool) -> Result<String, String> {
+        match value {
+            Value::Number(x) => self.float(*x),
+            Value::Decimal(x) => self.number(&x.to_string()),
+            Value::Interval(x) if midpoint_notation => Ok(format!(
+                "{} ± {}",
+                self.float(x.midpoint())?,
+                self.float(x.radius())?
+            )),
+            Value::Interval(x) => Ok(format!("[{} .. {}]", self.float(x.lo)?, self.float(x.hi)?)),
+            Value::Quantity(quantity) => {
+                let (magnitude, unit) = match &quantity.unit {
+                    Some(unit) => (
+                        ((*quantity.magnitude).clone() / Value::Number(unit.factor))?,
+                        unit.name.clone(),
+                    ),
+                    None => (
+                        (*quantity.magnitude).clone(),
+                        quantity.dimension.to_string(),
+                    ),
+                };
+                Ok(format!(
+                    "{} {}",
+                    self.value(&magnitude, midpoint_notation)?,
+                    unit
+                ))
+            }
+            Value::Function(_) => Ok(value.to_string()),
+            Value::List(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.value(value, midpoint_notation))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("[{}]", values.join(", ")))
+            }
+        }
+    }
+
+    /// Writes a float. Other bases start from its exact integer value, as
+    /// its shortest decimal form, such as 1180591620717411300000 for 2^70,
+    /// fills the low digits with zeros.
+    fn float(&self, x: f64) -> Result<String, String> {
+        match (self.notation, BigInt::from_f64(x)) {
+            (Notation::Radix(_), Some(integer)) if x != 0.0 => self.number(&integer.to_string()),
+            _ => self.number(&x.to_string()),
+        }
+    }
+
+    /// Reformats a number written in plain or scientific decimal notation.
+    pub fn number(&self, text: &str) -> Result<String, String> {
+        let Some(number) = Digits::parse(text) else {
+            // infinities and NaN are written as they are
+            return Ok(text.to_string());
+        };
+        let precision = self.precision.unwrap_or(DEFAULT_PRECISION);
+        let text = match self.notation {
+            Notation::General => number.plain(),
+            Notation::Fixed => number
+                .round(number.exponent + precision as i64)
+                .plain_fixed(precision),
+            Notation::Significant => {
+                let number = number.round(precision.max(1) as i64);
+                match PLAIN_EXPONENTS.contains(&(number.exponent - 1)) {
+                    true => number.plain_significant(precision.max(1)),
+                    false => number.scientific(precision.max(1), 1),
+                }
+            }
+            Notation::Scientific => number
+                .round(precision as i64 + 1)
+                .scientific(precision + 1, 1),
+            Notation::Engineering => {
+                let number = number.round(precision as i64 + 1);
+                // a mantissa of 1 to 3 digits before the point
+                let leading = (number.exponent - 1).rem_euclid(3) as usize + 1;
+                number.scientific((precision + 1).max(leading), leading)
+            }
+            Notation::Radix(radix) => return number.radix(radix, self.separators, text),
+        };
+        Ok(match self.separators {
+            true => separate_thousands(&text),
+            false => text,
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.separators {
+            write!(f, ",")?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        match self.notation {
+            Notation::General => Ok(()),
+            Notation::Fixed => write!(f, "f"),
+            Notation::Significant => write!(f, "s"),
+            Notation::Scientific => write!(f, "e"),
+            Notation::Engineering => write!(f, "eng"),
+            Notation::Radix(16) => write!(f, "x"),
+            Notation::Radix(8) => write!(f, "o"),
+            Notation::Radix(_) => write!(f, "b"),
+        }
+    }
+}
+
+/// A finite number as the decimal digits `0.d1 d2 d3 ... * 10^exponent`.
+#[derive(Debug, Clone, PartialEq)]
+struct Digits {
+    negative: bool,
+    /// Without leading zeros, and empty for zero.
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+impl Digits {
+    fn parse(text: &str) -> Option<Digits> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text),
+        };
+        let (mantissa, exponent) = match text.split_once('e') {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if whole.is_empty() || !(whole.bytes().chain(fraction.bytes())).all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let all: Vec<u8> = whole
+            .bytes()
+            .chain(fraction.bytes())
+            .map(|b| b - b'0')
+            .collect();
+        let leading = all.iter().take_while(|&&digit| digit == 0).count();
+        let mut digits = all[leading..].to_vec();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Some(Digits {
+            negative,
+            exponent: match digits.is_empty() {
+                true => 0,
+                false => whole.len() as i64 - leading as i64 + exponent,
+            },
+            digits,
+        })
+    }
+
+    /// Rounds to `keep` significant digits, with halves away from zero.
+    fn round(&self, keep: i64) -> Digits {
+        let mut rounded = self.clone();
+        if keep < 0 || self.digits.len() as i64 <= keep {
+            if keep < 0 {
+                rounded.digits.clear();
+            }
+            return rounded.normalized();
+        }
+        let keep = keep as usize;
+        let round_up = self.digits[keep] >= 5;
+        rounded.digits.truncate(keep);
+        if round_up {
+            // propagate the carry, growing a new leading digit if needed
+            let mut i = keep;
+            loop {
+                if i == 0 {
+                    rounded.digits.insert(0, 1);
+                    rounded.exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if rounded.digits[i] == 9 {
+                    rounded.digits[i] = 0;
+                } else {
+                    rounded.digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        rounded.normalized()
+    }
+
+    fn normalized(mut self) -> Digits {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        if self.digits.is_empty() {
+            // a value rounded to zero loses its sign
+            self.negative = false;
+            self.exponent = 0;
+        }
+        self
+    }
+
+    fn digit(&self, i: i64) -> char {
+        match usize::try_from(i) {
+            Ok(i) if i < self.digits.len() => (b'0' + self.digits[i]) as char,
+            _ => '0',
+        }
+    }
+
+    fn sign(&self) -> &'static str {
+        if self.negative {
+            "-"
+        } else {
+            ""
+        }
+    }
+
+    /// Plain notation with exactly `decimals` digits after the point.
+    fn plain_fixed(&self, decimals: usize) -> String {
+        let whole: String = match self.exponent > 0 {
+            true => (0..self.exponent).map(|i| self.digit(i)).collect(),
+            false => "0".to_string(),
+        };
+        let fraction: String = (0..decimals as i64)
+            .map(|i| self.digit(self.exponent + i))
+            .collect();
+        match decimals {
+            0 => format!("{}{}", self.sign(), whole),
+            _ => format!("{}{}.{}", self.sign(), whole, fraction),
+        }
+    }
+
+    /// Plain notation with every stored digit.
+    fn plain(&self) -> String {
+        let decimals = (self.digits.len() as i64 - self.exponent).max(0);
+        self.plain_fixed(decimals as usize)
+    }
+
+    /// Plain notation with `significant` digits, padding with zeros.
+    fn plain_significant(&self, significant: usize) -> String {
+        let decimals = match self.digits.is_empty() {
+            true => significant as i64 - 1,
+            false => significant as i64 - self.exponent,
+        };
+        self.plain_fixed(decimals.max(0) as usize)
+    }
+
+    /// `significant` digits with `leading` of them before the point, and the
+    /// exponent that makes up the difference.
+    fn scientific(&self, significant: usize, leading: usize) -> String {
+        let mantissa: String = (0..significant as i64).map(|i| self.digit(i)).collect();
+        let (whole, fraction) = mantissa.split_at(leading.min(significant));
+        let exponent = match self.digits.is_empty() {
+            true => 0,
+            false => self.exponent - leading as i64,
+        };
+        match fraction.is_empty() {
+            true => format!("{}{}e{}", self.sign(), whole, exponent),
+            false => format!("{}{}.{}e{}", self.sign(), whole, fraction, exponent),
+        }
+    }
+
+    /// An integer in base `radix` with a `0x`, `0o` or `0b` prefix.
+    fn radix(&self, radix: u32, separators: bool, text: &str) -> Result<String, String> {
+        if self.exponent < self.digits.len() as i64 {
+            return Err(format!(
+                "Only integers can be formatted in base {}, found {}",
+                radix, text
+            ));
+        }
+        let mut integer = BigInt::parse(&self.plain()).unwrap_or_default().abs();
+        let mut digits = vec![];
+        while !integer.is_zero() {
+            digits.push(char::from_digit(integer.rem_small(radix), radix).unwrap_or('0'));
+            integer = integer.div_small(radix);
+        }
+        if digits.is_empty() {
+            digits.push('0');
+        }
+        let mut grouped = String::new();
+        for (i, digit) in digits.iter().enumerate() {
+            if separators && i > 0 && i % 4 == 0 {
+                grouped.push('_');
+            }
+            grouped.push(*digit);
+        }
+        let prefix = match radix {
+            16 => "0x",
+            8 => "0o",
+            _ => "0b",
+        };
+        Ok(format!(
+            "{}{}{}",
+            self.sign(),
+            prefix,
+            grouped.chars().rev().collect::<String>()
+        ))
+    }
+}
+
+/// Inserts `,` between groups of three digits before the point.
+fn separate_thousands(text: &str) -> String {
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let end = rest.find(['.', 'e']).unwrap_or(rest.len());
+    let (whole, tail) = rest.split_at(end);
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{}{}{}", sign, grouped, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(spec: &str, text: &str) -> String {
+        Format::parse(spec).unwrap().number(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Format::parse(",.2f"),
+            Ok(Format {
+                notation: Notation::Fixed,
+                precision: Some(2),
+                separators: true
+            })
+        );
+        assert_eq!(Format::parse(".3eng").unwrap().to_string(), ".3eng");
+        assert!(Format::parse(".2").is_err());
+        assert!(Format::parse(".2x").is_err());
+        assert!(Format::parse("q").is_err());
+        assert!(Format::parse(".1000f").is_ok());
+        assert_eq!(
+            Format::parse(".1234567890123f"),
+            Err("Format precision is at most 1000, found 1234567890123".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fixed_and_significant() {
+        assert_eq!(format(".2f", "0.30000000000000004"), "0.30");
+        assert_eq!(format(".2f", "2.675"), "2.68");
+        assert_eq!(format(".2f", "-0.001"), "0.00");
+        assert_eq!(format(".0f", "99.5"), "100");
+        assert_eq!(format(",.2f", "-1234567.891"), "-1,234,567.89");
+        assert_eq!(format(".3s", "0.000123456"), "0.000123");
+        assert_eq!(format(".4s", "1.5"), "1.500");
+        assert_eq!(format(".3s", "1e21"), "1.00e21");
+        assert_eq!(format(",", "1000000"), "1,000,000");
+    }
+
+    #[test]
+    fn test_exponents() {
+        assert_eq!(format(".3e", "12345"), "1.235e4");
+        assert_eq!(format(".2e", "0.000999"), "9.99e-4");
+        assert_eq!(format(".2e", "0"), "0.00e0");
+        assert_eq!(format(".3eng", "12345"), "12.35e3");
+        assert_eq!(format(".2eng", "0.00047"), "470e-6");
+        assert_eq!(format("eng", "1e21"), "1.000000e21");
+    }
+
+    #[test]
+    fn test_radix() {
+        assert_eq!(format("x", "255"), "0xff");
+        assert_eq!(format("b", "-5"), "-0b101");
+        assert_eq!(format(",b", "255"), "0b1111_1111");
+        assert_eq!(format("x", "4294967296"), "0x100000000");
+        assert!(Format::parse("x").unwrap().number("2.5").is_err());
+
+        // above 2^53 the shortest decimal form is not the exact value
+        let hex = Format::parse("x").unwrap();
+        let power = Value::Number(2f64.powi(70));
+        assert_eq!(hex.value(&power, false).unwrap(), "0x400000000000000000");
+        let odd = Value::Number(2f64.powi(60) + 2f64.powi(10));
+        assert_eq!(hex.value(&odd, false).unwrap(), "0x1000000000000400");
+    }
+}
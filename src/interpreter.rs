@@ -1,98 +1,1120 @@
-use crate::enums::{Expression, Statement};
+use crate::builtins::{self, Angle};
+use crate::bytecode;
+use crate::decimal::Decimal;
+use crate::enums::{Expression, PrintItem, Statement};
+use crate::format::Format;
+use crate::interval::Interval;
+use crate::native::{Native, NativeFunction};
+use crate::numeric;
+use crate::optimizer;
 use crate::parser::Parser;
+use crate::random::Rng;
+use crate::symbolic::{self, Function};
+use crate::units::Quantity;
+use crate::value::Value;
+use crate::vm;
 use std::collections::HashMap;
 use std::f64::consts::PI;
+use std::fmt::Display;
+
+/// Built-ins whose arguments are passed as expressions rather than values.
+pub const SPECIAL_FORMS: [&str; 8] = [
+    "diff",
+    "simplify",
+    "expand",
+    "solve",
+    "integrate",
+    "sum",
+    "prod",
+    "ode",
+];
+
+/// Built-ins that draw from the interpreter's random number generator.
+const RANDOM_FUNCTIONS: [&str; 5] = ["rand", "randint", "normal", "shuffle", "seed"];
+
+/// The interval `solve` searches for roots when none is given.
+const SOLVE_RANGE: f64 = 100.0;
+
+/// How many pieces `solve` splits its interval into to bracket roots.
+const SOLVE_SAMPLES: usize = 2000;
+
+const ODE_EQUATION: &str = "ode expects an equation such as dy/dt = f(t, y)";
+
+/// The relative error estimate above which `integrate` reports failure.
+const INTEGRATION_TOLERANCE: f64 = 1e-8;
+
+/// How close to zero a refined root must bring the equation, and how close
+/// two roots must be to count as one.
+const ROOT_TOLERANCE: f64 = 1e-9;
 
 pub struct Interpreter<'a> {
     parser: &'a mut Parser<'a>,
-    pub variables: HashMap<String, f64>,
+    pub variables: HashMap<String, Value>,
+    /// Bindings that shadow `variables`, innermost last, such as the
+    /// parameters of the function being called.
+    scopes: Vec<HashMap<String, Value>>,
+    /// Significant digits used for decimal arithmetic, or `None` to evaluate
+    /// with `f64`.
+    pub precision: Option<usize>,
+    /// Print intervals as `midpoint ± radius` rather than as bounds.
+    pub midpoint_notation: bool,
+    /// How `print` writes numbers when it is not given a format.
+    pub format: Option<Format>,
+    /// The generator behind `rand` and the other random built-ins.
+    rng: Rng,
+    /// The unit of the angles taken and returned by trigonometric built-ins.
+    pub angle: Angle,
+    /// Print the value of each expression statement as the REPL does, as
+    /// `$n = value`, binding it to `$n`, `ans` and `_`.
+    pub echo: bool,
+    /// How many values have been echoed.
+    outputs: usize,
+    /// How hard `interpret` optimizes the program before running it.
+    pub opt_level: u8,
+    /// Functions registered by the embedding program.
+    natives: HashMap<String, Native>,
+    /// Whether programs may call the built-in functions. Registered native
+    /// functions stay callable either way.
+    pub builtins: bool,
 }
 
 impl<'a> Interpreter<'a> {
     pub fn new(parser: &'a mut Parser<'a>) -> Self {
         Interpreter {
             parser,
-            variables: HashMap::from([("pi".to_string(), PI)]),
+            variables: HashMap::from([("pi".to_string(), Value::Number(PI))]),
+            scopes: vec![],
+            precision: None,
+            midpoint_notation: false,
+            format: None,
+            rng: Rng::from_time(),
+            angle: Angle::Radians,
+            echo: false,
+            outputs: 0,
+            opt_level: optimizer::DEFAULT_LEVEL,
+            natives: HashMap::new(),
+            builtins: true,
+        }
+    }
+
+    /// Restarts the random number generator from `seed`, making the numbers
+    /// drawn afterwards reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Switches to decimal arithmetic with the given number of significant
+    /// digits. The predefined `pi` is recomputed at the new precision.
+    pub fn set_precision(&mut self, precision: usize) -> Result<(), String> {
+        if precision == 0 {
+            return Err("Precision must be at least one digit".to_owned());
         }
+        self.precision = Some(precision);
+        self.variables
+            .insert("pi".to_string(), Value::Decimal(Decimal::pi(precision)));
+        Ok(())
     }
 
     pub fn interpret(&mut self) -> Result<(), String> {
         let statements = self.parser.parse()?;
+        self.execute(optimizer::optimize(statements, self.opt_level))
+    }
+
+    /// Like `interpret`, but runs the program on the bytecode virtual
+    /// machine, unless it uses something the compiler cannot handle, such
+    /// as calling a function passed as a parameter.
+    pub fn interpret_compiled(&mut self) -> Result<(), String> {
+        let statements = optimizer::optimize(self.parser.parse()?, self.opt_level);
+        // the compiler turns `sum` and `prod` into loops without asking
+        // whether they are available
+        let shadowed = SPECIAL_FORMS
+            .iter()
+            .any(|name| self.natives.contains_key(*name));
+        if !self.builtins || shadowed {
+            return self.execute(statements);
+        }
+        match bytecode::compile(&statements) {
+            Ok(program) => vm::run(self, &program),
+            Err(_) => self.execute(statements),
+        }
+    }
+
+    /// Runs statements parsed elsewhere, such as each line of the REPL, in
+    /// this interpreter's environment.
+    pub fn execute(&mut self, statements: Vec<Statement>) -> Result<(), String> {
         for statement in statements {
             match statement {
                 Statement::Expression(expression) => {
-                    self.evaluate_expression(expression)?;
+                    let value = self.evaluate_expression(expression)?;
+                    if self.echo {
+                        self.outputs += 1;
+                        println!("${} = {}", self.outputs, self.display(&value, None)?);
+                        self.variables
+                            .insert(format!("${}", self.outputs), value.clone());
+                        self.variables.insert("_".to_string(), value.clone());
+                        self.variables.insert("ans".to_string(), value);
+                    }
                 }
                 Statement::Assignment(name, expression) => {
                     let value = self.evaluate_expression(expression)?;
                     self.variables.insert(name, value);
                 }
-                Statement::Print(expression) => {
-                    let value = self.evaluate_expression(expression)?;
-                    println!("{}", value);
+                Statement::Assignments(names, expressions) => {
+                    let values = expressions
+                        .into_iter()
+                        .map(|expression| self.evaluate_expression(expression))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    self.variables.extend(names.into_iter().zip(values));
+                }
+                Statement::Function(name, parameters, body) => {
+                    let function = Function { parameters, body };
+                    self.variables.insert(name, Value::Function(function));
                 }
+                Statement::Print(items) => {
+                    let mut line = String::new();
+                    for item in items {
+                        match item {
+                            PrintItem::Text(text) => line.push_str(&text),
+                            PrintItem::Value(expression, format) => {
+                                let value = self.evaluate_expression(expression)?;
+                                line.push_str(&self.display(&value, format.as_ref())?);
+                            }
+                        }
+                    }
+                    println!("{}", line);
+                }
+                Statement::Pragma(name, argument) => {
+                    self.apply_pragma(&name, argument)?;
+                }
+                Statement::Format(format) => self.format = format,
             }
         }
         Ok(())
     }
 
-    fn evaluate_expression(&mut self, expression: Expression) -> Result<f64, String> {
-        match expression {
-            Expression::Group(expression) => self.evaluate_expression(*expression),
-            Expression::Number(value) => Ok(value),
-            Expression::Variable(name) => {
-                if let Some(value) = self.variables.get(&name) {
-                    Ok(*value)
-                } else {
-                    Err(format!("Undefined variable: {}", name))
+    pub fn apply_pragma(&mut self, name: &str, argument: Expression) -> Result<(), String> {
+        match name {
+            "precision" => {
+                let digits = self.evaluate_expression(argument)?.to_f64();
+                if digits.fract() != 0.0 || digits < 1.0 {
+                    return Err(format!("Invalid precision: {}", digits));
+                }
+                self.set_precision(digits as usize)
+            }
+            "interval" => {
+                self.midpoint_notation = match argument {
+                    Expression::Variable(notation) if notation == "midpoint" => true,
+                    Expression::Variable(notation) if notation == "bounds" => false,
+                    _ => return Err("#interval expects 'bounds' or 'midpoint'".to_owned()),
+                };
+                Ok(())
+            }
+            "angle" => {
+                self.angle = match argument {
+                    Expression::Variable(name) => Angle::parse(&name),
+                    _ => None,
                 }
+                .ok_or("#angle expects 'rad', 'deg' or 'grad'")?;
+                Ok(())
             }
+            _ => Err(format!("Unknown pragma: #{}", name)),
+        }
+    }
+
+    /// Writes `value` for `print`, in `format` or else the session's format.
+    pub fn display(&self, value: &Value, format: Option<&Format>) -> Result<String, String> {
+        let format = format.or(self.format.as_ref());
+        match value {
+            // a list of rows, such as an ODE trajectory, prints as a table
+            Value::List(rows)
+                if !rows.is_empty() && rows.iter().all(|row| matches!(row, Value::List(_))) =>
+            {
+                let rows = rows
+                    .iter()
+                    .map(|row| match row {
+                        Value::List(cells) => {
+                            let cells = cells
+                                .iter()
+                                .map(|cell| self.display(cell, format))
+                                .collect::<Result<Vec<_>, _>>()?;
+                            Ok(cells.join("\t"))
+                        }
+                        row => self.display(row, format),
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(rows.join("\n"))
+            }
+            value => match (format, value) {
+                (Some(format), value) => format.value(value, self.midpoint_notation),
+                (None, Value::Interval(interval)) if self.midpoint_notation => {
+                    Ok(interval.to_midpoint_string())
+                }
+                (None, value) => Ok(value.to_string()),
+            },
+        }
+    }
+
+    fn evaluate_expression(&mut self, expression: Expression) -> Result<Value, String> {
+        let value = match expression {
+            Expression::Group(expression) => self.evaluate_expression(*expression),
+            Expression::Number(value) => Ok(self.number(value)),
+            Expression::Literal(text) => self.literal(&text),
+            Expression::Variable(name) => match self.lookup(&name)? {
+                Some(value) => Ok(value),
+                None => Err(format!("Undefined variable: {}", name)),
+            },
             Expression::Plus(left, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                Ok(left + right)
+                left + right
             }
             Expression::Minus(left, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                Ok(left - right)
+                left - right
             }
             Expression::Asterisk(left, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                Ok(left * right)
+                left * right
             }
             Expression::Slash(left, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                Ok(left / right)
+                left / right
             }
             Expression::Factorial(factor) => {
-                let factor = self.evaluate_expression(*factor)? as i32;
-                let result: i32 = (1..=factor).product();
-                Ok(result as f64)
+                let factor = self.evaluate_expression(*factor)?;
+                factor.factorial()
             }
             Expression::Power(left, right) => {
                 let left = self.evaluate_expression(*left)?;
                 let right = self.evaluate_expression(*right)?;
-                Ok(left.powf(right))
+                left.pow(right)
+            }
+            Expression::Negative(operand) => {
+                let operand = self.evaluate_expression(*operand)?;
+                -operand
+            }
+            Expression::Degrees(angle) => {
+                let angle = self.evaluate_expression(*angle)?;
+                self.degrees(angle)
+            }
+            Expression::Interval(lower, upper) => {
+                let lower = self.evaluate_expression(*lower)?.to_interval();
+                let upper = self.evaluate_expression(*upper)?.to_interval();
+                Interval::new(lower.lo, upper.hi).map(Value::Interval)
+            }
+            Expression::PlusMinus(midpoint, radius) => {
+                let midpoint = self.evaluate_expression(*midpoint)?.to_interval();
+                let radius = self.evaluate_expression(*radius)?.to_interval();
+                Interval::from_midpoint(&midpoint, radius.hi).map(Value::Interval)
+            }
+            Expression::Quantity(magnitude, unit) => {
+                let magnitude = self.evaluate_expression(*magnitude)?;
+                Quantity::with_unit(magnitude, &unit)
+            }
+            Expression::Convert(value, unit) => {
+                let value = self.evaluate_expression(*value)?;
+                Quantity::convert(value, &unit)
+            }
+            Expression::Call(name, arguments) => {
+                let function = match self.lookup(&name)? {
+                    Some(Value::Function(function)) => Some(function),
+                    Some(_) => return Err(format!("{} is not a function", name)),
+                    None if self.is_special_form(&name) => {
+                        let value = self.call_special_form(&name, arguments)?;
+                        return Ok(self.float_unless_integer(value));
+                    }
+                    None => None,
+                };
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| self.evaluate_expression(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match function {
+                    Some(function) => self.call_function(&name, function, arguments),
+                    None => self.call_builtin(&name, arguments),
+                }
+            }
+            Expression::Equation(..) => {
+                Err("Equations can only be passed to solve and ode".to_owned())
+            }
+            Expression::List(items) => items
+                .into_iter()
+                .map(|item| self.evaluate_expression(item))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            Expression::None => Ok(Value::Number(0.0)),
+        }?;
+        Ok(self.float_unless_integer(value))
+    }
+
+    /// Evaluates a number literal too long for an `f64` to hold exactly.
+    pub fn literal(&self, text: &str) -> Result<Value, String> {
+        let value = match self.precision {
+            Some(precision) => Decimal::parse(text, precision).map(Value::Decimal),
+            // keep every digit of integers too large for an f64, so
+            // that number theory built-ins see them exactly
+            None if text.bytes().all(|b| b.is_ascii_digit()) => {
+                Decimal::parse(text, text.len()).map(Value::Decimal)
+            }
+            None => text.parse().ok().map(Value::Number),
+        };
+        value.ok_or_else(|| format!("Invalid number: {}", text))
+    }
+
+    /// Without `#precision`, turns a decimal that is not an integer back
+    /// into a float. Long integer literals are decimals only so that they
+    /// stay exact; an inexact result such as their quotient must not take
+    /// its precision from their digit count.
+    pub fn float_unless_integer(&self, value: Value) -> Value {
+        match value {
+            Value::Decimal(decimal) if self.precision.is_none() && !decimal.is_integer() => {
+                Value::Number(decimal.to_f64())
+            }
+            Value::Quantity(mut quantity) => {
+                quantity.magnitude = Box::new(self.float_unless_integer(*quantity.magnitude));
+                Value::Quantity(quantity)
+            }
+            Value::List(values) => Value::List(
+                values
+                    .into_iter()
+                    .map(|value| self.float_unless_integer(value))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Converts an angle given in degrees, as in `90°`, to the angle mode.
+    pub fn degrees(&self, angle: Value) -> Result<Value, String> {
+        match self.angle {
+            Angle::Degrees => Ok(angle),
+            _ => (angle * self.half_turn())? / self.number(180.0),
+        }
+    }
+
+    /// Makes `function` callable from programs as `name`, taking `arity`
+    /// arguments. It takes precedence over a built-in of the same name, but
+    /// not over functions the program defines.
+    pub fn register(&mut self, name: &str, arity: usize, function: impl NativeFunction + 'static) {
+        let function = Box::new(function);
+        self.natives
+            .insert(name.to_string(), Native { arity, function });
+    }
+
+    /// Like `register`, for a closure whose errors are reported with their
+    /// `Display` text.
+    pub fn register_fn<F, E>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, E> + 'static,
+        E: Display,
+    {
+        self.register(name, arity, move |arguments| {
+            function(arguments).map_err(|error| error.to_string())
+        });
+    }
+
+    fn is_special_form(&self, name: &str) -> bool {
+        self.builtins && SPECIAL_FORMS.contains(&name) && !self.natives.contains_key(name)
+    }
+
+    /// Calls a registered native or built-in function on evaluated
+    /// arguments.
+    pub fn call_builtin(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        if let Some(native) = self.natives.get(name) {
+            native.call(name, arguments)
+        } else if !self.builtins {
+            Err(format!("Undefined function: {}", name))
+        } else if RANDOM_FUNCTIONS.contains(&name) {
+            self.call_random(name, arguments)
+        } else if self.angle != Angle::Radians {
+            self.call_in_angle_mode(name, arguments)
+        } else {
+            builtins::call(name, arguments)
+        }
+    }
+
+    /// Calls a built-in, converting the angles that trigonometric built-ins
+    /// take or return from or to the session's angle mode.
+    fn call_in_angle_mode(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        if builtins::INVERSE_TRIGONOMETRIC.contains(&name) {
+            let radians = builtins::call(name, arguments)?;
+            return (radians * self.number(self.angle.half_turn()))? / self.pi();
+        }
+        if !builtins::TRIGONOMETRIC.contains(&name) {
+            return builtins::call(name, arguments);
+        }
+
+        // whole quarter turns are exact, so that sin(180) is 0 in degrees
+        if let [Value::Number(angle)] = arguments[..] {
+            let quarter_turn = self.angle.half_turn() / 2.0;
+            let turns = angle.rem_euclid(4.0 * quarter_turn);
+            if turns % quarter_turn == 0.0 {
+                let sine = [0.0, 1.0, 0.0, -1.0];
+                let quarter = (turns / quarter_turn) as usize;
+                return match name {
+                    "sin" => Ok(Value::Number(sine[quarter])),
+                    "cos" => Ok(Value::Number(sine[(quarter + 1) % 4])),
+                    _ if quarter % 2 == 1 => Err(format!("tan is undefined at {}", angle)),
+                    _ => Ok(Value::Number(0.0)),
+                };
+            }
+        }
+        let arguments = arguments
+            .into_iter()
+            .map(|angle| (angle * self.pi())? / self.number(self.angle.half_turn()))
+            .collect::<Result<_, _>>()?;
+        builtins::call(name, arguments)
+    }
+
+    /// Half a turn in the session's angle mode.
+    fn half_turn(&self) -> Value {
+        match self.angle {
+            Angle::Radians => self.pi(),
+            angle => self.number(angle.half_turn()),
+        }
+    }
+
+    /// Pi in the active number representation, whatever `pi` is bound to.
+    fn pi(&self) -> Value {
+        match self.precision {
+            Some(precision) => Value::Decimal(Decimal::pi(precision)),
+            None => Value::Number(PI),
+        }
+    }
+
+    /// Calls one of the `RANDOM_FUNCTIONS`.
+    fn call_random(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        let x = match name {
+            "rand" => {
+                let [] = builtins::expect_arguments(name, arguments)?;
+                self.rng.next_f64()
+            }
+            "randint" => {
+                let [low, high] = builtins::expect_arguments(name, arguments)?;
+                let (low, high) = (low.to_f64(), high.to_f64());
+                if low.fract() != 0.0 || high.fract() != 0.0 || low > high {
+                    return Err(format!(
+                        "randint expects integers a <= b, found {} and {}",
+                        low, high
+                    ));
+                }
+                // u64::MAX rounds up to 2^64, so a smaller span leaves room for the + 1
+                let count = match high - low {
+                    span if span < u64::MAX as f64 => span as u64 + 1,
+                    _ => {
+                        return Err(format!(
+                            "randint range from {} to {} is too large",
+                            low, high
+                        ))
+                    }
+                };
+                low + self.rng.below(count) as f64
+            }
+            "normal" => {
+                let [mean, stdev] = builtins::expect_arguments(name, arguments)?;
+                let (mean, stdev) = (mean.to_f64(), stdev.to_f64());
+                if stdev < 0.0 || stdev.is_nan() {
+                    return Err(format!(
+                        "normal expects a non-negative standard deviation, found {}",
+                        stdev
+                    ));
+                }
+                mean + stdev * self.rng.normal()
+            }
+            "shuffle" => {
+                let [list] = builtins::expect_arguments(name, arguments)?;
+                let Value::List(mut items) = list else {
+                    return Err(format!("shuffle expects a list, found {}", list));
+                };
+                self.rng.shuffle(&mut items);
+                return Ok(Value::List(items));
+            }
+            _ => {
+                let [seed] = builtins::expect_arguments(name, arguments)?;
+                let seed = seed.to_f64();
+                if seed.fract() != 0.0 || !seed.is_finite() {
+                    return Err(format!("seed expects an integer, found {}", seed));
+                }
+                self.set_seed(seed as i64 as u64);
+                seed
+            }
+        };
+        Ok(self.number(x))
+    }
+
+    /// Looks a name up in the innermost scope that binds it. A name with a
+    /// trailing prime, such as `f'`, is the derivative of the function `f`.
+    fn lookup(&self, name: &str) -> Result<Option<Value>, String> {
+        let value = self
+            .scopes
+            .iter()
+            .rev()
+            .chain([&self.variables])
+            .find_map(|scope| scope.get(name));
+        if let Some(value) = value {
+            return Ok(Some(value.clone()));
+        }
+
+        let Some(base) = name.strip_suffix('\'') else {
+            return Ok(None);
+        };
+        match self.lookup(base)? {
+            Some(Value::Function(function)) => {
+                let Some(variable) = function.parameters.first() else {
+                    return Err(format!("{} has no parameter to differentiate by", base));
+                };
+                let body = self.inline_functions(function.body.clone(), Some(variable))?;
+                let body = symbolic::differentiate(&body, variable, self.angle)?;
+                Ok(Some(Value::Function(Function {
+                    body: symbolic::simplify(body),
+                    ..function
+                })))
+            }
+            Some(_) => Err(format!("{} is not a function", base)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn call_function(
+        &mut self, name: &str, function: Function, arguments: Vec<Value>,
+    ) -> Result<Value, String> {
+        if arguments.len() != function.parameters.len() {
+            return Err(format!(
+                "{} expects {} argument(s), found {}",
+                name,
+                function.parameters.len(),
+                arguments.len()
+            ));
+        }
+
+        // the body sees its parameters and the globals, not the caller's scope
+        let bindings = function.parameters.into_iter().zip(arguments).collect();
+        let scopes = std::mem::replace(&mut self.scopes, vec![bindings]);
+        let result = self.evaluate_expression(function.body);
+        self.scopes = scopes;
+        result
+    }
+
+    /// Calls a built-in that receives its arguments as unevaluated
+    /// expressions.
+    fn call_special_form(
+        &mut self, name: &str, arguments: Vec<Expression>,
+    ) -> Result<Value, String> {
+        match name {
+            "diff" => self.differentiate(arguments),
+            "simplify" | "expand" => self.rewrite(name, arguments),
+            "solve" => self.solve(arguments),
+            "integrate" => self.integrate(arguments),
+            "sum" | "prod" => self.iterate(name, arguments),
+            "ode" => self.ode(arguments),
+            _ => Err(format!("Undefined function: {}", name)),
+        }
+    }
+
+    /// Evaluates `diff(expr, x)` to the derivative of `expr` as a function
+    /// of `x`.
+    fn differentiate(&self, arguments: Vec<Expression>) -> Result<Value, String> {
+        let [expression, variable] = builtins::expect_arguments("diff", arguments)?;
+        let Expression::Variable(variable) = variable else {
+            return Err("diff expects a variable to differentiate by".to_owned());
+        };
+
+        let expression = self.inline_functions(expression, Some(&variable))?;
+        let body = symbolic::differentiate(&expression, &variable, self.angle)?;
+        Ok(Value::Function(Function {
+            parameters: vec![variable],
+            body: symbolic::simplify(body),
+        }))
+    }
+
+    /// Evaluates `simplify(expr)` or `expand(expr)`. The result is a function
+    /// of the free variables of `expr`, or a number if there are none.
+    fn rewrite(&mut self, name: &str, arguments: Vec<Expression>) -> Result<Value, String> {
+        let [expression] = builtins::expect_arguments(name, arguments)?;
+        let parameters = match &expression {
+            Expression::Variable(function) => match self.lookup(function)? {
+                Some(Value::Function(function)) => Some(function.parameters),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let expression = self.inline_functions(expression, None)?;
+        let body = match name {
+            "expand" => symbolic::expand(expression),
+            _ => symbolic::simplify(expression),
+        };
+        let parameters = match parameters {
+            Some(parameters) => parameters,
+            None => symbolic::free_variables(&body)
+                .into_iter()
+                .filter(|name| !matches!(self.lookup(name), Ok(Some(_))))
+                .collect(),
+        };
+        if parameters.is_empty() {
+            return self.evaluate_expression(body);
+        }
+        Ok(Value::Function(Function { parameters, body }))
+    }
+
+    /// Evaluates `solve(equation, x)` or `solve(equation, x, [a .. b])` to the
+    /// list of real roots, in ascending order. Linear and quadratic equations
+    /// are solved exactly; otherwise roots are bracketed on a grid over the
+    /// interval and refined numerically.
+    fn solve(&mut self, arguments: Vec<Expression>) -> Result<Value, String> {
+        let (equation, variable, range) = match <[Expression; 3]>::try_from(arguments) {
+            Ok([equation, variable, range]) => (equation, variable, Some(range)),
+            Err(arguments) => {
+                let [equation, variable] = builtins::expect_arguments("solve", arguments)?;
+                (equation, variable, None)
+            }
+        };
+        let Expression::Variable(variable) = variable else {
+            return Err("solve expects a variable to solve for".to_owned());
+        };
+        let range = match range {
+            Some(range) => Some(self.evaluate_expression(range)?.to_interval()),
+            None => None,
+        };
+
+        let expression = match equation {
+            Expression::Equation(left, right) => {
+                Expression::Minus(left, Box::new(Expression::Group(right)))
+            }
+            expression => expression,
+        };
+        let expression = self.inline_functions(expression, Some(&variable))?;
+
+        let mut roots = match symbolic::coefficients(expression.clone(), &variable) {
+            Some(coefficients) if coefficients.len() <= 3 => {
+                let mut roots = self.solve_polynomial(coefficients, &variable)?;
+                if let Some(range) = range {
+                    roots.retain(|root| (range.lo..=range.hi).contains(&root.to_f64()));
+                }
+                roots
+            }
+            _ => {
+                let range = range.unwrap_or(Interval {
+                    lo: -SOLVE_RANGE,
+                    hi: SOLVE_RANGE,
+                });
+                self.find_roots(&expression, &variable, range)?
+            }
+        };
+        roots.sort_by(|a, b| a.to_f64().total_cmp(&b.to_f64()));
+        // adding zero turns a root of -0, as from -b/a with b = 0, into 0
+        let roots = roots
+            .into_iter()
+            .map(|root| root + self.number(0.0))
+            .collect::<Result<_, _>>()?;
+        Ok(Value::List(roots))
+    }
+
+    /// Solves `c0 + c1*x + c2*x^2 = 0` exactly, in the active number
+    /// representation.
+    fn solve_polynomial(
+        &mut self, coefficients: Vec<Expression>, variable: &str,
+    ) -> Result<Vec<Value>, String> {
+        let mut coefficients = coefficients
+            .into_iter()
+            .map(|coefficient| self.evaluate_expression(coefficient))
+            .collect::<Result<Vec<_>, _>>()?;
+        while coefficients
+            .last()
+            .is_some_and(|leading| leading.to_f64() == 0.0)
+        {
+            coefficients.pop();
+        }
+
+        let two = self.number(2.0);
+        match coefficients.as_slice() {
+            [] => Err(format!("Every value of {} is a solution", variable)),
+            [_] => Ok(vec![]),
+            [b, a] => Ok(vec![((-b.clone())? / a.clone())?]),
+            [c, b, a] => {
+                let discriminant =
+                    ((b.clone() * b.clone())? - ((self.number(4.0) * a.clone())? * c.clone())?)?;
+                if discriminant.to_f64() < 0.0 {
+                    return Ok(vec![]);
+                }
+                if discriminant.to_f64() == 0.0 {
+                    return Ok(vec![((-b.clone())? / (two * a.clone())?)?]);
+                }
+
+                let root = builtins::call("sqrt", vec![discriminant])?;
+                if b.to_f64() == 0.0 {
+                    let root = (root / (two * a.clone())?)?;
+                    return Ok(vec![(-root.clone())?, root]);
+                }
+
+                // avoid cancellation: q = -(b + sign(b)*sqrt(d))/2, then x = q/a and c/q
+                let q = match b.to_f64() < 0.0 {
+                    true => ((root - b.clone())? / two)?,
+                    false => ((-(b.clone() + root)?)? / two)?,
+                };
+                Ok(vec![(q.clone() / a.clone())?, (c.clone() / q)?])
+            }
+            _ => unreachable!("solve only passes polynomials of degree two or less"),
+        }
+    }
+
+    /// Finds the roots of `expression` in `range` by sampling it on a grid
+    /// and refining each sign change with Brent's method. Local minima of
+    /// `|f|` are refined through the derivative, which catches double roots.
+    fn find_roots(
+        &mut self, expression: &Expression, variable: &str, range: Interval,
+    ) -> Result<Vec<Value>, String> {
+        if !range.lo.is_finite() || !range.hi.is_finite() {
+            return Err("solve needs a finite interval".to_owned());
+        }
+        let derivative = symbolic::differentiate(expression, variable, self.angle)
+            .ok()
+            .map(symbolic::simplify);
+
+        let step = (range.hi - range.lo) / SOLVE_SAMPLES as f64;
+        let mut samples = Vec::with_capacity(SOLVE_SAMPLES + 1);
+        let mut error = None;
+        for i in 0..=SOLVE_SAMPLES {
+            let x = range.lo + step * i as f64;
+            // points outside the domain, such as ln(-1), are skipped
+            let y = self
+                .evaluate_at(expression, variable, x)
+                .unwrap_or_else(|e| {
+                    error.get_or_insert(e);
+                    f64::NAN
+                });
+            samples.push((x, y));
+        }
+        if let Some(error) = error.filter(|_| samples.iter().all(|(_, y)| y.is_nan())) {
+            return Err(error);
+        }
+
+        let mut roots = vec![];
+        for (i, window) in samples.windows(2).enumerate() {
+            let [(x0, y0), (x1, y1)] = [window[0], window[1]];
+            if y0 == 0.0 {
+                roots.push(x0);
+            } else if y0 * y1 < 0.0 {
+                let root = numeric::brent(|x| self.evaluate_at(expression, variable, x), x0, x1)?;
+                // a sign change across a pole is not a root
+                if self.evaluate_at(expression, variable, root)?.abs() <= y0.abs().min(y1.abs()) {
+                    roots.push(root);
+                }
+            } else if let (Some(derivative), Some(&(_, y2))) = (&derivative, samples.get(i + 2)) {
+                let is_minimum = y1.abs() < y0.abs() && y1.abs() < y2.abs() && y1 * y2 > 0.0;
+                if !is_minimum {
+                    continue;
+                }
+                // at a double root f' changes sign, so bracket the root of f'
+                let (start, end) = (x0, x0 + 2.0 * step);
+                let slope_at = |interpreter: &mut Self, x| {
+                    interpreter
+                        .evaluate_at(derivative, variable, x)
+                        .unwrap_or(f64::NAN)
+                };
+                let changes_sign = slope_at(self, start) * slope_at(self, end) < 0.0;
+                if !changes_sign {
+                    continue;
+                }
+                let root =
+                    numeric::brent(|x| self.evaluate_at(derivative, variable, x), start, end)?;
+                if self.evaluate_at(expression, variable, root)?.abs() <= ROOT_TOLERANCE {
+                    roots.push(root);
+                }
+            }
+        }
+        if samples.last().is_some_and(|&(_, y)| y == 0.0) {
+            roots.push(range.hi);
+        }
+
+        roots.sort_by(f64::total_cmp);
+        roots.dedup_by(|a, b| (*a - *b).abs() <= ROOT_TOLERANCE * b.abs().max(1.0));
+        Ok(roots.into_iter().map(Value::Number).collect())
+    }
+
+    /// Evaluates `integrate(expr, x, a, b)`, the definite integral of `expr`
+    /// over `x` from `a` to `b`.
+    fn integrate(&mut self, arguments: Vec<Expression>) -> Result<Value, String> {
+        let [expression, variable, lower, upper] =
+            builtins::expect_arguments("integrate", arguments)?;
+        let Expression::Variable(variable) = variable else {
+            return Err("integrate expects a variable of integration".to_owned());
+        };
+        let lower = self.evaluate_expression(lower)?.to_f64();
+        let upper = self.evaluate_expression(upper)?.to_f64();
+        if !lower.is_finite() || !upper.is_finite() {
+            return Err("integrate needs finite bounds".to_owned());
+        }
+
+        let expression = self.inline_functions(expression, Some(&variable))?;
+        let (value, error) = numeric::integrate(
+            |x| self.evaluate_at(&expression, &variable, x),
+            lower,
+            upper,
+        )?;
+        if error > INTEGRATION_TOLERANCE * value.abs().max(1.0) {
+            return Err(format!(
+                "integrate did not converge; the error estimate is {}",
+                error
+            ));
+        }
+        Ok(Value::Number(value))
+    }
+
+    /// Evaluates `ode(dy/dt = f(t, y), y0, t0, t1, step)` to the trajectory
+    /// of `y` as a list of `[t, y]` rows, using the classic Runge-Kutta
+    /// method. Systems take lists on both sides and a list of initial values,
+    /// as in `ode([dx/dt, dv/dt] = [v, -x], [1, 0], 0, 10, 0.1)`. Without a
+    /// step the adaptive Dormand-Prince method chooses its own.
+    fn ode(&mut self, arguments: Vec<Expression>) -> Result<Value, String> {
+        let (equation, initial, start, end, step) = match <[Expression; 5]>::try_from(arguments) {
+            Ok([equation, initial, start, end, step]) => {
+                (equation, initial, start, end, Some(step))
+            }
+            Err(arguments) => {
+                let [equation, initial, start, end] = builtins::expect_arguments("ode", arguments)?;
+                (equation, initial, start, end, None)
+            }
+        };
+
+        let Expression::Equation(derivatives, slopes) = equation else {
+            return Err(ODE_EQUATION.to_owned());
+        };
+        let (derivatives, slopes) = match (*derivatives, *slopes) {
+            (Expression::List(derivatives), Expression::List(slopes)) => (derivatives, slopes),
+            (derivative, slope) => (vec![derivative], vec![slope]),
+        };
+        if derivatives.len() != slopes.len() {
+            return Err(format!(
+                "ode has {} derivative(s) but {} right-hand side(s)",
+                derivatives.len(),
+                slopes.len()
+            ));
+        }
+        let mut names = vec![];
+        let mut time: Option<String> = None;
+        for derivative in derivatives {
+            let (name, variable) = derivative_names(derivative).ok_or(ODE_EQUATION)?;
+            if time.as_ref().is_some_and(|time| *time != variable) {
+                return Err("ode derivatives must share one independent variable".to_owned());
+            }
+            names.push(name);
+            time = Some(variable);
+        }
+        let time = time.ok_or(ODE_EQUATION)?;
+
+        let initial: Vec<f64> = match self.evaluate_expression(initial)? {
+            Value::List(values) => values.iter().map(Value::to_f64).collect(),
+            value => vec![value.to_f64()],
+        };
+        if initial.len() != names.len() {
+            return Err(format!(
+                "ode expects {} initial value(s), found {}",
+                names.len(),
+                initial.len()
+            ));
+        }
+        let start = self.evaluate_expression(start)?.to_f64();
+        let end = self.evaluate_expression(end)?.to_f64();
+        let step = match step {
+            Some(step) => Some(self.evaluate_expression(step)?.to_f64()),
+            None => None,
+        };
+
+        let slope = |t: f64, y: &[f64]| {
+            let mut bindings: HashMap<String, Value> = names
+                .iter()
+                .cloned()
+                .zip(y.iter().map(|&y| Value::Number(y)))
+                .collect();
+            bindings.insert(time.clone(), Value::Number(t));
+            slopes
+                .iter()
+                .map(|slope| {
+                    let value = self.evaluate_with(bindings.clone(), slope.clone())?;
+                    Ok(value.to_f64())
+                })
+                .collect()
+        };
+        let trajectory = match step {
+            Some(step) => numeric::rk4(slope, initial, start, end, step)?,
+            None => numeric::rk45(slope, initial, start, end)?,
+        };
+        let rows = trajectory.into_iter().map(|(t, y)| {
+            let row = std::iter::once(t).chain(y).map(Value::Number);
+            Value::List(row.collect())
+        });
+        Ok(Value::List(rows.collect()))
+    }
+
+    /// Evaluates `sum(expr, i, first, last)` or `prod(expr, i, first, last)`,
+    /// binding the index `i` to each integer from `first` to `last` in a
+    /// child scope.
+    fn iterate(&mut self, name: &str, arguments: Vec<Expression>) -> Result<Value, String> {
+        let [expression, index, first, last] = builtins::expect_arguments(name, arguments)?;
+        let Expression::Variable(index) = index else {
+            return Err(format!("{} expects an index variable", name));
+        };
+        let first = self.evaluate_expression(first)?;
+        let first = self.integer_bound(name, &first)?;
+        let last = self.evaluate_expression(last)?;
+        let last = self.integer_bound(name, &last)?;
+
+        let mut total = self.number(if name == "sum" { 0.0 } else { 1.0 });
+        for i in first..=last {
+            let value = self.number(i as f64);
+            let bindings = HashMap::from([(index.clone(), value)]);
+            let term = self.evaluate_with(bindings, expression.clone())?;
+            total = match name {
+                "sum" => (total + term)?,
+                _ => (total * term)?,
+            };
+        }
+        Ok(total)
+    }
+
+    /// Checks that a bound of `sum` or `prod` is an integer.
+    pub fn integer_bound(&self, name: &str, bound: &Value) -> Result<i64, String> {
+        let bound = bound.to_f64();
+        if bound.fract() != 0.0 || !bound.is_finite() {
+            return Err(format!("{} expects integer bounds, found {}", name, bound));
+        }
+        Ok(bound as i64)
+    }
+
+    /// Evaluates `expression` with `variable` bound to `x` in a child scope.
+    fn evaluate_at(
+        &mut self, expression: &Expression, variable: &str, x: f64,
+    ) -> Result<f64, String> {
+        let bindings = HashMap::from([(variable.to_string(), Value::Number(x))]);
+        let value = self.evaluate_with(bindings, expression.clone())?;
+        Ok(value.to_f64())
+    }
+
+    /// Evaluates `expression` in a child scope of the current one.
+    pub fn evaluate_with(
+        &mut self, bindings: HashMap<String, Value>, expression: Expression,
+    ) -> Result<Value, String> {
+        self.scopes.push(bindings);
+        let result = self.evaluate_expression(expression);
+        self.scopes.pop();
+        result
+    }
+
+    /// Replaces calls to user functions with their bodies so they can be
+    /// handled symbolically. Names bound to one-parameter functions are
+    /// inlined too, as functions of `variable` when one is given.
+    fn inline_functions(
+        &self, expression: Expression, variable: Option<&str>,
+    ) -> Result<Expression, String> {
+        let inline = |function: Function, arguments: Vec<Expression>| {
+            let bindings: Vec<_> = function.parameters.into_iter().zip(arguments).collect();
+            symbolic::substitute(function.body, &bindings)
+        };
+        let expression = match expression {
+            Expression::Variable(name) if Some(name.as_str()) != variable => {
+                match self.lookup(&name)? {
+                    Some(Value::Function(function)) if function.parameters.len() == 1 => {
+                        let body = match variable {
+                            Some(variable) => {
+                                let argument = Expression::Variable(variable.to_string());
+                                inline(function, vec![argument])
+                            }
+                            None => function.body,
+                        };
+                        self.inline_functions(body, variable)?
+                    }
+                    _ => Expression::Variable(name),
+                }
             }
-            Expression::None => Ok(0.0),
+            Expression::Call(name, arguments) => {
+                let arguments = arguments
+                    .into_iter()
+                    .map(|argument| self.inline_functions(argument, variable))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match self.lookup(&name)? {
+                    Some(Value::Function(function)) => {
+                        if arguments.len() != function.parameters.len() {
+                            return Err(format!(
+                                "{} expects {} argument(s), found {}",
+                                name,
+                                function.parameters.len(),
+                                arguments.len()
+                            ));
+                        }
+                        self.inline_functions(inline(function, arguments), variable)?
+                    }
+                    _ => Expression::Call(name, arguments),
+                }
+            }
+            expression => symbolic::map_operands(expression, |operand| {
+                self.inline_functions(operand, variable)
+            })?,
+        };
+        Ok(expression)
+    }
+
+    /// Converts a numeric literal into the active number representation.
+    pub fn number(&self, value: f64) -> Value {
+        match self
+            .precision
+            .and_then(|precision| Decimal::from_f64(value, precision))
+        {
+            Some(decimal) => Value::Decimal(decimal),
+            None => Value::Number(value),
         }
     }
 }
 
+/// Splits a derivative such as `dy/dt` into the names `y` and `t`.
+fn derivative_names(derivative: Expression) -> Option<(String, String)> {
+    let Expression::Slash(numerator, denominator) = derivative else {
+        return None;
+    };
+    let (Expression::Variable(numerator), Expression::Variable(denominator)) =
+        (*numerator, *denominator)
+    else {
+        return None;
+    };
+    let name = numerator
+        .strip_prefix('d')
+        .filter(|name| !name.is_empty())?;
+    let variable = denominator
+        .strip_prefix('d')
+        .filter(|name| !name.is_empty())?;
+    Some((name.to_string(), variable.to_string()))
+}
+
 mod tests {
     #[test]
     fn test_interpreter() {
         use super::*;
         use crate::lexer::Lexer;
 
-        let mut lexer = Lexer::new("a = 1 + 2 * 3 - 4 / 5");
+        let mut lexer = Lexer::new("a = 1 + 2");
         let mut parser = Parser::new(&mut lexer);
         let mut interpreter = Interpreter::new(&mut parser);
         interpreter.interpret().unwrap();
-        assert_eq!(interpreter.variables.get("a"), Some(&3.0));
+        assert_eq!(interpreter.variables.get("a"), Some(&Value::Number(3.0)));
+    }
+
+    // The original parser stopped after the first operator, so this used
+    // to evaluate to 1 + 2 = 3; with precedence climbing it is 6.2.
+    #[test]
+    fn test_interpreter_precedence() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new("a = 1 + 2 * 3 - 4 / 5\nb = 2 * 3 ^ 2\nc = 8 / 4 / 2");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables.get("a"), Some(&Value::Number(6.2)));
+        assert_eq!(interpreter.variables.get("b"), Some(&Value::Number(18.0)));
+        assert_eq!(interpreter.variables.get("c"), Some(&Value::Number(1.0)));
     }
 
     #[test]
@@ -116,7 +1138,7 @@ mod tests {
         let mut parser = Parser::new(&mut lexer);
         let mut interpreter = Interpreter::new(&mut parser);
         interpreter.interpret().unwrap();
-        assert_eq!(interpreter.variables.get("a"), Some(&120.0));
+        assert_eq!(interpreter.variables.get("a"), Some(&Value::Number(120.0)));
     }
 
     #[test]
@@ -128,6 +1150,421 @@ mod tests {
         let mut parser = Parser::new(&mut lexer);
         let mut interpreter = Interpreter::new(&mut parser);
         interpreter.interpret().unwrap();
-        assert_eq!(interpreter.variables.get("a"), Some(&8.0));
+        assert_eq!(interpreter.variables.get("a"), Some(&Value::Number(8.0)));
+    }
+
+    #[test]
+    fn test_interpreter_call() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new("a = sqrt(16) + abs(-2)");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables.get("a"), Some(&Value::Number(6.0)));
+    }
+
+    #[test]
+    fn test_interpreter_precision() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new("#precision 40\na = 0.1 + 0.2\nb = sqrt(2)\nc = pi");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables["a"].to_string(), "0.3");
+        assert_eq!(
+            interpreter.variables["b"].to_string(),
+            "1.41421356237309504880168872420969807857"
+        );
+        assert_eq!(
+            interpreter.variables["c"].to_string(),
+            "3.141592653589793238462643383279502884197"
+        );
+    }
+
+    #[test]
+    fn test_interpreter_interval() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new("a = [1 .. 2] * (3 ± 1)\nb = sqrt([4 .. 9])");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables["a"].to_string(), "[2 .. 8]");
+        assert_eq!(interpreter.variables["b"].to_string(), "[2 .. 3]");
+    }
+
+    #[test]
+    fn test_interpreter_units() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "g = 9.81 m/s^2\nd = 3 km + 200 m\nv = 90 km/h to m/s\nr = d / (400 m)\nx = 2 m + 3 s",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(
+            interpreter.interpret(),
+            Err("Incompatible dimensions: m and s".to_string())
+        );
+        assert_eq!(interpreter.variables["g"].to_string(), "9.81 m/s^2");
+        assert_eq!(interpreter.variables["d"].to_string(), "3.2 km");
+        assert_eq!(interpreter.variables["v"].to_string(), "25 m/s");
+        assert_eq!(interpreter.variables["r"], Value::Number(8.0));
+    }
+
+    #[test]
+    fn test_interpreter_diff() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "f(x) = x^3 - 2*x\nd = diff(f(t), t)\na = d(2)\nb = f'(2)\nc = f''(1)\ng(x) = sin(f(x))\nh = g'(0)",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables["d"].to_string(), "3*t^2 - 2");
+        assert_eq!(interpreter.variables["a"], Value::Number(10.0));
+        assert_eq!(interpreter.variables["b"], Value::Number(10.0));
+        assert_eq!(interpreter.variables["c"], Value::Number(6.0));
+        assert_eq!(interpreter.variables["h"], Value::Number(-2.0));
+    }
+
+    #[test]
+    fn test_interpreter_simplify() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "f(x) = (x + 1)^2 - 1\ng = expand(f)\nh = simplify(f' / 2)\nn = simplify(2 + 3)",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables["g"].to_string(), "x^2 + 2*x");
+        assert_eq!(interpreter.variables["h"].to_string(), "x + 1");
+        assert_eq!(interpreter.variables["n"], Value::Number(5.0));
+    }
+
+    #[test]
+    fn test_interpreter_solve() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = solve(x^2 - 2 = 0, x)\nb = solve(2*x + 1 = 4, x)\nc = solve(x^2 + 1, x)\nd = solve(cos(x) = x, x)\ne = solve(sin(x), x, [-4 .. 4])\nf = solve((x^2 - 1)^2, x, [-2 .. 2])\ng = solve(x, x, [0..1])",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        let root = 2f64.sqrt();
+        assert_eq!(
+            interpreter.variables["a"],
+            Value::List(vec![Value::Number(-root), Value::Number(root)])
+        );
+        assert_eq!(
+            interpreter.variables["b"],
+            Value::List(vec![Value::Number(1.5)])
+        );
+        assert_eq!(interpreter.variables["c"], Value::List(vec![]));
+        assert_eq!(
+            interpreter.variables["d"].to_string(),
+            "[0.7390851332151607]"
+        );
+        assert_eq!(
+            interpreter.variables["e"].to_string(),
+            "[-3.141592653589793, 0, 3.141592653589793]"
+        );
+        assert_eq!(interpreter.variables["f"].to_string(), "[-1, 1]");
+        assert_eq!(interpreter.variables["g"].to_string(), "[0]");
+    }
+
+    #[test]
+    fn test_interpreter_integrate() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = integrate(x^2, x, 0, 3)\nf(t) = exp(-t^2)\nb = integrate(f, t, -10, 10)^2\nc = sum(i^2, i, 1, 10)\nd = prod(k, k, 1, 5)\ne = sum(sum(i*j, j, 1, i), i, 1, 3)\nz = sum(i, i, 5, 1)\ni",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(
+            interpreter.interpret(),
+            Err("Undefined variable: i".to_string())
+        );
+        assert!((interpreter.variables["a"].to_f64() - 9.0).abs() < 1e-12);
+        assert!((interpreter.variables["b"].to_f64() - PI).abs() < 1e-12);
+        assert_eq!(interpreter.variables["c"], Value::Number(385.0));
+        assert_eq!(interpreter.variables["d"], Value::Number(120.0));
+        assert_eq!(interpreter.variables["e"], Value::Number(25.0));
+        assert_eq!(interpreter.variables["z"], Value::Number(0.0));
+    }
+
+    #[test]
+    fn test_interpreter_ode() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = ode(dy/dt = y, 1, 0, 1, 0.01)\nb = ode(dy/dt = -2*t*y, 1, 0, 2)\nc = ode([dx/dt, dv/dt] = [v, -x], [1, 0], 0, 2*pi)\nd = ode(y = 1, 1, 0, 1)",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(
+            interpreter.interpret(),
+            Err("ode expects an equation such as dy/dt = f(t, y)".to_string())
+        );
+        let last = |name: &str| match &interpreter.variables[name] {
+            Value::List(rows) => match rows.last() {
+                Some(Value::List(row)) => row.iter().map(Value::to_f64).collect::<Vec<_>>(),
+                row => panic!("unexpected row {:?}", row),
+            },
+            value => panic!("unexpected value {:?}", value),
+        };
+        let a = last("a");
+        assert_eq!(a[0], 1.0);
+        assert!((a[1] - std::f64::consts::E).abs() < 1e-9);
+        let b = last("b");
+        assert!((b[1] - (-4.0f64).exp()).abs() < 1e-7);
+        let c = last("c");
+        assert!((c[0] - 2.0 * PI).abs() < 1e-12);
+        assert!((c[1] - 1.0).abs() < 1e-6 && c[2].abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_interpreter_index_named_like_unit() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = integrate(2t, t, 0, 1)\nb = diff(3h^2, h)\nc = sum(2s, s, 1, 3)\nd = solve(2m - 4, m)\ne = ode(dy/dt = 2t, 0, 0, 1, 0.5)\nf = 2 t",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(interpreter.interpret(), Ok(()));
+        assert!((interpreter.variables["a"].to_f64() - 1.0).abs() < 1e-12);
+        assert_eq!(interpreter.variables["b"].to_string(), "6*h");
+        assert_eq!(interpreter.variables["c"], Value::Number(12.0));
+        assert_eq!(interpreter.variables["d"].to_string(), "[2]");
+        assert_eq!(
+            interpreter.variables["e"].to_string(),
+            "[[0, 0], [0.5, 0.25], [1, 0.9999999999999999]]"
+        );
+        assert_eq!(interpreter.variables["f"].to_string(), "2 t");
+    }
+
+    #[test]
+    fn test_interpreter_number_theory() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = factor(1000000016000000063)\nb = isprime(2305843009213693951)\nc = nCr(60, 30) + 1",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(interpreter.interpret(), Ok(()));
+        assert_eq!(
+            interpreter.variables["a"].to_string(),
+            "[1000000007, 1000000009]"
+        );
+        assert_eq!(interpreter.variables["b"], Value::Number(1.0));
+        assert_eq!(interpreter.variables["c"].to_string(), "118264581564861425");
+
+        // long literals stay exact integers, but inexact results are floats
+        let mut lexer = Lexer::new(
+            "n = 100000000000000000000000
+a = n / 3
+b = sqrt(n + 1)
+c = n / 4 + 1",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(interpreter.interpret(), Ok(()));
+        assert_eq!(interpreter.variables["a"], Value::Number(1e23 / 3.0));
+        assert_eq!(interpreter.variables["b"].to_string(), "316227766016.83795");
+        assert_eq!(
+            interpreter.variables["c"].to_string(),
+            "25000000000000000000001"
+        );
+    }
+
+    #[test]
+    fn test_interpreter_random() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let source = "seed(7)\na = rand()\nb = randint(1, 6)\nc = normal(10, 0)\nd = shuffle([1, 2, 3])\nseed(7)\ne = rand()";
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(interpreter.interpret(), Ok(()));
+        let a = interpreter.variables["a"].to_f64();
+        assert!((0.0..1.0).contains(&a));
+        assert_eq!(interpreter.variables["e"].to_f64(), a);
+        assert!([1.0, 2.0, 3.0, 4.0, 5.0, 6.0].contains(&interpreter.variables["b"].to_f64()));
+        assert_eq!(interpreter.variables["c"], Value::Number(10.0));
+        let Value::List(mut d) = interpreter.variables["d"].clone() else {
+            panic!("shuffle should return a list");
+        };
+        d.sort_by(|a, b| a.to_f64().total_cmp(&b.to_f64()));
+        assert_eq!(
+            d.iter().map(Value::to_f64).collect::<Vec<_>>(),
+            [1.0, 2.0, 3.0]
+        );
+
+        let mut lexer = Lexer::new("randint(-10000000000000000000, 10000000000000000000)");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(
+            interpreter.interpret(),
+            Err(
+                "randint range from -10000000000000000000 to 10000000000000000000 is too large"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_interpreter_angle() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new(
+            "a = sin(90°)\n#angle deg\nb = sin(30)\nc = cos(180)\nd = asin(1)\ne = 45° + deg(pi)\n#angle grad\nf = acos(0)\ntan(100)",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(
+            interpreter.interpret(),
+            Err("tan is undefined at 100".to_string())
+        );
+        assert_eq!(interpreter.variables["a"], Value::Number(1.0));
+        assert!((interpreter.variables["b"].to_f64() - 0.5).abs() < 1e-15);
+        assert_eq!(interpreter.variables["c"], Value::Number(-1.0));
+        assert_eq!(interpreter.variables["d"], Value::Number(90.0));
+        assert_eq!(interpreter.variables["e"], Value::Number(225.0));
+        assert_eq!(interpreter.variables["f"], Value::Number(100.0));
+
+        let mut lexer = Lexer::new(
+            "#angle deg\nf(x) = sin(x)\na = f'(0)\ng = diff(asin(x), x)\nb = g(0)\nc = integrate(cos(x), x, 0, 90)",
+        );
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        assert_eq!(interpreter.interpret(), Ok(()));
+        assert_eq!(interpreter.variables["a"], Value::Number(PI / 180.0));
+        assert_eq!(interpreter.variables["b"], Value::Number(180.0 / PI));
+        assert!((interpreter.variables["c"].to_f64() - 180.0 / PI).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_interpreter_compound_assignment() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer =
+            Lexer::new("a, b = 0, 1\na, b = b, a + b\na, b = b, a + b\na += 10\nb ^= 3\nb /= 4");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.interpret().unwrap();
+        assert_eq!(interpreter.variables["a"], Value::Number(11.0));
+        assert_eq!(interpreter.variables["b"], Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_interpreter_echo() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        let mut lexer = Lexer::new("");
+        let mut parser = Parser::new(&mut lexer);
+        let mut interpreter = Interpreter::new(&mut parser);
+        interpreter.echo = true;
+        for line in ["2 + 3", "x = ans * 2", "x + _", "a = $1 + $2", "$2"] {
+            let mut lexer = Lexer::new(line);
+            let mut parser = Parser::new(&mut lexer);
+            interpreter.execute(parser.parse().unwrap()).unwrap();
+        }
+        assert_eq!(interpreter.variables["a"], Value::Number(20.0));
+        assert_eq!(interpreter.variables["ans"], Value::Number(15.0));
+        assert_eq!(interpreter.variables["$3"], Value::Number(15.0));
+        assert_eq!(
+            interpreter.execute(vec![Statement::Expression(Expression::Variable(
+                "$9".to_string()
+            ))]),
+            Err("Undefined variable: $9".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpreter_native() {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        struct Total;
+        impl NativeFunction for Total {
+            fn call(&self, arguments: Vec<Value>) -> Result<Value, String> {
+                Ok(Value::Number(arguments.iter().map(Value::to_f64).sum()))
+            }
+        }
+
+        let run = |source: &str, builtins: bool, compiled: bool| {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(&mut lexer);
+            let mut interpreter = Interpreter::new(&mut parser);
+            interpreter.builtins = builtins;
+            interpreter.register("sum", 2, Total);
+            interpreter.register_fn("tax_rate", 1, |arguments| match arguments[0].to_f64() {
+                year if year >= 2000.0 => Ok(Value::Number(0.2)),
+                year => Err(format!("no rate for {}", year)),
+            });
+            let result = match compiled {
+                true => interpreter.interpret_compiled(),
+                false => interpreter.interpret(),
+            };
+            result.map(|_| interpreter.variables.get("a").cloned())
+        };
+
+        for compiled in [false, true] {
+            assert_eq!(
+                run(
+                    "f(x) = 2 * x\na = f(sum(tax_rate(2024), 1))",
+                    true,
+                    compiled
+                ),
+                Ok(Some(Value::Number(2.4)))
+            );
+            assert_eq!(
+                run("a = tax_rate(1990)", true, compiled),
+                Err("tax_rate: no rate for 1990".to_string())
+            );
+            assert_eq!(
+                run("a = tax_rate(2024, 1)", true, compiled),
+                Err("tax_rate expects 1 argument(s), found 2".to_string())
+            );
+            assert_eq!(
+                run("a = sqrt(tax_rate(2024) * 5)", true, compiled),
+                Ok(Some(Value::Number(1.0)))
+            );
+            assert_eq!(
+                run("a = sqrt(tax_rate(2024) * 5)", false, compiled),
+                Err("Undefined function: sqrt".to_string())
+            );
+            assert_eq!(
+                run("a = prod(2, i, 1, 3)", false, compiled),
+                Err("Undefined variable: i".to_string())
+            );
+            assert_eq!(
+                run("f(x) = x + 1\na = f(tax_rate(2024))", false, compiled),
+                Ok(Some(Value::Number(1.2)))
+            );
+        }
     }
 }
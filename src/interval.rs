@@ -0,0 +1,578 @@
+use crate::numeric;
+use crate::value::Operator;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A closed interval of real numbers with `f64` bounds.
+///
+/// Every operation rounds its lower bound down and its upper bound up, so the
+/// result always encloses the exact result for every point in the operands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    pub fn new(lo: f64, hi: f64) -> Result<Self, String> {
+        if lo.is_nan() || hi.is_nan() || lo > hi {
+            return Err(format!("Invalid interval: [{} .. {}]", lo, hi));
+        }
+        Ok(Interval { lo, hi })
+    }
+
+    pub fn point(value: f64) -> Self {
+        Interval {
+            lo: value,
+            hi: value,
+        }
+    }
+
+    /// Builds `midpoint ± radius`.
+    pub fn from_midpoint(midpoint: &Interval, radius: f64) -> Result<Self, String> {
+        if radius.is_nan() || radius < 0.0 {
+            return Err(format!("Invalid interval radius: {}", radius));
+        }
+        Interval::new(sub_down(midpoint.lo, radius), add_up(midpoint.hi, radius))
+    }
+
+    /// Widens a value that is only known to the nearest `f64`.
+    pub fn around(value: f64) -> Self {
+        Interval {
+            lo: value.next_down(),
+            hi: value.next_up(),
+        }
+    }
+
+    /// Encloses the decimal number `value` is written as: just `value` if
+    /// the `f64` holds it exactly, as it does `0.5`, and otherwise widened
+    /// like `around`, since `0.1` is only the nearest `f64` to 0.1.
+    pub fn enclosing(value: f64) -> Self {
+        if !value.is_finite() {
+            return Interval::point(value);
+        }
+        // every finite f64 is a decimal of at most 767 significant digits
+        let exact = format!("{:.767e}", value);
+        let shortest = format!("{:e}", value);
+        let mantissa = |text: &str| text.split('e').next().unwrap_or_default().to_string();
+        let exact = mantissa(&exact);
+        match exact.trim_end_matches('0').trim_end_matches('.') == mantissa(&shortest) {
+            true => Interval::point(value),
+            false => Interval::around(value),
+        }
+    }
+
+    pub fn midpoint(&self) -> f64 {
+        self.lo / 2.0 + self.hi / 2.0
+    }
+
+    /// The distance from the midpoint to the furthest bound, rounded up.
+    pub fn radius(&self) -> f64 {
+        let midpoint = self.midpoint();
+        sub_up(self.hi, midpoint).max(sub_up(midpoint, self.lo))
+    }
+
+    pub fn contains_zero(&self) -> bool {
+        self.lo <= 0.0 && self.hi >= 0.0
+    }
+
+    pub fn apply(&self, operator: Operator, rhs: &Interval) -> Result<Self, String> {
+        match operator {
+            Operator::Add => Ok(*self + *rhs),
+            Operator::Subtract => Ok(*self - *rhs),
+            Operator::Multiply => Ok(*self * *rhs),
+            Operator::Divide => self
+                .checked_div(rhs)
+                .ok_or_else(|| "Division by an interval containing zero".to_owned()),
+            Operator::Power => self.pow(rhs),
+        }
+    }
+
+    pub fn checked_div(&self, rhs: &Interval) -> Option<Self> {
+        if rhs.contains_zero() {
+            return None;
+        }
+        let quotients = [
+            (self.lo, rhs.lo),
+            (self.lo, rhs.hi),
+            (self.hi, rhs.lo),
+            (self.hi, rhs.hi),
+        ];
+        Some(Interval {
+            lo: quotients
+                .iter()
+                .map(|&(a, b)| div_down(a, b))
+                .fold(f64::INFINITY, f64::min),
+            hi: quotients
+                .iter()
+                .map(|&(a, b)| div_up(a, b))
+                .fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+
+    pub fn pow(&self, exponent: &Interval) -> Result<Self, String> {
+        if exponent.lo == exponent.hi && exponent.lo.fract() == 0.0 && exponent.lo.abs() <= 1e9 {
+            return self.powi(exponent.lo as i64);
+        }
+        if self.lo <= 0.0 {
+            return Err(
+                "Fractional power of an interval containing non-positive numbers".to_owned(),
+            );
+        }
+        // x^y is monotone in both arguments for x > 0, so the extremes lie
+        // on the corners
+        let corners = [
+            self.lo.powf(exponent.lo),
+            self.lo.powf(exponent.hi),
+            self.hi.powf(exponent.lo),
+            self.hi.powf(exponent.hi),
+        ];
+        Ok(Interval {
+            lo: corners
+                .iter()
+                .copied()
+                .fold(f64::INFINITY, f64::min)
+                .next_down(),
+            hi: corners
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max)
+                .next_up(),
+        })
+    }
+
+    pub fn sqrt(&self) -> Result<Self, String> {
+        if self.lo < 0.0 {
+            return Err("sqrt of an interval containing negative numbers".to_owned());
+        }
+        Ok(Interval {
+            lo: sqrt_down(self.lo),
+            hi: sqrt_up(self.hi),
+        })
+    }
+
+    pub fn exp(&self) -> Self {
+        Interval {
+            lo: outward(self.lo, self.lo.exp(), false).max(0.0),
+            hi: outward(self.hi, self.hi.exp(), true),
+        }
+    }
+
+    pub fn ln(&self) -> Result<Self, String> {
+        if self.lo <= 0.0 {
+            return Err("ln of an interval containing non-positive numbers".to_owned());
+        }
+        Ok(Interval {
+            lo: outward(self.lo, self.lo.ln(), false),
+            hi: outward(self.hi, self.hi.ln(), true),
+        })
+    }
+
+    pub fn sin(&self) -> Self {
+        self.periodic(f64::sin, FRAC_PI_2)
+    }
+
+    pub fn cos(&self) -> Self {
+        self.periodic(f64::cos, 0.0)
+    }
+
+    pub fn tan(&self) -> Result<Self, String> {
+        if self.hi - self.lo >= PI || contains_phase(self, FRAC_PI_2, PI) {
+            return Err("tan is undefined on an interval containing an asymptote".to_owned());
+        }
+        Ok(Interval {
+            lo: outward(self.lo, self.lo.tan(), false),
+            hi: outward(self.hi, self.hi.tan(), true),
+        })
+    }
+
+    pub fn asin(&self) -> Result<Self, String> {
+        if self.lo < -1.0 || self.hi > 1.0 {
+            return Err("asin is undefined outside [-1, 1]".to_owned());
+        }
+        Ok(Interval {
+            lo: outward(self.lo, self.lo.asin(), false),
+            hi: outward(self.hi, self.hi.asin(), true),
+        })
+    }
+
+    pub fn acos(&self) -> Result<Self, String> {
+        if self.lo < -1.0 || self.hi > 1.0 {
+            return Err("acos is undefined outside [-1, 1]".to_owned());
+        }
+        // acos is decreasing, and only exact at 1, so `outward` (which also
+        // trusts the argument 0) does not apply
+        let (lo, hi) = (self.hi.acos(), self.lo.acos());
+        Ok(Interval {
+            lo: if self.hi == 1.0 { lo } else { lo.next_down() },
+            hi: if self.lo == 1.0 { hi } else { hi.next_up() },
+        })
+    }
+
+    pub fn atan(&self) -> Self {
+        Interval {
+            lo: outward(self.lo, self.lo.atan(), false),
+            hi: outward(self.hi, self.hi.atan(), true),
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        if self.lo >= 0.0 {
+            *self
+        } else if self.hi <= 0.0 {
+            -*self
+        } else {
+            Interval {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+            }
+        }
+    }
+
+    pub fn factorial(&self) -> Result<Self, String> {
+        if self.lo < 0.0 || self.lo.fract() != 0.0 || self.hi.fract() != 0.0 {
+            return Err("factorial of an interval requires non-negative integer bounds".to_owned());
+        }
+        // products up to 22! are exact in an f64
+        let factorial = |n: f64| -> Interval {
+            let product = numeric::factorial(n).unwrap_or(f64::INFINITY);
+            match n <= 22.0 {
+                true => Interval::point(product),
+                false => Interval::around(product),
+            }
+        };
+        Ok(Interval {
+            lo: factorial(self.lo).lo,
+            hi: factorial(self.hi).hi,
+        })
+    }
+
+    /// Formats the interval as `midpoint ± radius`.
+    pub fn to_midpoint_string(&self) -> String {
+        format!("{} ± {}", self.midpoint(), self.radius())
+    }
+
+    fn powi(&self, exponent: i64) -> Result<Self, String> {
+        if exponent < 0 {
+            return Interval::point(1.0)
+                .checked_div(&self.powi(-exponent)?)
+                .ok_or_else(|| "Division by an interval containing zero".to_owned());
+        }
+        let n = exponent as u64;
+        if n % 2 == 1 {
+            return Ok(Interval {
+                lo: powi_directed(self.lo, n, false),
+                hi: powi_directed(self.hi, n, true),
+            });
+        }
+        // even powers are monotone in the magnitude
+        let magnitude = self.abs();
+        Ok(Interval {
+            lo: powi_directed(magnitude.lo, n, false),
+            hi: powi_directed(magnitude.hi, n, true),
+        })
+    }
+
+    /// Encloses a function with period 2pi, a maximum of 1 at `peak` and a
+    /// minimum of -1 half a period later.
+    fn periodic(&self, function: fn(f64) -> f64, peak: f64) -> Self {
+        let width = self.hi - self.lo;
+        if width.is_nan() || width >= TAU || self.lo.abs().max(self.hi.abs()) > 1e9 {
+            return Interval { lo: -1.0, hi: 1.0 };
+        }
+        let (a, b) = (function(self.lo), function(self.hi));
+        let mut lo = outward(self.lo, a, false).min(outward(self.hi, b, false));
+        let mut hi = outward(self.lo, a, true).max(outward(self.hi, b, true));
+        if contains_phase(self, peak, TAU) {
+            hi = 1.0;
+        }
+        if contains_phase(self, peak + PI, TAU) {
+            lo = -1.0;
+        }
+        Interval {
+            lo: lo.max(-1.0),
+            hi: hi.min(1.0),
+        }
+    }
+}
+
+/// Rounds a library function result outward by one ulp, except at the
+/// arguments where it is exact (sin 0, cos 0, tan 0, exp 0 and ln 1).
+fn outward(argument: f64, result: f64, up: bool) -> f64 {
+    if argument == 0.0 || (argument == 1.0 && result == 0.0) {
+        return result;
+    }
+    match up {
+        true => result.next_up(),
+        false => result.next_down(),
+    }
+}
+
+/// Whether the interval may contain `phase + k * period` for some integer k.
+/// Rounding errors are resolved towards "yes", which only widens results.
+fn contains_phase(interval: &Interval, phase: f64, period: f64) -> bool {
+    let tolerance = 1e-12 * interval.lo.abs().max(interval.hi.abs()).max(1.0);
+    let k = ((interval.lo - phase) / period).ceil();
+    [k - 1.0, k].iter().any(|k| {
+        let point = phase + k * period;
+        point >= interval.lo - tolerance && point <= interval.hi + tolerance
+    })
+}
+
+/// Computes x^n by repeated squaring, rounding the magnitude so the signed
+/// result moves in the requested direction.
+fn powi_directed(x: f64, mut n: u64, up: bool) -> f64 {
+    let negative = x < 0.0 && n % 2 == 1;
+    let multiply = match up != negative {
+        true => mul_up,
+        false => mul_down,
+    };
+    let mut base = x.abs();
+    let mut result = 1.0;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = multiply(result, base);
+        }
+        n >>= 1;
+        base = multiply(base, base);
+    }
+    if negative {
+        -result
+    } else {
+        result
+    }
+}
+
+/// The rounding error of `a + b`, exact by Knuth's two-sum.
+fn sum_error(a: f64, b: f64, sum: f64) -> f64 {
+    let virtual_b = sum - a;
+    (a - (sum - virtual_b)) + (b - virtual_b)
+}
+
+fn add_down(a: f64, b: f64) -> f64 {
+    let sum = a + b;
+    match sum.is_finite() && sum_error(a, b, sum) < 0.0 {
+        true => sum.next_down(),
+        false => sum,
+    }
+}
+
+fn add_up(a: f64, b: f64) -> f64 {
+    let sum = a + b;
+    match sum.is_finite() && sum_error(a, b, sum) > 0.0 {
+        true => sum.next_up(),
+        false => sum,
+    }
+}
+
+fn sub_down(a: f64, b: f64) -> f64 {
+    add_down(a, -b)
+}
+
+fn sub_up(a: f64, b: f64) -> f64 {
+    add_up(a, -b)
+}
+
+fn mul_down(a: f64, b: f64) -> f64 {
+    let product = a * b;
+    // the fused multiply-add yields the exact rounding error of the product
+    match product.is_finite() && a.mul_add(b, -product) < 0.0 {
+        true => product.next_down(),
+        false => product,
+    }
+}
+
+fn mul_up(a: f64, b: f64) -> f64 {
+    let product = a * b;
+    match product.is_finite() && a.mul_add(b, -product) > 0.0 {
+        true => product.next_up(),
+        false => product,
+    }
+}
+
+/// The sign of the exact quotient minus the rounded one.
+fn quotient_error(a: f64, b: f64, quotient: f64) -> f64 {
+    (-quotient).mul_add(b, a) * b.signum()
+}
+
+fn div_down(a: f64, b: f64) -> f64 {
+    let quotient = a / b;
+    match quotient.is_finite() && quotient_error(a, b, quotient) < 0.0 {
+        true => quotient.next_down(),
+        false => quotient,
+    }
+}
+
+fn div_up(a: f64, b: f64) -> f64 {
+    let quotient = a / b;
+    match quotient.is_finite() && quotient_error(a, b, quotient) > 0.0 {
+        true => quotient.next_up(),
+        false => quotient,
+    }
+}
+
+fn sqrt_down(x: f64) -> f64 {
+    let root = x.sqrt();
+    match (-root).mul_add(root, x) < 0.0 {
+        true => root.next_down(),
+        false => root,
+    }
+}
+
+fn sqrt_up(x: f64) -> f64 {
+    let root = x.sqrt();
+    match (-root).mul_add(root, x) > 0.0 {
+        true => root.next_up(),
+        false => root,
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, rhs: Interval) -> Interval {
+        Interval {
+            lo: add_down(self.lo, rhs.lo),
+            hi: add_up(self.hi, rhs.hi),
+        }
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, rhs: Interval) -> Interval {
+        Interval {
+            lo: sub_down(self.lo, rhs.hi),
+            hi: sub_up(self.hi, rhs.lo),
+        }
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, rhs: Interval) -> Interval {
+        let products = [
+            (self.lo, rhs.lo),
+            (self.lo, rhs.hi),
+            (self.hi, rhs.lo),
+            (self.hi, rhs.hi),
+        ];
+        Interval {
+            lo: products
+                .iter()
+                .map(|&(a, b)| mul_down(a, b))
+                .fold(f64::INFINITY, f64::min),
+            hi: products
+                .iter()
+                .map(|&(a, b)| mul_up(a, b))
+                .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{} .. {}]", self.lo, self.hi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enclosing() {
+        assert_eq!(Interval::enclosing(0.5), Interval::point(0.5));
+        assert_eq!(Interval::enclosing(-1.25e-3), Interval::around(-1.25e-3));
+        assert_eq!(Interval::enclosing(1e22), Interval::point(1e22));
+        assert_eq!(Interval::enclosing(1e23), Interval::around(1e23));
+        // 2^-30 prints in 16 digits, which are not exactly its value
+        assert_eq!(
+            Interval::enclosing(2f64.powi(-30)),
+            Interval::around(2f64.powi(-30))
+        );
+        assert_eq!(Interval::enclosing(0.1), Interval::around(0.1));
+        assert_eq!(
+            Interval::enclosing(f64::INFINITY),
+            Interval::point(f64::INFINITY)
+        );
+    }
+
+    fn interval(lo: f64, hi: f64) -> Interval {
+        Interval::new(lo, hi).unwrap()
+    }
+
+    #[test]
+    fn test_exact_arithmetic_is_not_widened() {
+        assert_eq!(interval(1.0, 2.0) + interval(1.0, 1.0), interval(2.0, 3.0));
+        assert_eq!(
+            interval(-1.0, 2.0) * interval(3.0, 4.0),
+            interval(-4.0, 8.0)
+        );
+        assert_eq!(interval(1.0, 2.0) - interval(1.0, 2.0), interval(-1.0, 1.0));
+    }
+
+    #[test]
+    fn test_outward_rounding() {
+        let third = Interval::point(1.0)
+            .checked_div(&Interval::point(3.0))
+            .unwrap();
+        assert!(third.lo < third.hi);
+        assert!(third.lo * 3.0 <= 1.0 && third.hi * 3.0 >= 1.0);
+
+        let sum = Interval::point(0.1) + Interval::point(0.2);
+        assert!(sum.lo <= 0.3 && 0.3 <= sum.hi);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(interval(1.0, 2.0).checked_div(&interval(-1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn test_powers() {
+        assert_eq!(
+            interval(-1.0, 2.0).pow(&Interval::point(2.0)).unwrap(),
+            interval(0.0, 4.0)
+        );
+        assert_eq!(
+            interval(-2.0, 1.0).pow(&Interval::point(3.0)).unwrap(),
+            interval(-8.0, 1.0)
+        );
+        assert!(interval(-1.0, 2.0).pow(&Interval::point(0.5)).is_err());
+    }
+
+    #[test]
+    fn test_trigonometry() {
+        let sin = interval(0.0, 2.0).sin();
+        assert_eq!(sin.hi, 1.0);
+        assert!(sin.lo <= 0.0);
+        let cos = interval(3.0, 3.5).cos();
+        assert_eq!(cos.lo, -1.0);
+        assert!(interval(1.0, 2.0).tan().is_err());
+        let acos = interval(0.0, 1.0).acos().unwrap();
+        assert_eq!(acos.lo, 0.0);
+        assert!(acos.hi > std::f64::consts::FRAC_PI_2);
+        assert!(interval(0.0, 2.0).asin().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(interval(1.5, 2.5).to_string(), "[1.5 .. 2.5]");
+        assert_eq!(interval(1.5, 2.5).to_midpoint_string(), "2 ± 0.5");
+    }
+}
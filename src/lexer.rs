@@ -5,17 +5,30 @@ use crate::enums::Token;
 
 pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
+    /// Whether the rest of the line is a format spec, as after `#format`.
+    format_next: bool,
+    /// A token already read, such as the exponent after a superscript's `^`.
+    pending: Option<Token>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Lexer {
             chars: input.chars().peekable(),
+            format_next: false,
+            pending: None,
         }
     }
 
     pub fn next_token(&mut self) -> Option<Token> {
         let chars = &mut self.chars;
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+        if self.format_next {
+            self.format_next = false;
+            return Some(Token::Format(read_format(chars)));
+        }
 
         loop {
             match chars.peek() {
@@ -32,56 +45,85 @@ impl<'a> Lexer<'a> {
                     chars.next();
                     continue;
                 }
-                Some(&c) if c.is_digit(10) => {
+                Some(&c) if c.is_ascii_digit() => {
                     let mut number = String::new();
                     while let Some(&c) = chars.peek() {
-                        if c.is_digit(10) || c == '.' {
+                        if c.is_ascii_digit() || (c == '.' && !is_range(chars)) {
                             number.push(c);
                             chars.next();
                         } else {
                             break;
                         }
                     }
-                    return Some(Token::Number(number.parse().unwrap()));
+                    // keep the source text of literals an f64 cannot hold exactly
+                    return match number.parse() {
+                        Ok(value) if significant_digits(&number) <= MAX_FLOAT_DIGITS => {
+                            Some(Token::Number(value))
+                        }
+                        _ => Some(Token::Literal(number)),
+                    };
                 }
-                Some(&'p') => {
+                Some(&'π') => {
                     chars.next();
-                    if let Some(&'r') = chars.peek() {
-                        chars.next();
-                        if let Some(&'i') = chars.peek() {
-                            chars.next();
-                            if let Some(&'n') = chars.peek() {
-                                chars.next();
-                                if let Some(&'t') = chars.peek() {
-                                    chars.next();
-                                    return Some(Token::Print);
-                                }
-                            }
-                        }
-                    }
-                    return None;
+                    return Some(Token::Identifier("pi".to_string()));
                 }
-                Some(&c) if c.is_alphabetic() => {
-                    let mut identifier = String::new();
-                    while let Some(&c) = chars.peek() {
-                        if c.is_alphanumeric() {
-                            identifier.push(c);
-                            chars.next();
-                        } else {
+                Some(&c) if superscript(c).is_some() => {
+                    // `x²` is `x^2`, and `x⁻¹` is `x^-1`
+                    let mut exponent = String::new();
+                    while let Some(digit) = chars.peek().and_then(|&c| superscript(c)) {
+                        if digit == '-' && !exponent.is_empty() {
                             break;
                         }
+                        exponent.push(digit);
+                        chars.next();
+                    }
+                    return match exponent.parse() {
+                        Ok(exponent) => {
+                            self.pending = Some(Token::Number(exponent));
+                            Some(Token::Power)
+                        }
+                        Err(_) => Some(Token::Char('⁻')),
+                    };
+                }
+                Some(&c) if is_identifier_start(c) => {
+                    let mut identifier = read_identifier(chars);
+                    // primes name derivatives, as in `f'(x)`
+                    while chars.peek() == Some(&'\'') {
+                        identifier.push('\'');
+                        chars.next();
+                    }
+                    if identifier == "print" {
+                        return Some(Token::Print);
                     }
                     return Some(Token::Identifier(identifier));
                 }
+                Some(&'$') => {
+                    // `$3` names the third value the REPL echoed
+                    chars.next();
+                    let mut name = String::from("$");
+                    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                        name.push(digit);
+                    }
+                    return Some(match name.len() {
+                        1 => Token::Char('$'),
+                        _ => Token::Identifier(name),
+                    });
+                }
+                Some(&'#') => {
+                    chars.next();
+                    let name = read_identifier(chars);
+                    self.format_next = name == "format";
+                    return Some(Token::Pragma(name));
+                }
                 Some(&'+') => {
                     chars.next();
                     return Some(Token::Plus);
                 }
-                Some(&'-') => {
+                Some(&'-' | &'−') => {
                     chars.next();
                     return Some(Token::Minus);
                 }
-                Some(&'*') => {
+                Some(&'*' | &'×') => {
                     chars.next();
                     return Some(Token::Star);
                 }
@@ -89,7 +131,7 @@ impl<'a> Lexer<'a> {
                     chars.next();
                     return Some(Token::Power);
                 }
-                Some(&'/') => {
+                Some(&'/' | &'÷') => {
                     chars.next();
                     return Some(Token::Slash);
                 }
@@ -109,12 +151,138 @@ impl<'a> Lexer<'a> {
                     chars.next();
                     return Some(Token::Factorial);
                 }
-                _ => return None,
+                Some(&',') => {
+                    chars.next();
+                    return Some(Token::Comma);
+                }
+                Some(&'[') => {
+                    chars.next();
+                    return Some(Token::LBracket);
+                }
+                Some(&']') => {
+                    chars.next();
+                    return Some(Token::RBracket);
+                }
+                Some(&'.') => {
+                    chars.next();
+                    if let Some(&'.') = chars.peek() {
+                        chars.next();
+                        return Some(Token::Range);
+                    }
+                    return Some(Token::Dot);
+                }
+                Some(&':') => {
+                    chars.next();
+                    return Some(Token::Format(read_format(chars)));
+                }
+                Some(&'"') => {
+                    chars.next();
+                    return Some(match read_string(chars) {
+                        Some(text) => Token::String(text),
+                        None => Token::Char('"'),
+                    });
+                }
+                Some(&'√') => {
+                    chars.next();
+                    return Some(Token::Sqrt);
+                }
+                Some(&'°') => {
+                    chars.next();
+                    return Some(Token::Degree);
+                }
+                Some(&'±') => {
+                    chars.next();
+                    return Some(Token::PlusMinus);
+                }
+                Some(&c) => {
+                    chars.next();
+                    return Some(Token::Char(c));
+                }
             }
         }
     }
 }
 
+/// The number of significant decimal digits an `f64` always round-trips.
+const MAX_FLOAT_DIGITS: usize = 15;
+
+fn significant_digits(number: &str) -> usize {
+    number
+        .trim_start_matches(['0', '.'])
+        .chars()
+        .filter(char::is_ascii_digit)
+        .count()
+}
+
+/// Whether the next two characters are the `..` of an interval.
+fn is_range(chars: &Peekable<Chars>) -> bool {
+    let mut ahead = chars.clone();
+    ahead.next() == Some('.') && ahead.next() == Some('.')
+}
+
+/// Reads a format spec such as `.2f`, which ends at whitespace or `;`.
+fn read_format(chars: &mut Peekable<Chars>) -> String {
+    while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+    let mut spec = String::new();
+    // a comma after the first character separates `print` items, as in `x : .2f, " m"`
+    while let Some(c) = chars.next_if(|&c| {
+        !c.is_whitespace() && !matches!(c, ';' | '"' | '}' | ')') && (c != ',' || spec.is_empty())
+    }) {
+        spec.push(c);
+    }
+    spec
+}
+
+/// Reads the source text of a string literal after its opening quote, up to
+/// the closing quote, leaving escapes and interpolations for the parser.
+/// Returns `None` if the line ends first.
+fn read_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut text = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(text),
+            '\n' => return None,
+            '\\' => {
+                text.push('\\');
+                text.push(chars.next_if(|&c| c != '\n')?);
+            }
+            c => text.push(c),
+        }
+    }
+}
+
+/// Whether `c` can start a name: letters in any script and `_`, except `π`,
+/// which is always the constant.
+fn is_identifier_start(c: char) -> bool {
+    (c.is_alphabetic() || c == '_') && c != 'π'
+}
+
+/// The ASCII digit or sign that a superscript character stands for.
+fn superscript(c: char) -> Option<char> {
+    match c {
+        '⁰' => Some('0'),
+        '¹' => Some('1'),
+        '²' => Some('2'),
+        '³' => Some('3'),
+        '⁴'..='⁹' => char::from_digit(c as u32 - '⁰' as u32, 10),
+        '⁻' => Some('-'),
+        _ => None,
+    }
+}
+
+fn read_identifier(chars: &mut Peekable<Chars>) -> String {
+    let mut identifier = String::new();
+    while let Some(&c) = chars.peek() {
+        if is_identifier_start(c) || c.is_numeric() && superscript(c).is_none() {
+            identifier.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    identifier
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,6 +351,56 @@ mod tests {
         assert_eq!(lexer.next_token(), None);
     }
 
+    #[test]
+    fn test_next_token_with_call_and_pragma() {
+        let mut lexer = Lexer::new("#precision 50\nprint sqrt(pi, 2)");
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Pragma("precision".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::Number(50.0)));
+        assert_eq!(lexer.next_token(), Some(Token::End));
+        assert_eq!(lexer.next_token(), Some(Token::Print));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Identifier("sqrt".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::LParen));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Identifier("pi".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::Comma));
+        assert_eq!(lexer.next_token(), Some(Token::Number(2.0)));
+        assert_eq!(lexer.next_token(), Some(Token::RParen));
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn test_next_token_with_long_literal() {
+        let mut lexer = Lexer::new("0.1 3.14159265358979323846");
+        assert_eq!(lexer.next_token(), Some(Token::Number(0.1)));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Literal("3.14159265358979323846".to_string()))
+        );
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn test_next_token_with_interval() {
+        let mut lexer = Lexer::new("[1..2.5] 2 ± 0.1");
+        assert_eq!(lexer.next_token(), Some(Token::LBracket));
+        assert_eq!(lexer.next_token(), Some(Token::Number(1.0)));
+        assert_eq!(lexer.next_token(), Some(Token::Range));
+        assert_eq!(lexer.next_token(), Some(Token::Number(2.5)));
+        assert_eq!(lexer.next_token(), Some(Token::RBracket));
+        assert_eq!(lexer.next_token(), Some(Token::Number(2.0)));
+        assert_eq!(lexer.next_token(), Some(Token::PlusMinus));
+        assert_eq!(lexer.next_token(), Some(Token::Number(0.1)));
+        assert_eq!(lexer.next_token(), None);
+    }
+
     #[test]
     fn test_next_token_with_parentheses() {
         let mut lexer = Lexer::new("(1 + 2) - (3 * 4) / 5");
@@ -201,4 +419,57 @@ mod tests {
         assert_eq!(lexer.next_token(), Some(Token::Number(5.0)));
         assert_eq!(lexer.next_token(), None);
     }
+
+    #[test]
+    fn test_prime() {
+        let mut lexer = Lexer::new("f''(x)");
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Identifier("f''".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::LParen));
+        assert_eq!(lexer.next_token(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(lexer.next_token(), Some(Token::RParen));
+    }
+
+    #[test]
+    fn test_string() {
+        let mut lexer = Lexer::new(r#"print "x = {x}\"", x : ,.2f, "" "open"#);
+        assert_eq!(lexer.next_token(), Some(Token::Print));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::String(r#"x = {x}\""#.to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::Comma));
+        assert_eq!(lexer.next_token(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(lexer.next_token(), Some(Token::Format(",.2f".to_string())));
+        assert_eq!(lexer.next_token(), Some(Token::Comma));
+        assert_eq!(lexer.next_token(), Some(Token::String(String::new())));
+        assert_eq!(lexer.next_token(), Some(Token::Char('"')));
+    }
+
+    #[test]
+    fn test_unicode() {
+        let mut lexer = Lexer::new("2π×r_1²÷√θ − x⁻¹");
+        assert_eq!(lexer.next_token(), Some(Token::Number(2.0)));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Identifier("pi".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::Star));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Identifier("r_1".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Some(Token::Power));
+        assert_eq!(lexer.next_token(), Some(Token::Number(2.0)));
+        assert_eq!(lexer.next_token(), Some(Token::Slash));
+        assert_eq!(lexer.next_token(), Some(Token::Sqrt));
+        assert_eq!(lexer.next_token(), Some(Token::Identifier("θ".to_string())));
+        assert_eq!(lexer.next_token(), Some(Token::Minus));
+        assert_eq!(lexer.next_token(), Some(Token::Identifier("x".to_string())));
+        assert_eq!(lexer.next_token(), Some(Token::Power));
+        assert_eq!(lexer.next_token(), Some(Token::Number(-1.0)));
+        assert_eq!(lexer.next_token(), None);
+    }
 }
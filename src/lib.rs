@@ -0,0 +1,24 @@
+pub mod bigint;
+pub mod binary;
+pub mod builtins;
+pub mod bytecode;
+pub mod codegen;
+pub mod compiled;
+pub mod decimal;
+pub mod enums;
+pub mod format;
+pub mod interpreter;
+pub mod interval;
+pub mod lexer;
+pub mod native;
+pub mod number_theory;
+pub mod numeric;
+pub mod optimizer;
+pub mod parser;
+pub mod random;
+pub mod statistics;
+pub mod symbolic;
+pub mod transpile;
+pub mod units;
+pub mod value;
+pub mod vm;
@@ -1,63 +1,319 @@
-pub mod enums;
-pub mod interpreter;
-pub mod lexer;
-pub mod parser;
-
-use crate::interpreter::Interpreter;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
+use rustic::builtins::Angle;
+use rustic::interpreter::Interpreter;
+use rustic::lexer::Lexer;
+use rustic::parser::Parser;
+use rustic::transpile::Target;
+use rustic::{binary, bytecode, codegen, optimizer, transpile, vm};
 use std::io::{stdin, stdout, Write};
 use std::path::Path;
 
+/// Command line options shared by file execution and the REPL.
+#[derive(Default)]
+struct Options {
+    repl: bool,
+    precision: Option<usize>,
+    seed: Option<u64>,
+    angle: Angle,
+    opt_level: Option<u8>,
+    dump_ast: bool,
+    vm: bool,
+    /// `rustic compile`, which writes bytecode to `output` instead of running.
+    compile: bool,
+    /// `rustic build`, which writes a native executable to `output` instead.
+    build: bool,
+    /// `rustic transpile`, which prints the program in another language.
+    transpile: Option<Target>,
+    output: Option<String>,
+    source_file: Option<String>,
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
 
-    // help command
-    if (args.len() != 2) || (args[1] == "-h") || (args[1] == "--help") {
-        println!("Usage: rustic <source_file>");
-        println!("Options:");
-        println!("  -h, --help\t\tShow this help message");
-        println!("  -r, --repl\t\tStart the Rustic REPL");
+    let options = match parse_options(&args[1..]) {
+        Some(options) => options,
+        None => {
+            // help command
+            println!("Usage: rustic [options] <source_file>");
+            println!("       rustic compile [options] <source_file> [-o <output>]");
+            println!("       rustic build [options] <source_file> [-o <output>]");
+            println!(
+                "       rustic transpile --target <rust|python|js> <source_file> [-o <output>]"
+            );
+            println!("Options:");
+            println!("  -h, --help\t\tShow this help message");
+            println!("  -r, --repl\t\tStart the Rustic REPL");
+            println!("  --precision <digits>\tUse decimal arithmetic with the given precision");
+            println!("  --seed <n>\t\tSeed the random number generator");
+            println!("  --angle <unit>\tMeasure angles in rad, deg or grad");
+            println!("  --opt-level <n>\tOptimize the program at level 0, 1 or 2");
+            println!("  --dump-ast\t\tPrint the optimized program instead of running it");
+            println!("  --vm\t\t\tRun on the bytecode virtual machine");
+            println!("  -o <output>\t\tWhere compile, build or transpile write their output");
+            return;
+        }
+    };
+
+    // repl command
+    if options.repl {
+        repl(&options);
         return;
     }
 
-    // repl command
-    if (args[1] == "-r") || (args[1] == "--repl") {
-        println!("Rustic REPL");
-        println!("Type 'exit' to exit or enter Ctrl+C\n");
-        let mut input = String::new();
-        loop {
-            print!(">>> ");
-            stdout().flush().unwrap();
-            stdin().read_line(&mut input).expect("Error reading input");
-            if input.trim().split('\n').last() == Some("exit") {
-                println!("Exiting the REPL");
-                break;
+    let source_file = options.source_file.as_deref().unwrap_or_default();
+    let path = Path::new(source_file);
+    let source = std::fs::read(path).expect("Error reading source file");
+
+    // compiled files are recognized by their magic bytes, not their extension
+    if source.starts_with(binary::MAGIC) {
+        run_compiled(&source, &options);
+        return;
+    }
+    let source_code = String::from_utf8(source).expect("Source file is not valid UTF-8");
+    if options.compile {
+        compile(&source_code, path, &options);
+        return;
+    }
+    if options.build {
+        build(&source_code, path, &options);
+        return;
+    }
+    if let Some(target) = options.transpile {
+        transpile(&source_code, target, &options);
+        return;
+    }
+
+    // run the program
+    run(&source_code, &options);
+}
+
+/// Parses the command line, returning `None` when help should be shown.
+fn parse_options(args: &[String]) -> Option<Options> {
+    let mut options = Options::default();
+    let mut args = args.iter().peekable();
+    let command = args.next_if(|arg| ["compile", "build", "transpile"].contains(&arg.as_str()));
+    match command.map(String::as_str) {
+        Some("compile") => options.compile = true,
+        Some("build") => options.build = true,
+        Some(_) => {
+            // the target is required, so it is read before any other option
+            let ("--target", Some(target)) = (args.next()?.as_str(), args.next()) else {
+                return None;
+            };
+            options.transpile = Some(Target::parse(target)?);
+        }
+        None => {}
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return None,
+            "-r" | "--repl" => options.repl = true,
+            "--precision" => options.precision = Some(args.next()?.parse().ok()?),
+            "--seed" => options.seed = Some(args.next()?.parse().ok()?),
+            "--angle" => options.angle = Angle::parse(args.next()?)?,
+            "--opt-level" => {
+                options.opt_level = Some(args.next()?.parse().ok().filter(|&level| level <= 2)?)
             }
-            // if the input is empty, continue
-            if input.trim().is_empty() {
-                continue;
+            "--dump-ast" => options.dump_ast = true,
+            "--vm" => options.vm = true,
+            "-o" if options.compile || options.build || options.transpile.is_some() => {
+                options.output = Some(args.next()?.clone())
             }
-            run(&format!("{}\n", input));
+            _ if arg.starts_with('-') || options.source_file.is_some() => return None,
+            _ => options.source_file = Some(arg.clone()),
         }
+    }
+
+    if !options.repl && options.source_file.is_none() {
+        return None;
+    }
+    Some(options)
+}
+
+/// Reads and runs one line at a time in a single interpreter, so that each
+/// line sees the variables and settings left by the ones before it.
+fn repl(options: &Options) {
+    println!("Rustic REPL");
+    println!("Type 'exit' to exit or enter Ctrl+C\n");
+    let mut session = Lexer::new("");
+    let mut session = Parser::new(&mut session);
+    let mut interpreter = Interpreter::new(&mut session);
+    interpreter.echo = true;
+    if let Err(e) = configure(&mut interpreter, options) {
+        println!("Error: {}", e);
         return;
     }
+    // `#implicit` changes how later lines parse, so it outlives each parser,
+    // as do the variables, which decide whether `2t` is in tonnes
+    let mut tight_implicit = true;
+    loop {
+        print!(">>> ");
+        stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin().read_line(&mut line).expect("Error reading input") == 0 {
+            break;
+        }
+        // commands such as `:angle deg` change the session, not the program
+        if let Some(command) = line.trim().strip_prefix(':') {
+            repl_command(command, &mut interpreter);
+            continue;
+        }
+        if line.trim() == "exit" {
+            println!("Exiting the REPL");
+            break;
+        }
 
-    let source_file = &args[1];
-    let path = Path::new(source_file);
-    let source_code = std::fs::read_to_string(path).expect("Error reading source file");
+        let mut lexer = Lexer::new(&line);
+        let mut parser = Parser::new(&mut lexer);
+        parser.tight_implicit = tight_implicit;
+        parser.variables = interpreter.variables.keys().cloned().collect();
+        let statements = parser.parse();
+        tight_implicit = parser.tight_implicit;
+        let statements =
+            statements.map(|statements| optimizer::optimize(statements, interpreter.opt_level));
+        if let Err(e) = statements.and_then(|statements| interpreter.execute(statements)) {
+            println!("Error: {}", e);
+        }
+    }
+}
 
-    // run the program
-    run(&source_code);
+/// Runs a REPL command, given without its leading `:`.
+fn repl_command(command: &str, interpreter: &mut Interpreter) {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words[..] {
+        ["angle"] => println!("{}", interpreter.angle),
+        ["angle", name] => match Angle::parse(name) {
+            Some(angle) => interpreter.angle = angle,
+            None => println!("Error: :angle expects 'rad', 'deg' or 'grad'"),
+        },
+        _ => println!("Error: Unknown command: :{}", command),
+    }
 }
 
-fn run(source_code: &str) {
-    let mut lexer = Lexer::new(&source_code);
+fn run(source_code: &str, options: &Options) {
+    if options.dump_ast {
+        let mut lexer = Lexer::new(source_code);
+        let mut parser = Parser::new(&mut lexer);
+        let level = options.opt_level.unwrap_or(optimizer::DEFAULT_LEVEL);
+        match parser.parse() {
+            Ok(statements) => {
+                for statement in optimizer::optimize(statements, level) {
+                    println!("{:?}", statement);
+                }
+            }
+            Err(e) => println!("Error: {}", e),
+        }
+        return;
+    }
+
+    let mut lexer = Lexer::new(source_code);
     let mut parser = Parser::new(&mut lexer);
     let mut interpreter = Interpreter::new(&mut parser);
-
-    match interpreter.interpret() {
+    let result = configure(&mut interpreter, options).and_then(|_| match options.vm {
+        true => interpreter.interpret_compiled(),
+        false => interpreter.interpret(),
+    });
+    match result {
         Ok(_) => {}
         Err(e) => println!("Error: {}", e),
     }
 }
+
+/// Compiles a program to bytecode and writes it next to the source, or to
+/// the `-o` path.
+fn compile(source_code: &str, path: &Path, options: &Options) {
+    let mut lexer = Lexer::new(source_code);
+    let mut parser = Parser::new(&mut lexer);
+    let level = options.opt_level.unwrap_or(optimizer::DEFAULT_LEVEL);
+    let program = parser
+        .parse()
+        .and_then(|statements| bytecode::compile(&optimizer::optimize(statements, level)));
+    match program {
+        Ok(program) => {
+            let output = match &options.output {
+                Some(output) => Path::new(output).to_path_buf(),
+                None => path.with_extension("rtcb"),
+            };
+            std::fs::write(output, binary::encode(&program)).expect("Error writing compiled file");
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Translates a program to C and compiles it with the system C compiler
+/// into an executable named after the source, or at the `-o` path.
+fn build(source_code: &str, path: &Path, options: &Options) {
+    if options.precision.is_some() || options.angle != Angle::Radians {
+        println!("Error: build::--precision and --angle cannot be built");
+        return;
+    }
+    let mut lexer = Lexer::new(source_code);
+    let mut parser = Parser::new(&mut lexer);
+    let level = options.opt_level.unwrap_or(optimizer::DEFAULT_LEVEL);
+    let result = parser
+        .parse()
+        .and_then(|statements| codegen::generate(&optimizer::optimize(statements, level)))
+        .and_then(|source| {
+            let output = match &options.output {
+                Some(output) => Path::new(output).to_path_buf(),
+                None => path.with_extension(""),
+            };
+            if output == path {
+                return Err("The executable would overwrite the source; name it with -o".to_owned());
+            }
+            codegen::build(&source, &output)
+        });
+    if let Err(e) = result {
+        println!("Error: {}", e);
+    }
+}
+
+/// Prints a program translated to another language, or writes it to the
+/// `-o` path.
+fn transpile(source_code: &str, target: Target, options: &Options) {
+    if options.precision.is_some() || options.angle != Angle::Radians {
+        println!("Error: transpile::--precision and --angle cannot be transpiled");
+        return;
+    }
+    let mut lexer = Lexer::new(source_code);
+    let mut parser = Parser::new(&mut lexer);
+    let level = options.opt_level.unwrap_or(optimizer::DEFAULT_LEVEL);
+    let source = parser.parse().and_then(|statements| {
+        transpile::transpile(&optimizer::optimize(statements, level), target)
+    });
+    match (source, &options.output) {
+        (Ok(source), Some(output)) => {
+            std::fs::write(output, source).expect("Error writing transpiled file")
+        }
+        (Ok(source), None) => print!("{}", source),
+        (Err(e), _) => println!("Error: {}", e),
+    }
+}
+
+fn run_compiled(bytes: &[u8], options: &Options) {
+    let mut lexer = Lexer::new("");
+    let mut parser = Parser::new(&mut lexer);
+    let mut interpreter = Interpreter::new(&mut parser);
+    let result = binary::decode(bytes).and_then(|program| {
+        configure(&mut interpreter, options)?;
+        vm::run(&mut interpreter, &program)
+    });
+    if let Err(e) = result {
+        println!("Error: {}", e);
+    }
+}
+
+/// Applies the command line options to a new interpreter.
+fn configure(interpreter: &mut Interpreter, options: &Options) -> Result<(), String> {
+    interpreter.angle = options.angle;
+    if let Some(level) = options.opt_level {
+        interpreter.opt_level = level;
+    }
+    if let Some(seed) = options.seed {
+        interpreter.set_seed(seed);
+    }
+    match options.precision {
+        Some(precision) => interpreter.set_precision(precision),
+        None => Ok(()),
+    }
+}
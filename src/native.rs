@@ -0,0 +1,40 @@
+use crate::value::Value;
+
+/// A function implemented in Rust that programs can call like a built-in,
+/// registered with `Interpreter::register` or `Interpreter::register_fn`.
+pub trait NativeFunction {
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, String>;
+}
+
+impl<F> NativeFunction for F
+where
+    F: Fn(Vec<Value>) -> Result<Value, String>,
+{
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, String> {
+        self(arguments)
+    }
+}
+
+/// A registered native function and the number of arguments it takes.
+pub struct Native {
+    pub arity: usize,
+    pub function: Box<dyn NativeFunction>,
+}
+
+impl Native {
+    /// Checks the argument count and calls the function, reporting its
+    /// errors as errors of the call to `name`.
+    pub fn call(&self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        if arguments.len() != self.arity {
+            return Err(format!(
+                "{} expects {} argument(s), found {}",
+                name,
+                self.arity,
+                arguments.len()
+            ));
+        }
+        self.function
+            .call(arguments)
+            .map_err(|error| format!("{}: {}", name, error))
+    }
+}
@@ -0,0 +1,375 @@
+use crate::bigint::BigInt;
+
+/// The primes below 100, used both for trial division and as Miller-Rabin
+/// bases.
+const SMALL_PRIMES: [u32; 25] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+];
+
+/// The number of Miller-Rabin bases that make the test exact below 3.3e24.
+const DETERMINISTIC_BASES: usize = 13;
+
+/// Divisors up to this bound are found by trial division before falling
+/// back to Pollard's rho.
+const TRIAL_DIVISION_LIMIT: u32 = 1000;
+
+/// The greatest common divisor of `a` and `b`, which is never negative.
+pub fn gcd(a: &BigInt, b: &BigInt) -> BigInt {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while !b.is_zero() {
+        (a, b) = (b.clone(), remainder(&a, &b));
+    }
+    a
+}
+
+/// The least common multiple of `a` and `b`, which is never negative.
+pub fn lcm(a: &BigInt, b: &BigInt) -> BigInt {
+    if a.is_zero() || b.is_zero() {
+        return BigInt::zero();
+    }
+    let (quotient, _) = a.abs().div_rem(&gcd(a, b)).unwrap_or_default();
+    &quotient * &b.abs()
+}
+
+/// Tests `n` for primality with the Miller-Rabin test, which is exact below
+/// 3.3e24 and wrong with negligible probability above.
+pub fn is_prime(n: &BigInt) -> bool {
+    if n < &BigInt::from(2) {
+        return false;
+    }
+    for &prime in &SMALL_PRIMES {
+        if n.rem_small(prime) == 0 {
+            return n == &BigInt::from(prime as i64);
+        }
+    }
+    if n < &BigInt::from(100 * 100) {
+        return true;
+    }
+
+    let one = BigInt::one();
+    let n_minus_one = n - &one;
+    let (mut odd, mut halvings) = (n_minus_one.clone(), 0);
+    while odd.is_even() {
+        odd = odd.div_small(2);
+        halvings += 1;
+    }
+    let bases = match n.digits() > 24 {
+        true => &SMALL_PRIMES[..],
+        false => &SMALL_PRIMES[..DETERMINISTIC_BASES],
+    };
+    'bases: for &base in bases {
+        let mut x = power(&BigInt::from(base as i64), &odd, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..halvings {
+            x = remainder(&(&x * &x), n);
+            if x == n_minus_one {
+                continue 'bases;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// The smallest prime greater than `n`.
+pub fn next_prime(n: &BigInt) -> BigInt {
+    let one = BigInt::one();
+    let mut candidate = match n < &BigInt::from(2) {
+        true => return BigInt::from(2),
+        false => n + &one,
+    };
+    while !is_prime(&candidate) {
+        candidate = &candidate + &one;
+    }
+    candidate
+}
+
+/// The prime factors of a positive `n` in ascending order, repeated by
+/// multiplicity.
+pub fn factor(n: &BigInt) -> Result<Vec<BigInt>, String> {
+    if n.is_negative() || n.is_zero() {
+        return Err(format!("factor expects a positive integer, found {}", n));
+    }
+    let mut factors = vec![];
+    let mut rest = n.clone();
+    for divisor in 2..TRIAL_DIVISION_LIMIT {
+        while rest.rem_small(divisor) == 0 && rest != BigInt::one() {
+            factors.push(BigInt::from(divisor as i64));
+            rest = rest.div_small(divisor);
+        }
+    }
+
+    let mut composites = vec![rest];
+    while let Some(composite) = composites.pop() {
+        if composite == BigInt::one() {
+            continue;
+        }
+        if is_prime(&composite) {
+            factors.push(composite);
+            continue;
+        }
+        let divisor = pollard_rho(&composite);
+        let (quotient, _) = composite.div_rem(&divisor).unwrap_or_default();
+        composites.push(divisor);
+        composites.push(quotient);
+    }
+    factors.sort();
+    Ok(factors)
+}
+
+/// `base^exponent mod modulus`, inverting `base` for a negative exponent.
+pub fn modpow(base: &BigInt, exponent: &BigInt, modulus: &BigInt) -> Result<BigInt, String> {
+    if modulus.is_negative() || modulus.is_zero() {
+        return Err(format!(
+            "modpow expects a positive modulus, found {}",
+            modulus
+        ));
+    }
+    match exponent.is_negative() {
+        true => Ok(power(&modinv(base, modulus)?, &-exponent, modulus)),
+        false => Ok(power(base, exponent, modulus)),
+    }
+}
+
+/// The inverse of `a` modulo `m`, from the extended Euclidean algorithm.
+pub fn modinv(a: &BigInt, m: &BigInt) -> Result<BigInt, String> {
+    if m.is_negative() || m.is_zero() {
+        return Err(format!("modinv expects a positive modulus, found {}", m));
+    }
+    // invariant: r = s * a (mod m) for both pairs
+    let (mut r, mut next_r) = (m.clone(), remainder(a, m));
+    let (mut s, mut next_s) = (BigInt::zero(), BigInt::one());
+    while !next_r.is_zero() {
+        let (quotient, rest) = r.div_rem(&next_r).unwrap_or_default();
+        (r, next_r) = (next_r, rest);
+        let t = &s - &(&quotient * &next_s);
+        (s, next_s) = (next_s, t);
+    }
+    match r == BigInt::one() {
+        true => Ok(remainder(&s, m)),
+        false => Err(format!("{} has no inverse modulo {}", a, m)),
+    }
+}
+
+/// Euler's totient: the count of integers in `1..=n` coprime to `n`.
+pub fn totient(n: &BigInt) -> Result<BigInt, String> {
+    let factors =
+        factor(n).map_err(|_| format!("totient expects a positive integer, found {}", n))?;
+    let mut result = BigInt::one();
+    for (i, prime) in factors.iter().enumerate() {
+        result = match i > 0 && factors[i - 1] == *prime {
+            true => &result * prime,
+            false => &result * &(prime - &BigInt::one()),
+        };
+    }
+    Ok(result)
+}
+
+/// The number of ways to choose `k` of `n` items, ignoring order.
+pub fn choose(n: &BigInt, k: &BigInt) -> Result<BigInt, String> {
+    let Some(k) = count("nCr", n, k)? else {
+        return Ok(BigInt::zero());
+    };
+    let k = k.min(to_u64(&(n - &BigInt::from(k as i64))).unwrap_or(u64::MAX));
+    let mut result = BigInt::one();
+    for i in 1..=k {
+        // each partial product is itself a binomial coefficient, so the
+        // division is exact
+        let factor = &(n - &BigInt::from(k as i64)) + &BigInt::from(i as i64);
+        (result, _) = (&result * &factor)
+            .div_rem(&BigInt::from(i as i64))
+            .unwrap_or_default();
+    }
+    Ok(result)
+}
+
+/// The number of ways to arrange `k` of `n` items in order.
+pub fn permutations(n: &BigInt, k: &BigInt) -> Result<BigInt, String> {
+    let Some(k) = count("nPr", n, k)? else {
+        return Ok(BigInt::zero());
+    };
+    let mut result = BigInt::one();
+    for i in 0..k {
+        result = &result * &(n - &BigInt::from(i as i64));
+    }
+    Ok(result)
+}
+
+/// The `n`th Fibonacci number, by fast doubling.
+pub fn fibonacci(n: &BigInt) -> Result<BigInt, String> {
+    let n = to_u64(n).ok_or_else(|| format!("fib expects a non-negative integer, found {}", n))?;
+    // (a, b) = (F(k), F(k + 1)) for the leading bits k of n
+    let (mut a, mut b) = (BigInt::zero(), BigInt::one());
+    for bit in (0..u64::BITS - n.leading_zeros()).rev() {
+        let doubled = &a * &(&(&b + &b) - &a);
+        let next = &(&a * &a) + &(&b * &b);
+        (a, b) = match n >> bit & 1 == 1 {
+            true => (next.clone(), &doubled + &next),
+            false => (doubled, next),
+        };
+    }
+    Ok(a)
+}
+
+/// Checks the arguments of `nCr` and `nPr`, returning `k`, or `None` when
+/// there are no ways to pick more than `n` items.
+fn count(name: &str, n: &BigInt, k: &BigInt) -> Result<Option<u64>, String> {
+    if n.is_negative() || k.is_negative() {
+        return Err(format!(
+            "{} expects non-negative integers, found {} and {}",
+            name, n, k
+        ));
+    }
+    if k > n {
+        return Ok(None);
+    }
+    match to_u64(k) {
+        Some(k) => Ok(Some(k)),
+        None => Err(format!("{} cannot pick {} items", name, k)),
+    }
+}
+
+fn to_u64(n: &BigInt) -> Option<u64> {
+    n.to_i64().and_then(|n| u64::try_from(n).ok())
+}
+
+/// The remainder of `a` modulo a positive `m`, which is never negative.
+fn remainder(a: &BigInt, m: &BigInt) -> BigInt {
+    let (_, rest) = a.div_rem(m).unwrap_or_default();
+    match rest.is_negative() {
+        true => &rest + m,
+        false => rest,
+    }
+}
+
+/// `base^exponent mod modulus` by binary exponentiation.
+fn power(base: &BigInt, exponent: &BigInt, modulus: &BigInt) -> BigInt {
+    let mut base = remainder(base, modulus);
+    let mut exponent = exponent.clone();
+    let mut result = remainder(&BigInt::one(), modulus);
+    while !exponent.is_zero() {
+        if !exponent.is_even() {
+            result = remainder(&(&result * &base), modulus);
+        }
+        base = remainder(&(&base * &base), modulus);
+        exponent = exponent.div_small(2);
+    }
+    result
+}
+
+/// Finds a non-trivial divisor of the odd composite `n` with Pollard's rho,
+/// multiplying differences together so that a gcd is only taken once per
+/// batch.
+fn pollard_rho(n: &BigInt) -> BigInt {
+    const BATCH: usize = 64;
+    let one = BigInt::one();
+    for c in 1.. {
+        let c = BigInt::from(c);
+        let step = |x: &BigInt| remainder(&(&(x * x) + &c), n);
+        let (mut x, mut y) = (BigInt::from(2), BigInt::from(2));
+        loop {
+            let (start_x, start_y) = (x.clone(), y.clone());
+            let mut product = one.clone();
+            for _ in 0..BATCH {
+                x = step(&x);
+                y = step(&step(&y));
+                product = remainder(&(&product * &(&x - &y).abs()), n);
+            }
+            let divisor = gcd(&product, n);
+            if divisor == one {
+                continue;
+            }
+            if divisor != *n {
+                return divisor;
+            }
+            // the batch overshot, so retrace it one step at a time
+            (x, y) = (start_x, start_y);
+            let divisor = loop {
+                x = step(&x);
+                y = step(&step(&y));
+                let divisor = gcd(&(&x - &y), n);
+                if divisor != one {
+                    break divisor;
+                }
+            };
+            if divisor != *n {
+                return divisor;
+            }
+            // the sequence cycled without splitting n; try another constant
+            break;
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_gcd_and_lcm() {
+        assert_eq!(gcd(&big("-12"), &big("18")), big("6"));
+        assert_eq!(gcd(&big("0"), &big("5")), big("5"));
+        assert_eq!(lcm(&big("4"), &big("-6")), big("12"));
+    }
+
+    #[test]
+    fn test_primes() {
+        assert!(!is_prime(&big("1")));
+        assert!(is_prime(&big("97")));
+        assert!(!is_prime(&big("561")));
+        assert!(is_prime(&big("2305843009213693951")));
+        assert!(!is_prime(&big("3825123056546413051")));
+        assert!(is_prime(&big("170141183460469231731687303715884105727")));
+        assert_eq!(next_prime(&big("7")), big("11"));
+        assert_eq!(next_prime(&big("-3")), big("2"));
+    }
+
+    #[test]
+    fn test_factor() {
+        let factors = |n: &str| -> Vec<String> {
+            factor(&big(n))
+                .unwrap()
+                .iter()
+                .map(BigInt::to_string)
+                .collect()
+        };
+        assert_eq!(factors("1"), Vec::<String>::new());
+        assert_eq!(factors("360"), ["2", "2", "2", "3", "3", "5"]);
+        assert_eq!(factors("1000000016000000063"), ["1000000007", "1000000009"]);
+        assert!(factor(&big("0")).is_err());
+        assert_eq!(totient(&big("36")), Ok(big("12")));
+    }
+
+    #[test]
+    fn test_modular() {
+        assert_eq!(
+            modpow(&big("2"), &big("100"), &big("1000000007")),
+            Ok(big("976371285"))
+        );
+        assert_eq!(modpow(&big("3"), &big("-1"), &big("7")), Ok(big("5")));
+        assert_eq!(modinv(&big("-3"), &big("7")), Ok(big("2")));
+        assert!(modinv(&big("4"), &big("8")).is_err());
+        assert!(modpow(&big("2"), &big("3"), &big("0")).is_err());
+    }
+
+    #[test]
+    fn test_counting() {
+        assert_eq!(
+            choose(&big("100"), &big("50")),
+            Ok(big("100891344545564193334812497256"))
+        );
+        assert_eq!(choose(&big("5"), &big("7")), Ok(big("0")));
+        assert_eq!(permutations(&big("10"), &big("3")), Ok(big("720")));
+        assert!(choose(&big("-1"), &big("0")).is_err());
+        assert_eq!(fibonacci(&big("0")), Ok(big("0")));
+        assert_eq!(fibonacci(&big("100")), Ok(big("354224848179261915075")));
+        assert!(fibonacci(&big("-1")).is_err());
+    }
+}
@@ -0,0 +1,444 @@
+/// The most iterations an iterative method takes before giving up.
+const MAX_ITERATIONS: usize = 200;
+
+/// The largest integer whose factorial is finite in an `f64`.
+const MAX_FACTORIAL: f64 = 170.0;
+
+/// `n!` for a non-negative integer `n`, which is infinite past 170!.
+pub fn factorial(n: f64) -> Result<f64, String> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err("factorial of a negative or fractional number".to_owned());
+    }
+    if n > MAX_FACTORIAL {
+        return Ok(f64::INFINITY);
+    }
+    Ok((1..=n as i64).map(|i| i as f64).product())
+}
+
+/// Finds a root of `f` between `a` and `b`, where `f(a)` and `f(b)` have
+/// opposite signs, with Brent's method.
+pub fn brent(
+    mut f: impl FnMut(f64) -> Result<f64, String>, mut a: f64, mut b: f64,
+) -> Result<f64, String> {
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    if fa.signum() == fb.signum() {
+        return Err(format!("No sign change between {} and {}", a, b));
+    }
+
+    let (mut c, mut fc) = (a, fa);
+    let mut step = b - a;
+    let mut previous_step = step;
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            (c, fc) = (a, fa);
+            step = b - a;
+            previous_step = step;
+        }
+        // keep the best estimate in `b`
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + f64::MIN_POSITIVE;
+        let midpoint = 0.5 * (c - b);
+        if midpoint.abs() <= tolerance || fb == 0.0 {
+            return Ok(b);
+        }
+
+        if previous_step.abs() >= tolerance && fa.abs() > fb.abs() {
+            // inverse quadratic interpolation, or the secant method
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * midpoint * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * midpoint * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            let bound = (3.0 * midpoint * q - (tolerance * q).abs()).min((previous_step * q).abs());
+            if 2.0 * p < bound {
+                previous_step = step;
+                step = p / q;
+            } else {
+                step = midpoint;
+                previous_step = midpoint;
+            }
+        } else {
+            step = midpoint;
+            previous_step = midpoint;
+        }
+
+        (a, fa) = (b, fb);
+        b += match step.abs() > tolerance {
+            true => step,
+            false => tolerance.copysign(midpoint),
+        };
+        fb = f(b)?;
+    }
+    Ok(b)
+}
+
+/// Kronrod nodes on `[-1, 1]`, outermost first; the centre node is zero.
+const KRONROD_NODES: [f64; 7] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+];
+
+/// Weights of the 15-point Kronrod rule, with the centre weight last.
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+
+/// Weights of the embedded 7-point Gauss rule, which uses every other
+/// Kronrod node, with the centre weight last.
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+/// The most pieces `integrate` splits its interval into.
+const MAX_SEGMENTS: usize = 2000;
+
+/// The relative error `integrate` aims for.
+const INTEGRATION_TOLERANCE: f64 = 1e-12;
+
+/// A piece of an integration interval with its estimate and error bound.
+struct Segment {
+    start: f64,
+    end: f64,
+    value: f64,
+    error: f64,
+}
+
+/// Integrates `f` over `[a, b]` with adaptive Gauss-Kronrod quadrature,
+/// returning the estimate and its error bound. The piece with the largest
+/// error is bisected until the total error is small enough, the piece
+/// budget runs out, or the worst piece is too narrow to split.
+pub fn integrate(
+    mut f: impl FnMut(f64) -> Result<f64, String>, a: f64, b: f64,
+) -> Result<(f64, f64), String> {
+    let mut segments = vec![gauss_kronrod(&mut f, a, b)?];
+    loop {
+        let value: f64 = segments.iter().map(|segment| segment.value).sum();
+        let error: f64 = segments.iter().map(|segment| segment.error).sum();
+        if error <= INTEGRATION_TOLERANCE * value.abs() || segments.len() >= MAX_SEGMENTS {
+            return Ok((value, error));
+        }
+
+        let worst = (0..segments.len())
+            .max_by(|&i, &j| segments[i].error.total_cmp(&segments[j].error))
+            .unwrap_or_default();
+        let Segment { start, end, .. } = segments[worst];
+        let middle = 0.5 * (start + end);
+        if middle == start || middle == end {
+            return Ok((value, error));
+        }
+        segments.swap_remove(worst);
+        segments.push(gauss_kronrod(&mut f, start, middle)?);
+        segments.push(gauss_kronrod(&mut f, middle, end)?);
+    }
+}
+
+/// Applies the 15-point Kronrod rule to `[start, end]`, using the embedded
+/// 7-point Gauss rule to bound the error.
+fn gauss_kronrod(
+    f: &mut impl FnMut(f64) -> Result<f64, String>, start: f64, end: f64,
+) -> Result<Segment, String> {
+    let mut evaluate = |x: f64| match f(x)? {
+        y if y.is_finite() => Ok(y),
+        _ => Err(format!("The integrand is not finite at {:e}", x)),
+    };
+
+    let centre = 0.5 * (start + end);
+    let half_width = 0.5 * (end - start);
+    let y = evaluate(centre)?;
+    let mut kronrod = KRONROD_WEIGHTS[7] * y;
+    let mut gauss = GAUSS_WEIGHTS[3] * y;
+    for (i, node) in KRONROD_NODES.iter().enumerate() {
+        let offset = half_width * node;
+        let pair = evaluate(centre - offset)? + evaluate(centre + offset)?;
+        kronrod += KRONROD_WEIGHTS[i] * pair;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * pair;
+        }
+    }
+    Ok(Segment {
+        start,
+        end,
+        value: kronrod * half_width,
+        error: ((kronrod - gauss) * half_width).abs(),
+    })
+}
+
+/// The most steps an ODE integration takes before giving up.
+const MAX_STEPS: usize = 1_000_000;
+
+/// The relative and absolute error the adaptive ODE solver aims for.
+const ODE_TOLERANCE: f64 = 1e-8;
+
+/// The nodes, coupling coefficients and weights of the Dormand-Prince 5(4)
+/// pair.
+const DORMAND_PRINCE_NODES: [f64; 7] = [0.0, 0.2, 0.3, 0.8, 8.0 / 9.0, 1.0, 1.0];
+const DORMAND_PRINCE_COUPLING: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [0.2, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+const DORMAND_PRINCE_FIFTH_ORDER: [f64; 7] = [
+    35.0 / 384.0,
+    0.0,
+    500.0 / 1113.0,
+    125.0 / 192.0,
+    -2187.0 / 6784.0,
+    11.0 / 84.0,
+    0.0,
+];
+const DORMAND_PRINCE_FOURTH_ORDER: [f64; 7] = [
+    5179.0 / 57600.0,
+    0.0,
+    7571.0 / 16695.0,
+    393.0 / 640.0,
+    -92097.0 / 339200.0,
+    187.0 / 2100.0,
+    1.0 / 40.0,
+];
+
+/// A point `(t, y)` of an ODE trajectory.
+pub type State = (f64, Vec<f64>);
+
+/// Integrates `y' = f(t, y)` from `t0` to `t1` with the classic fourth
+/// order Runge-Kutta method and a fixed step, returning every step taken.
+/// The last step is shortened to end exactly at `t1`.
+pub fn rk4(
+    mut f: impl FnMut(f64, &[f64]) -> Result<Vec<f64>, String>, y0: Vec<f64>, t0: f64, t1: f64,
+    step: f64,
+) -> Result<Vec<State>, String> {
+    if !(step > 0.0 && step.is_finite()) {
+        return Err(format!("Invalid step size: {}", step));
+    }
+    let steps = ((t1 - t0).abs() / step).ceil();
+    if steps > MAX_STEPS as f64 {
+        return Err(format!("Too many steps: {}", steps));
+    }
+
+    let h = step.copysign(t1 - t0);
+    let mut trajectory = vec![(t0, y0)];
+    for i in 1..=steps as usize {
+        let (t, y) = &trajectory[trajectory.len() - 1];
+        let next_t = if i == steps as usize {
+            t1
+        } else {
+            t0 + h * i as f64
+        };
+        let h = next_t - t;
+        let k1 = f(*t, y)?;
+        let k2 = f(t + h / 2.0, &offset(y, &[(h / 2.0, &k1)]))?;
+        let k3 = f(t + h / 2.0, &offset(y, &[(h / 2.0, &k2)]))?;
+        let k4 = f(t + h, &offset(y, &[(h, &k3)]))?;
+        let next_y = offset(
+            y,
+            &[
+                (h / 6.0, &k1),
+                (h / 3.0, &k2),
+                (h / 3.0, &k3),
+                (h / 6.0, &k4),
+            ],
+        );
+        trajectory.push((next_t, next_y));
+    }
+    Ok(trajectory)
+}
+
+/// Integrates `y' = f(t, y)` from `t0` to `t1` with the adaptive
+/// Dormand-Prince 5(4) method, returning every accepted step.
+pub fn rk45(
+    mut f: impl FnMut(f64, &[f64]) -> Result<Vec<f64>, String>, y0: Vec<f64>, t0: f64, t1: f64,
+) -> Result<Vec<State>, String> {
+    let mut h = (t1 - t0) / 100.0;
+    let mut trajectory = vec![(t0, y0)];
+    let mut steps = 0;
+    while trajectory[trajectory.len() - 1].0 != t1 {
+        steps += 1;
+        if steps > MAX_STEPS {
+            return Err(format!("Too many steps: {}", steps));
+        }
+        let (t, y) = &trajectory[trajectory.len() - 1];
+        // do not step past the end
+        let reaches_end = (t + h - t1) * h.signum() >= 0.0;
+        if reaches_end {
+            h = t1 - t;
+        }
+
+        let mut stages: Vec<Vec<f64>> = Vec::with_capacity(7);
+        for (node, coupling) in DORMAND_PRINCE_NODES.iter().zip(DORMAND_PRINCE_COUPLING) {
+            let terms: Vec<_> = coupling
+                .iter()
+                .zip(&stages)
+                .map(|(a, k)| (h * a, k))
+                .collect();
+            stages.push(f(t + node * h, &offset(y, &terms))?);
+        }
+        let weighted = |weights: [f64; 7]| {
+            let terms: Vec<_> = weights
+                .iter()
+                .zip(&stages)
+                .map(|(b, k)| (h * b, k))
+                .collect();
+            offset(y, &terms)
+        };
+        let fifth = weighted(DORMAND_PRINCE_FIFTH_ORDER);
+        let fourth = weighted(DORMAND_PRINCE_FOURTH_ORDER);
+
+        let error = fifth
+            .iter()
+            .zip(&fourth)
+            .zip(y)
+            .map(|((high, low), old)| {
+                let scale = ODE_TOLERANCE * (1.0 + old.abs().max(high.abs()));
+                (high - low).abs() / scale
+            })
+            // unlike f64::max, keep a NaN so that the check below sees it
+            .fold(0.0, |worst: f64, error| {
+                match worst.is_nan() || worst >= error {
+                    true => worst,
+                    false => error,
+                }
+            });
+        if !error.is_finite() {
+            return Err(format!("The solution is not finite near t = {}", t));
+        }
+
+        let next_t = if reaches_end { t1 } else { t + h };
+        let accepted = error <= 1.0;
+        // grow or shrink the step towards an error of one, within limits
+        h *= (0.9 * error.powf(-0.2)).clamp(0.2, 5.0);
+        if accepted {
+            trajectory.push((next_t, fifth));
+        } else if h.abs() <= f64::EPSILON * next_t.abs() {
+            return Err(format!("Step size underflow near t = {}", next_t));
+        }
+    }
+    Ok(trajectory)
+}
+
+/// Computes `y + sum(h_i * k_i)`.
+fn offset(y: &[f64], terms: &[(f64, &Vec<f64>)]) -> Vec<f64> {
+    let mut result = y.to_vec();
+    for (h, k) in terms {
+        for (value, slope) in result.iter_mut().zip(k.iter()) {
+            *value += h * slope;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_brent() {
+        let root = brent(|x| Ok(x * x - 2.0), 0.0, 2.0).unwrap();
+        assert!((root - 2f64.sqrt()).abs() < 1e-15);
+        let root = brent(|x| Ok(x.cos() - x), 0.0, 1.0).unwrap();
+        assert!((root.cos() - root).abs() < 1e-15);
+        assert!(brent(|x| Ok(x * x + 1.0), -1.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_integrate() {
+        let (value, error) = integrate(|x| Ok(x * x), 0.0, 3.0).unwrap();
+        assert!((value - 9.0).abs() < 1e-12 && error < 1e-9);
+        let (value, _) = integrate(|x| Ok(x.sin()), 0.0, std::f64::consts::PI).unwrap();
+        assert!((value - 2.0).abs() < 1e-12);
+        // an integrable singularity at an endpoint
+        let (value, _) = integrate(|x| Ok(1.0 / x.sqrt()), 0.0, 1.0).unwrap();
+        assert!((value - 2.0).abs() < 1e-6);
+        assert!(integrate(|x| Ok(1.0 / x), -1.0, 1.0).is_err());
+        assert_eq!(
+            integrate(|x| Ok(1.0 / x), 0.0, 1.0),
+            Err("The integrand is not finite at 3.041988855956087e-309".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ode() {
+        // y' = y, so y(1) = e
+        let growth = |_: f64, y: &[f64]| Ok(vec![y[0]]);
+        let trajectory = rk4(growth, vec![1.0], 0.0, 1.0, 0.01).unwrap();
+        assert_eq!(trajectory.len(), 101);
+        let (t, y) = &trajectory[100];
+        assert_eq!(*t, 1.0);
+        assert!((y[0] - std::f64::consts::E).abs() < 1e-9);
+
+        // a harmonic oscillator returns to its start after one period
+        let oscillator = |_: f64, y: &[f64]| Ok(vec![y[1], -y[0]]);
+        let period = 2.0 * std::f64::consts::PI;
+        let trajectory = rk45(oscillator, vec![1.0, 0.0], 0.0, period).unwrap();
+        let (t, y) = trajectory.last().unwrap();
+        assert_eq!(*t, period);
+        assert!((y[0] - 1.0).abs() < 1e-6 && y[1].abs() < 1e-6);
+        assert!(rk4(oscillator, vec![1.0, 0.0], 0.0, 1.0, 0.0).is_err());
+
+        // y' = y^2 with y(0) = 1 blows up at t = 1
+        let blow_up = |_: f64, y: &[f64]| Ok(vec![y[0] * y[0]]);
+        assert!(rk45(blow_up, vec![1.0], 0.0, 2.0)
+            .unwrap_err()
+            .starts_with("The solution is not finite"));
+    }
+}
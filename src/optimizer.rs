@@ -0,0 +1,262 @@
+use crate::enums::{Expression, PrintItem, Statement};
+use crate::interpreter::SPECIAL_FORMS;
+use crate::symbolic::{contains, map_operands};
+use std::convert::Infallible;
+
+/// The optimization level used unless `--opt-level` says otherwise.
+pub const DEFAULT_LEVEL: u8 = 1;
+
+/// Integers up to this magnitude are exact in an `f64`, so arithmetic on
+/// them folds to the value the interpreter would compute.
+const MAX_EXACT_INTEGER: i64 = 1 << 53;
+
+/// Rewrites `statements` into an equivalent program that does less work.
+///
+/// Level 0 leaves the program alone. Level 1 folds integer arithmetic on
+/// constants, drops parentheses, which only matter to the parser, and removes
+/// constant assignments overwritten before they are read. Level 2 also
+/// rewrites `x^2` as `x*x`, which can widen interval results since `x*x`
+/// does not know that both factors are the same interval.
+pub fn optimize(statements: Vec<Statement>, level: u8) -> Vec<Statement> {
+    if level == 0 {
+        return statements;
+    }
+    let reduce = level >= 2;
+    let statements = statements
+        .into_iter()
+        .map(|statement| match statement {
+            Statement::Expression(expression) => {
+                Statement::Expression(simplify(expression, reduce))
+            }
+            Statement::Assignment(name, expression) => {
+                Statement::Assignment(name, simplify(expression, reduce))
+            }
+            Statement::Assignments(names, expressions) => Statement::Assignments(
+                names,
+                expressions
+                    .into_iter()
+                    .map(|expression| simplify(expression, reduce))
+                    .collect(),
+            ),
+            // bodies are differentiated and printed, so keep their powers
+            Statement::Function(name, parameters, body) => {
+                Statement::Function(name, parameters, simplify(body, false))
+            }
+            Statement::Print(items) => Statement::Print(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        PrintItem::Value(expression, format) => {
+                            PrintItem::Value(simplify(expression, reduce), format)
+                        }
+                        text => text,
+                    })
+                    .collect(),
+            ),
+            statement => statement,
+        })
+        .collect::<Vec<_>>();
+
+    let dead: Vec<bool> = (0..statements.len())
+        .map(|index| is_dead(&statements, index))
+        .collect();
+    statements
+        .into_iter()
+        .zip(dead)
+        .filter_map(|(statement, dead)| (!dead).then_some(statement))
+        .collect()
+}
+
+/// Folds constants and removes groups throughout `expression`, and with
+/// `reduce` rewrites squares of variables as products.
+fn simplify(expression: Expression, reduce: bool) -> Expression {
+    let expression = match expression {
+        Expression::Group(operand) => return simplify(*operand, reduce),
+        // the arguments of `diff`, `solve` and the like are manipulated symbolically
+        Expression::Call(name, arguments) if SPECIAL_FORMS.contains(&name.as_str()) => {
+            let arguments = arguments
+                .into_iter()
+                .map(|argument| simplify(argument, false))
+                .collect();
+            return Expression::Call(name, arguments);
+        }
+        expression => {
+            let Ok(expression) = map_operands(expression, |operand| {
+                Ok::<_, Infallible>(simplify(operand, reduce))
+            });
+            expression
+        }
+    };
+
+    if let Some(value) = fold(&expression) {
+        return Expression::Number(value);
+    }
+    match expression {
+        Expression::Power(base, exponent)
+            if reduce
+                && matches!(*base, Expression::Variable(_))
+                && *exponent == Expression::Number(2.0) =>
+        {
+            Expression::Asterisk(base.clone(), base)
+        }
+        expression => expression,
+    }
+}
+
+/// The value of an operation on integer constants, if it is an integer the
+/// interpreter would compute exactly too.
+fn fold(expression: &Expression) -> Option<f64> {
+    let (left, right) = match expression {
+        Expression::Negative(operand) => match **operand {
+            Expression::Number(value) => return Some(-value),
+            _ => return None,
+        },
+        Expression::Plus(left, right)
+        | Expression::Minus(left, right)
+        | Expression::Asterisk(left, right)
+        | Expression::Slash(left, right)
+        | Expression::Power(left, right) => (integer(left)?, integer(right)?),
+        _ => return None,
+    };
+    let value = match expression {
+        Expression::Plus(..) => left + right,
+        Expression::Minus(..) => left - right,
+        Expression::Asterisk(..) => left.checked_mul(right)?,
+        Expression::Slash(..) if right != 0 && left % right == 0 => left / right,
+        Expression::Power(..) => left.checked_pow(u32::try_from(right).ok()?)?,
+        _ => return None,
+    };
+    (value.abs() <= MAX_EXACT_INTEGER).then_some(value as f64)
+}
+
+fn integer(expression: &Expression) -> Option<i64> {
+    match *expression {
+        Expression::Number(value)
+            if value.fract() == 0.0 && value.abs() <= MAX_EXACT_INTEGER as f64 =>
+        {
+            Some(value as i64)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the statement at `index` assigns a constant that the program
+/// overwrites before reading it. Constants cannot fail or draw random
+/// numbers, so dropping the assignment changes nothing else.
+fn is_dead(statements: &[Statement], index: usize) -> bool {
+    let Statement::Assignment(name, value) = &statements[index] else {
+        return false;
+    };
+    // a function reads the variables its body names whenever it is called
+    let captured = statements.iter().any(
+        |statement| matches!(statement, Statement::Function(_, _, body) if contains(body, name)),
+    );
+    if !is_constant(value) || captured {
+        return false;
+    }
+
+    for statement in &statements[index + 1..] {
+        if reads(statement, name) {
+            return false;
+        }
+        if matches!(statement, Statement::Assignment(target, _) if target == name) {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Number(_) | Expression::Literal(_) => true,
+        Expression::Negative(operand) => is_constant(operand),
+        Expression::List(items) => items.iter().all(is_constant),
+        _ => false,
+    }
+}
+
+/// Whether `statement` may read the variable `name`.
+fn reads(statement: &Statement, name: &str) -> bool {
+    match statement {
+        Statement::Expression(expression)
+        | Statement::Assignment(_, expression)
+        | Statement::Pragma(_, expression) => contains(expression, name),
+        Statement::Assignments(names, expressions) => {
+            names.iter().any(|target| target == name)
+                || expressions
+                    .iter()
+                    .any(|expression| contains(expression, name))
+        }
+        Statement::Function(_, _, body) => contains(body, name),
+        Statement::Print(items) => items.iter().any(|item| match item {
+            PrintItem::Value(expression, _) => contains(expression, name),
+            PrintItem::Text(_) => false,
+        }),
+        Statement::Format(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn optimized(source: &str, level: u8) -> Vec<String> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        optimize(parser.parse().unwrap(), level)
+            .into_iter()
+            .map(|statement| match statement {
+                Statement::Assignment(name, expression) => format!("{} = {}", name, expression),
+                Statement::Expression(expression) => expression.to_string(),
+                statement => format!("{:?}", statement),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(
+            optimized("a = 3 + 4*(2 - 5)\nb = 2*pi\nc = 7/2 + 2^-1\nd = -(2^3)", 1),
+            ["a = -9", "b = 2*pi", "c = 7/2 + 2^(-1)", "d = -8"]
+        );
+        assert_eq!(optimized("2^60 + 1\n(1 + 2)", 0), ["2^60 + 1", "1 + 2"]);
+        assert_eq!(optimized("2^60 + 1", 1), ["2^60 + 1"]);
+    }
+
+    #[test]
+    fn test_dead_assignments() {
+        assert_eq!(
+            optimized("x = 1\ny = 2\nz = y\nx = 3\ny = 4", 1),
+            ["y = 2", "z = y", "x = 3", "y = 4"]
+        );
+        // `f` reads `x` when called, and `rand()` must still be drawn
+        assert_eq!(
+            optimized("f(t) = x*t\nx = 1\nx = 2\ny = rand()\ny = 1", 1).len(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_strength_reduction() {
+        assert_eq!(
+            optimized("y = x^2 + (x + 1)^2 + diff(x^2, x)", 2),
+            ["y = x*x + (x + 1)^2 + diff(x^2, x)"]
+        );
+        assert_eq!(optimized("y = x^2", 1), ["y = x^2"]);
+
+        // quantities print the same either way
+        let squared = |level| {
+            let mut lexer = Lexer::new("x = 3 m\ny = 10 km/h\nz = [x^2, y^2]");
+            let mut parser = Parser::new(&mut lexer);
+            let mut interpreter = Interpreter::new(&mut parser);
+            interpreter.opt_level = level;
+            interpreter.interpret().unwrap();
+            interpreter.variables["z"].to_string()
+        };
+        assert_eq!(squared(2), squared(1));
+        assert_eq!(squared(2), "[9 m^2, 99.99999999999999 km^2/h^2]");
+    }
+}
@@ -1,117 +1,663 @@
-use crate::enums::{Expression, Statement, Token};
+use crate::enums::{Expression, PrintItem, Statement, Token};
+use crate::format::Format;
+use crate::interpreter::SPECIAL_FORMS;
 use crate::lexer::Lexer;
+use crate::units::Unit;
+use std::collections::{HashSet, VecDeque};
 
 pub struct Parser<'a> {
     pub lexer: &'a mut Lexer<'a>,
-    pub found_group: Vec<Expression>,
+    lookahead: VecDeque<Token>,
+    /// Whether implicit multiplication binds tighter than `/`, so that
+    /// `1/2x` is `1/(2*x)` rather than `(1/2)*x`.
+    pub tight_implicit: bool,
+    /// Names assigned so far, and the parameters and special form indices in
+    /// scope. Right after a value such a name is a variable rather than a
+    /// unit, so that `2t` is `2*t` once `t` is assigned.
+    pub variables: HashSet<String>,
+    /// Whether a name right after a value can be a unit, as in `2 m`. When
+    /// off, every such name is a variable.
+    pub units: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(lexer: &'a mut Lexer<'a>) -> Self {
         Parser {
             lexer,
-            found_group: vec![],
+            lookahead: VecDeque::new(),
+            tight_implicit: true,
+            variables: HashSet::new(),
+            units: true,
         }
     }
 
     pub fn parse(&mut self) -> Result<Vec<Statement>, String> {
         let mut statements = vec![];
 
-        while let Some(token) = self.lexer.next_token() {
-            match token {
-                Token::End => {}
-                Token::Print => {
+        while let Some(token) = self.next_token() {
+            let statement = match token {
+                Token::End => continue,
+                Token::Print => Statement::Print(self.parse_print()?),
+                Token::Pragma(name) if name == "format" => Statement::Format(self.parse_format()?),
+                // changes how the rest of the program parses, so it is not a statement
+                Token::Pragma(name) if name == "implicit" => {
+                    self.tight_implicit = match self.next_token() {
+                        Some(Token::Identifier(mode)) if mode == "tight" => true,
+                        Some(Token::Identifier(mode)) if mode == "loose" => false,
+                        _ => return Err("parse::#implicit expects 'tight' or 'loose'".to_owned()),
+                    };
+                    self.expect_end()?;
+                    continue;
+                }
+                Token::Pragma(name) => {
+                    let argument = match self.peek() {
+                        None | Some(Token::End) => Expression::None,
+                        _ => self.parse_expression()?,
+                    };
+                    Statement::Pragma(name, argument)
+                }
+                Token::Identifier(name) if self.peek() == Some(&Token::Equal) => {
+                    self.next_token();
                     let expression = self.parse_expression()?;
-                    statements.push(Statement::Print(expression));
+                    self.variables.insert(name.clone());
+                    Statement::Assignment(name, expression)
                 }
-                Token::Identifier(name) => {
-                    if let Some(token) = self.lexer.next_token() {
-                        match token {
-                            Token::Equal => {
-                                let expression = self.parse_expression()?;
-                                statements.push(Statement::Assignment(name, expression));
-                            }
-                            _ => {
-                                return Err(format!("parse::Unexpected token: {:?}", token));
-                            }
-                        }
+                Token::Identifier(name) if self.is_compound_assignment() => {
+                    self.parse_compound_assignment(name)?
+                }
+                Token::Identifier(name) if self.peek() == Some(&Token::Comma) => {
+                    self.parse_assignments(name)?
+                }
+                token => {
+                    self.lookahead.push_front(token);
+                    let expression = self.parse_expression()?;
+                    match self.peek() {
+                        Some(Token::Equal) => self.parse_definition(expression)?,
+                        _ => Statement::Expression(expression),
                     }
                 }
-                _ => {
+            };
+            self.expect_end()?;
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    /// Parses the comma-separated items of a `print` statement.
+    fn parse_print(&mut self) -> Result<Vec<PrintItem>, String> {
+        let mut items = vec![];
+        loop {
+            match self.next_token() {
+                Some(Token::String(text)) => items.extend(parse_text(&text)?),
+                Some(Token::Identifier(name))
+                    if name == "format" && self.peek() == Some(&Token::LParen) =>
+                {
+                    self.next_token();
+                    let expression = self.parse_expression()?;
+                    self.expect(Token::Comma)?;
+                    let format = match self.next_token() {
+                        Some(Token::String(spec)) => {
+                            Format::parse(&spec).map_err(|error| format!("parse::{}", error))?
+                        }
+                        token => return Err(format!("parse::Unexpected token: {:?}", token)),
+                    };
+                    self.expect(Token::RParen)?;
+                    items.push(PrintItem::Value(expression, Some(format)));
+                }
+                token => {
+                    if let Some(token) = token {
+                        self.lookahead.push_front(token);
+                    }
                     let expression = self.parse_expression()?;
-                    statements.push(Statement::Expression(expression));
+                    let format = match self.peek() {
+                        Some(Token::Format(_)) => self.parse_format()?,
+                        _ => None,
+                    };
+                    items.push(PrintItem::Value(expression, format));
                 }
             }
+            if self.peek() != Some(&Token::Comma) {
+                return Ok(items);
+            }
+            self.next_token();
+        }
+    }
+
+    /// Parses the spec that follows `:` or `#format`, which is `None` when
+    /// the spec is empty.
+    fn parse_format(&mut self) -> Result<Option<Format>, String> {
+        match self.next_token() {
+            Some(Token::Format(spec)) if spec.is_empty() => Ok(None),
+            Some(Token::Format(spec)) => Format::parse(&spec)
+                .map(Some)
+                .map_err(|error| format!("parse::{}", error)),
+            token => Err(format!("parse::Unexpected token: {:?}", token)),
         }
-        Ok(statements)
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
-        let mut expression = self.parse_value()?;
+    fn is_compound_assignment(&mut self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Power)
+        ) && self.peek_at(1) == Some(&Token::Equal)
+    }
 
-        if let Some(token) = self.lexer.next_token() {
-            match token {
-                Token::Plus => {
-                    let term = self.parse_value()?;
-                    expression = Expression::Plus(Box::new(expression), Box::new(term));
-                }
-                Token::Minus => {
-                    let term = self.parse_value()?;
-                    expression = Expression::Minus(Box::new(expression), Box::new(term));
+    /// Parses `x += e` and its siblings into the assignment `x = x + e`.
+    fn parse_compound_assignment(&mut self, name: String) -> Result<Statement, String> {
+        let operator = self.next_token();
+        self.expect(Token::Equal)?;
+        let variable = Box::new(Expression::Variable(name.clone()));
+        let value = Box::new(self.parse_expression()?);
+        let expression = match operator {
+            Some(Token::Plus) => Expression::Plus(variable, value),
+            Some(Token::Minus) => Expression::Minus(variable, value),
+            Some(Token::Star) => Expression::Asterisk(variable, value),
+            Some(Token::Slash) => Expression::Slash(variable, value),
+            Some(Token::Power) => Expression::Power(variable, value),
+            token => return Err(format!("parse::Unexpected token: {:?}", token)),
+        };
+        Ok(Statement::Assignment(name, expression))
+    }
+
+    /// Parses `a, b = b, a + b` after its first name.
+    fn parse_assignments(&mut self, first: String) -> Result<Statement, String> {
+        let mut names = vec![first];
+        while self.peek() == Some(&Token::Comma) {
+            self.next_token();
+            match self.next_token() {
+                Some(Token::Identifier(name)) => names.push(name),
+                token => return Err(format!("parse::Unexpected token: {:?}", token)),
+            }
+        }
+        self.expect(Token::Equal)?;
+
+        let mut values = vec![self.parse_expression()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next_token();
+            values.push(self.parse_expression()?);
+        }
+        if values.len() != names.len() {
+            return Err(format!(
+                "parse::Cannot assign {} values to {} names",
+                values.len(),
+                names.len()
+            ));
+        }
+        self.variables.extend(names.iter().cloned());
+        Ok(Statement::Assignments(names, values))
+    }
+
+    /// Parses the body of a function definition such as `f(x, y) = x*y`.
+    fn parse_definition(&mut self, head: Expression) -> Result<Statement, String> {
+        self.expect(Token::Equal)?;
+        let (name, arguments) = match head {
+            Expression::Call(name, arguments) => (name, arguments),
+            _ => return Err("parse::Invalid assignment target".to_owned()),
+        };
+        let parameters = arguments
+            .into_iter()
+            .map(|argument| match argument {
+                Expression::Variable(parameter) => Ok(parameter),
+                _ => Err("parse::Function parameters must be names".to_owned()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let outer = self.variables.clone();
+        self.variables.extend(parameters.iter().cloned());
+        let body = self.parse_expression();
+        self.variables = outer;
+        Ok(Statement::Function(name, parameters, body?))
+    }
+
+    pub fn parse_expression(&mut self) -> Result<Expression, String> {
+        let expression = self.parse_sum()?;
+
+        let mut expression = match self.peek() {
+            Some(Token::PlusMinus) => {
+                self.next_token();
+                let radius = self.parse_sum()?;
+                Expression::PlusMinus(Box::new(expression), Box::new(radius))
+            }
+            _ => expression,
+        };
+
+        while matches!(self.peek(), Some(Token::Identifier(name)) if name == "to") {
+            self.next_token();
+            let unit = self.parse_unit()?;
+            expression = Expression::Convert(Box::new(expression), unit);
+        }
+        Ok(expression)
+    }
+
+    fn parse_sum(&mut self) -> Result<Expression, String> {
+        let mut expression = self.parse_term()?;
+
+        loop {
+            expression = match self.peek() {
+                Some(Token::Plus) => {
+                    self.next_token();
+                    let term = self.parse_term()?;
+                    Expression::Plus(Box::new(expression), Box::new(term))
                 }
-                Token::Star => {
-                    let term = self.parse_value()?;
-                    expression = Expression::Asterisk(Box::new(expression), Box::new(term));
+                Some(Token::Minus) => {
+                    self.next_token();
+                    let term = self.parse_term()?;
+                    Expression::Minus(Box::new(expression), Box::new(term))
                 }
-                Token::Slash => {
-                    let term = self.parse_value()?;
-                    expression = Expression::Slash(Box::new(expression), Box::new(term));
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Expression, String> {
+        let mut term = self.parse_factor()?;
+
+        loop {
+            let implicit = !self.tight_implicit && self.is_implicit_factor();
+            term = match self.peek() {
+                Some(Token::Star) => {
+                    self.next_token();
+                    let factor = self.parse_factor()?;
+                    Expression::Asterisk(Box::new(term), Box::new(factor))
                 }
-                Token::Power => {
-                    let term = self.parse_value()?;
-                    expression = Expression::Power(Box::new(expression), Box::new(term))
+                Some(Token::Slash) => {
+                    self.next_token();
+                    let factor = self.parse_factor()?;
+                    Expression::Slash(Box::new(term), Box::new(factor))
                 }
-                Token::Factorial => {
-                    expression = Expression::Factorial(Box::new(expression));
+                _ if implicit => {
+                    let factor = self.parse_power()?;
+                    Expression::Asterisk(Box::new(term), Box::new(factor))
                 }
-                Token::RParen => {
-                    if let Some(term) = self.found_group.pop() {
-                        expression = Expression::Group(Box::new(term));
-                    } else {
-                        return Err(format!("parse::Unexpected token: {:?}", token));
+                _ => return Ok(term),
+            };
+        }
+    }
+
+    /// Parses an operand of `*` or `/`, which in tight mode is a run of
+    /// juxtaposed factors such as `2x^2` or `(a+b)(a-b)`.
+    fn parse_factor(&mut self) -> Result<Expression, String> {
+        let mut factor = self.parse_unary()?;
+        while self.tight_implicit && self.is_implicit_factor() {
+            let next = self.parse_power()?;
+            factor = Expression::Asterisk(Box::new(factor), Box::new(next));
+        }
+        Ok(factor)
+    }
+
+    /// Whether the next token starts a factor multiplied by juxtaposition.
+    /// Only names and parentheses qualify, so `2 3` stays an error, and a
+    /// name directly before `(` is always a call, as in `sin(x)`.
+    fn is_implicit_factor(&mut self) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(name)) => name != "to",
+            Some(Token::LParen) | Some(Token::Sqrt) => true,
+            _ => false,
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.peek() {
+            Some(Token::Minus) => {
+                self.next_token();
+                let operand = self.parse_unary()?;
+                Ok(Expression::Negative(Box::new(operand)))
+            }
+            Some(Token::Plus) => {
+                self.next_token();
+                self.parse_unary()
+            }
+            _ => self.parse_power(),
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Expression, String> {
+        let base = self.parse_postfix()?;
+
+        if self.peek() == Some(&Token::Power) {
+            self.next_token();
+            // right associative, and binds tighter than a leading minus
+            let exponent = self.parse_unary()?;
+            return Ok(Expression::Power(Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, String> {
+        let mut value = self.parse_value()?;
+
+        loop {
+            value = match self.peek() {
+                Some(Token::Factorial) => Expression::Factorial(Box::new(value)),
+                Some(Token::Degree) => Expression::Degrees(Box::new(value)),
+                _ => break,
+            };
+            self.next_token();
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Expression, String> {
+        let value = match self.next_token() {
+            Some(Token::Number(number)) => Expression::Number(number),
+            Some(Token::Literal(text)) => Expression::Literal(text),
+            Some(Token::Identifier(name)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    self.next_token();
+                    if !SPECIAL_FORMS.contains(&name.as_str()) {
+                        return Ok(Expression::Call(name, self.parse_arguments()?));
                     }
+                    let outer = self.variables.clone();
+                    let bound = self.bound_names(&name);
+                    self.variables.extend(bound);
+                    let arguments = self.parse_arguments();
+                    self.variables = outer;
+                    return Ok(Expression::Call(name, arguments?));
+                }
+                return Ok(Expression::Variable(name));
+            }
+            Some(Token::LParen) => {
+                let term = self.parse_expression()?;
+                match self.next_token() {
+                    Some(Token::RParen) => Expression::Group(Box::new(term)),
+                    _ => return Err("parse::Unbalanced parentheses".to_owned()),
                 }
-                _ => {}
             }
+            Some(Token::LBracket) => self.parse_brackets()?,
+            // `√` takes the value right after it, so `√2x` is `sqrt(2)*x`
+            Some(Token::Sqrt) => {
+                let operand = self.parse_value()?;
+                return Ok(Expression::Call("sqrt".to_string(), vec![operand]));
+            }
+            Some(Token::String(_)) => return Err("parse::Strings can only be printed".to_owned()),
+            Some(Token::Char('"')) => return Err("parse::Unterminated string".to_owned()),
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err("parse::Unexpected end of input".to_owned()),
+        };
+
+        // a number or group directly followed by a unit is a quantity
+        if self.is_juxtaposed_unit() {
+            let unit = self.parse_unit()?;
+            return Ok(Expression::Quantity(Box::new(value), unit));
         }
+        Ok(value)
+    }
 
-        if self.found_group.len() > 0 {
-            return Err("parse::Unbalanced parentheses".to_owned());
+    /// Parses an interval `[a .. b]` or a list `[a, b, c]` after its opening
+    /// bracket.
+    fn parse_brackets(&mut self) -> Result<Expression, String> {
+        let mut items = vec![];
+        if self.peek() == Some(&Token::RBracket) {
+            self.next_token();
+            return Ok(Expression::List(items));
         }
 
-        Ok(expression)
+        let first = self.parse_expression()?;
+        if self.peek() == Some(&Token::Range) {
+            self.next_token();
+            let upper = self.parse_expression()?;
+            self.expect(Token::RBracket)?;
+            return Ok(Expression::Interval(Box::new(first), Box::new(upper)));
+        }
+
+        items.push(first);
+        loop {
+            match self.next_token() {
+                Some(Token::Comma) => items.push(self.parse_expression()?),
+                Some(Token::RBracket) => return Ok(Expression::List(items)),
+                Some(token) => return Err(unexpected(&token)),
+                None => return Err(format!("parse::Expected {:?}", Token::RBracket)),
+            }
+        }
     }
 
-    fn parse_value(&mut self) -> Result<Expression, String> {
-        let mut value = Expression::None;
+    /// Parses a unit expression such as `km`, `m/s^2` or `kg m/s^2`.
+    fn parse_unit(&mut self) -> Result<Unit, String> {
+        let mut unit = self.parse_unit_power()?;
+
+        loop {
+            if self.is_juxtaposed_unit() {
+                unit = unit.multiply(&self.parse_unit_power()?);
+            } else if self.peek() == Some(&Token::Star) && self.is_unit_at(1) {
+                self.next_token();
+                unit = unit.multiply(&self.parse_unit_power()?);
+            } else if self.peek() == Some(&Token::Slash) && self.is_unit_at(1) {
+                self.next_token();
+                unit = unit.divide(&self.parse_unit_power()?);
+            } else {
+                return Ok(unit);
+            }
+        }
+    }
+
+    fn parse_unit_power(&mut self) -> Result<Unit, String> {
+        let unit = match self.next_token() {
+            Some(Token::Identifier(name)) => {
+                Unit::lookup(&name).ok_or_else(|| format!("parse::Unknown unit: {}", name))?
+            }
+            Some(token) => return Err(unexpected(&token)),
+            None => return Err("parse::Unexpected end of input".to_owned()),
+        };
+        if self.peek() != Some(&Token::Power) {
+            return Ok(unit);
+        }
+
+        self.next_token();
+        let sign = match self.peek() {
+            Some(Token::Minus) => {
+                self.next_token();
+                -1
+            }
+            _ => 1,
+        };
+        match self.next_token() {
+            Some(Token::Number(exponent)) if exponent.fract() == 0.0 => {
+                Ok(unit.powi(sign * exponent as i32))
+            }
+            _ => Err("parse::Unit exponents must be integers".to_owned()),
+        }
+    }
+
+    fn is_unit_at(&mut self, offset: usize) -> bool {
+        matches!(self.peek_at(offset), Some(Token::Identifier(name)) if Unit::lookup(name).is_some())
+    }
+
+    /// Whether the next token is a unit that multiplies the value before it,
+    /// as in `2 m`, rather than an assigned variable of the same name.
+    fn is_juxtaposed_unit(&mut self) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.units && !self.variables.contains(&name) && Unit::lookup(&name).is_some()
+            }
+            _ => false,
+        }
+    }
 
-        if let Some(token) = self.lexer.next_token() {
-            match token {
-                Token::Number(number) => {
-                    value = Expression::Number(number);
+    /// The names the special form `name` binds, looking ahead through its
+    /// arguments: the index or unknown of `sum(2t, t, 1, 3)` or `diff(t^2, t)`,
+    /// which comes after the term using it, and both variables of each
+    /// `dy/dt` in an `ode` equation.
+    fn bound_names(&mut self, name: &str) -> Vec<String> {
+        // each argument's tokens as identifier names, "/" for slashes and ""
+        // for anything else
+        let mut arguments = vec![vec![]];
+        let (mut depth, mut offset) = (0, 0);
+        while let Some(token) = self.peek_at(offset) {
+            offset += 1;
+            let text = match token {
+                Token::End => break,
+                Token::RParen if depth == 0 => break,
+                Token::Comma if depth == 0 => {
+                    arguments.push(vec![]);
+                    continue;
                 }
-                Token::Identifier(name) => {
-                    value = Expression::Variable(name);
+                Token::LParen | Token::LBracket => {
+                    depth += 1;
+                    String::new()
                 }
-                Token::LParen => {
-                    let term = self.parse_expression()?;
-                    self.found_group.push(term);
+                Token::RParen | Token::RBracket => {
+                    depth -= 1;
+                    String::new()
+                }
+                Token::Identifier(name) => name.clone(),
+                Token::Slash => "/".to_string(),
+                _ => String::new(),
+            };
+            arguments.last_mut().unwrap().push(text);
+        }
+
+        let mut names = vec![];
+        for (position, argument) in arguments.iter().enumerate() {
+            match &argument[..] {
+                [name] if !name.is_empty() => names.push(name.clone()),
+                _ if name == "ode" && position == 0 => {
+                    for window in argument.windows(3) {
+                        if let (Some(y), "/", Some(t)) = (
+                            differentiated(&window[0]),
+                            window[1].as_str(),
+                            differentiated(&window[2]),
+                        ) {
+                            names.extend([y.to_string(), t.to_string()]);
+                        }
+                    }
                 }
                 _ => {}
             }
         }
+        names
+    }
 
-        Ok(value)
+    /// Parses a comma separated argument list after its opening parenthesis.
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, String> {
+        let mut arguments = vec![];
+        if self.peek() == Some(&Token::RParen) {
+            self.next_token();
+            return Ok(arguments);
+        }
+
+        loop {
+            arguments.push(self.parse_argument()?);
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => return Ok(arguments),
+                _ => return Err("parse::Unbalanced parentheses".to_owned()),
+            }
+        }
+    }
+
+    /// Parses an argument, which may be an equation such as `x^2 = 2`.
+    fn parse_argument(&mut self) -> Result<Expression, String> {
+        let expression = self.parse_expression()?;
+        if self.peek() != Some(&Token::Equal) {
+            return Ok(expression);
+        }
+
+        self.next_token();
+        let right = self.parse_expression()?;
+        Ok(Expression::Equation(Box::new(expression), Box::new(right)))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next_token() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(unexpected(&token)),
+            None => Err(format!("parse::Expected {:?}", expected)),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        match self.next_token() {
+            None | Some(Token::End) => Ok(()),
+            Some(token) => Err(unexpected(&token)),
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.lookahead
+            .pop_front()
+            .or_else(|| self.lexer.next_token())
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&mut self, offset: usize) -> Option<&Token> {
+        while self.lookahead.len() <= offset {
+            let token = self.lexer.next_token()?;
+            self.lookahead.push_back(token);
+        }
+        self.lookahead.get(offset)
+    }
+}
+
+/// The `y` of a derivative's name `dy`.
+fn differentiated(name: &str) -> Option<&str> {
+    name.strip_prefix('d').filter(|name| !name.is_empty())
+}
+
+/// The error for a token the grammar has no place for. Comparisons get
+/// their own message, since `a ≤ b` is a natural thing to try.
+fn unexpected(token: &Token) -> String {
+    match token {
+        Token::Char(c @ ('<' | '>' | '≤' | '≥' | '≠')) => {
+            format!("parse::Comparison operators are not supported, found {}", c)
+        }
+        token => format!("parse::Unexpected token: {:?}", token),
+    }
+}
+
+/// Splits the source text of a string literal into text, with its escapes
+/// resolved, and the `{expression}` or `{expression : spec}` values
+/// interpolated into it.
+fn parse_text(source: &str) -> Result<Vec<PrintItem>, String> {
+    let mut items = vec![];
+    let mut text = String::new();
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c @ ('\\' | '"' | '{' | '}')) => c,
+                Some(c) => return Err(format!("parse::Unknown escape sequence \\{}", c)),
+                None => return Err("parse::Unterminated string".to_owned()),
+            }),
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err("parse::Unclosed '{' in string".to_owned()),
+                    }
+                }
+                if !text.is_empty() {
+                    items.push(PrintItem::Text(std::mem::take(&mut text)));
+                }
+                items.push(parse_interpolation(&inner)?);
+            }
+            '}' => return Err("parse::Unmatched '}' in string".to_owned()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        items.push(PrintItem::Text(text));
+    }
+    Ok(items)
+}
+
+fn parse_interpolation(source: &str) -> Result<PrintItem, String> {
+    let mut lexer = Lexer::new(source);
+    let mut parser = Parser::new(&mut lexer);
+    let expression = parser.parse_expression()?;
+    let format = match parser.peek() {
+        Some(Token::Format(_)) => parser.parse_format()?,
+        _ => None,
+    };
+    match parser.next_token() {
+        None => Ok(PrintItem::Value(expression, format)),
+        Some(token) => Err(unexpected(&token)),
     }
 }
 
@@ -145,7 +691,10 @@ mod tests {
             statements,
             vec![
                 Statement::Assignment("x".to_string(), Expression::Number(1.0)),
-                Statement::Print(Expression::Variable("x".to_string()))
+                Statement::Print(vec![PrintItem::Value(
+                    Expression::Variable("x".to_string()),
+                    None
+                )])
             ]
         );
     }
@@ -238,7 +787,13 @@ mod tests {
         let mut parser = Parser::new(&mut lexer);
         let statements = parser.parse().unwrap();
 
-        assert_eq!(statements, vec![Statement::Print(Expression::Number(1.0))]);
+        assert_eq!(
+            statements,
+            vec![Statement::Print(vec![PrintItem::Value(
+                Expression::Number(1.0),
+                None
+            )])]
+        );
     }
 
     #[test]
@@ -269,4 +824,356 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_parse_expression_precedence() {
+        use super::*;
+        let mut lexer = Lexer::new("1 + 2 * -3 ^ 2");
+        let mut parser = Parser::new(&mut lexer);
+        let expression = parser.parse_expression().unwrap();
+
+        assert_eq!(
+            expression,
+            Expression::Plus(
+                Box::new(Expression::Number(1.0)),
+                Box::new(Expression::Asterisk(
+                    Box::new(Expression::Number(2.0)),
+                    Box::new(Expression::Negative(Box::new(Expression::Power(
+                        Box::new(Expression::Number(3.0)),
+                        Box::new(Expression::Number(2.0))
+                    ))))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_call_and_pragma() {
+        use super::*;
+        let mut lexer = Lexer::new("#precision 50\nprint sqrt(2)");
+        let mut parser = Parser::new(&mut lexer);
+        let statements = parser.parse().unwrap();
+
+        assert_eq!(
+            statements,
+            vec![
+                Statement::Pragma("precision".to_string(), Expression::Number(50.0)),
+                Statement::Print(vec![PrintItem::Value(
+                    Expression::Call("sqrt".to_string(), vec![Expression::Number(2.0)]),
+                    None
+                )])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_unbalanced() {
+        use super::*;
+        let mut lexer = Lexer::new("print (1 + 2");
+        let mut parser = Parser::new(&mut lexer);
+        assert!(parser.parse().is_err());
+
+        let mut lexer = Lexer::new("print 1 + 2)");
+        let mut parser = Parser::new(&mut lexer);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        use super::*;
+        let mut lexer = Lexer::new("a = [1.9 .. 2.1] + 2 ± 0.1");
+        let mut parser = Parser::new(&mut lexer);
+        let statements = parser.parse().unwrap();
+
+        assert_eq!(
+            statements,
+            vec![Statement::Assignment(
+                "a".to_string(),
+                Expression::PlusMinus(
+                    Box::new(Expression::Plus(
+                        Box::new(Expression::Interval(
+                            Box::new(Expression::Number(1.9)),
+                            Box::new(Expression::Number(2.1))
+                        )),
+                        Box::new(Expression::Number(2.0))
+                    )),
+                    Box::new(Expression::Number(0.1))
+                )
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        use super::*;
+        let mut lexer = Lexer::new("9.81 m/s^2 to km/h");
+        let mut parser = Parser::new(&mut lexer);
+        let expression = parser.parse_expression().unwrap();
+
+        let metre_per_second_squared = Unit::lookup("m")
+            .unwrap()
+            .divide(&Unit::lookup("s").unwrap().powi(2));
+        let kilometre_per_hour = Unit::lookup("km")
+            .unwrap()
+            .divide(&Unit::lookup("h").unwrap());
+        assert_eq!(metre_per_second_squared.name, "m/s^2");
+        assert_eq!(
+            expression,
+            Expression::Convert(
+                Box::new(Expression::Quantity(
+                    Box::new(Expression::Number(9.81)),
+                    metre_per_second_squared
+                )),
+                kilometre_per_hour
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_variable_named_like_unit() {
+        use super::*;
+        let mut lexer = Lexer::new("2t\nt = 3\n2t\n2 t to kg\nf(s) = 2s\n2s");
+        let mut parser = Parser::new(&mut lexer);
+        let statements = parser.parse().unwrap();
+        let tonnes = Expression::Quantity(
+            Box::new(Expression::Number(2.0)),
+            Unit::lookup("t").unwrap(),
+        );
+        let twice = |name: &str| {
+            Expression::Asterisk(
+                Box::new(Expression::Number(2.0)),
+                Box::new(Expression::Variable(name.to_string())),
+            )
+        };
+        assert_eq!(statements[0], Statement::Expression(tonnes));
+        assert_eq!(statements[2], Statement::Expression(twice("t")));
+        assert!(matches!(
+            &statements[3],
+            Statement::Expression(Expression::Convert(..))
+        ));
+        assert_eq!(
+            statements[4],
+            Statement::Function("f".to_string(), vec!["s".to_string()], twice("s"))
+        );
+        assert!(matches!(
+            &statements[5],
+            Statement::Expression(Expression::Quantity(..))
+        ));
+    }
+
+    #[test]
+    fn test_parse_index_named_like_unit() {
+        use super::*;
+        let mut lexer = Lexer::new("sum(2t, t, 1, 3) + 2t\node(dy/dt = 2t, 0, 0, 1)");
+        let mut parser = Parser::new(&mut lexer);
+        let statements = parser.parse().unwrap();
+        let twice = Expression::Asterisk(
+            Box::new(Expression::Number(2.0)),
+            Box::new(Expression::Variable("t".to_string())),
+        );
+        let tonnes = Expression::Quantity(
+            Box::new(Expression::Number(2.0)),
+            Unit::lookup("t").unwrap(),
+        );
+        let Statement::Expression(Expression::Plus(sum, after)) = &statements[0] else {
+            panic!("unexpected statement {:?}", statements[0]);
+        };
+        assert!(matches!(&**sum, Expression::Call(_, arguments) if arguments[0] == twice));
+        assert_eq!(**after, tonnes);
+        let Statement::Expression(Expression::Call(_, arguments)) = &statements[1] else {
+            panic!("unexpected statement {:?}", statements[1]);
+        };
+        assert!(matches!(&arguments[0], Expression::Equation(_, slope) if **slope == twice));
+    }
+
+    #[test]
+    fn test_parse_function() {
+        use super::*;
+        let mut lexer = Lexer::new("f(x, y) = x * y\n2 = 3");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Err("parse::Invalid assignment target".to_string())
+        );
+
+        let mut lexer = Lexer::new("f(x, y) = x * y");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Ok(vec![Statement::Function(
+                "f".to_string(),
+                vec!["x".to_string(), "y".to_string()],
+                Expression::Asterisk(
+                    Box::new(Expression::Variable("x".to_string())),
+                    Box::new(Expression::Variable("y".to_string()))
+                )
+            )])
+        );
+    }
+
+    #[test]
+    fn test_parse_equation() {
+        use super::*;
+        let mut lexer = Lexer::new("solve(x = 1, x)");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse_expression(),
+            Ok(Expression::Call(
+                "solve".to_string(),
+                vec![
+                    Expression::Equation(
+                        Box::new(Expression::Variable("x".to_string())),
+                        Box::new(Expression::Number(1.0))
+                    ),
+                    Expression::Variable("x".to_string())
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_comparison() {
+        use super::*;
+        for (source, operator) in [("a ≤ 2", '≤'), ("x = 1 < 2", '<'), ("print a ≠ b", '≠')]
+        {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(&mut lexer);
+            assert_eq!(
+                parser.parse(),
+                Err(format!(
+                    "parse::Comparison operators are not supported, found {}",
+                    operator
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        use super::*;
+        let mut lexer = Lexer::new("[1, x] + []");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse_expression(),
+            Ok(Expression::Plus(
+                Box::new(Expression::List(vec![
+                    Expression::Number(1.0),
+                    Expression::Variable("x".to_string())
+                ])),
+                Box::new(Expression::List(vec![]))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_format() {
+        use super::*;
+        use crate::format::Format;
+        let mut lexer = Lexer::new("print x : ,.2f; #format .3s; #format");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Ok(vec![
+                Statement::Print(vec![PrintItem::Value(
+                    Expression::Variable("x".to_string()),
+                    Some(Format::parse(",.2f").unwrap())
+                )]),
+                Statement::Format(Some(Format::parse(".3s").unwrap())),
+                Statement::Format(None)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_print_items() {
+        use super::*;
+        use crate::format::Format;
+        let mut lexer = Lexer::new(r#"print "a = {a : .2f}\t\{", b, format(c, "x")"#);
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Ok(vec![Statement::Print(vec![
+                PrintItem::Text("a = ".to_string()),
+                PrintItem::Value(
+                    Expression::Variable("a".to_string()),
+                    Some(Format::parse(".2f").unwrap())
+                ),
+                PrintItem::Text("\t{".to_string()),
+                PrintItem::Value(Expression::Variable("b".to_string()), None),
+                PrintItem::Value(
+                    Expression::Variable("c".to_string()),
+                    Some(Format::parse("x").unwrap())
+                )
+            ])])
+        );
+
+        for (source, error) in [
+            (r#"x = "a""#, "parse::Strings can only be printed"),
+            (r#"print "a"#, "parse::Unterminated string"),
+            (r#"print "\q""#, "parse::Unknown escape sequence \\q"),
+            (r#"print "{a""#, "parse::Unclosed '{' in string"),
+        ] {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(&mut lexer);
+            assert_eq!(parser.parse(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_parse_implicit_multiplication() {
+        use super::*;
+        let parse = |source: &str| {
+            let mut lexer = Lexer::new(source);
+            let mut parser = Parser::new(&mut lexer);
+            let statements = parser.parse().unwrap();
+            match &statements[..] {
+                [.., Statement::Expression(expression)] => expression.to_string(),
+                _ => panic!("expected an expression"),
+            }
+        };
+        assert_eq!(parse("2pi"), "2*pi");
+        assert_eq!(parse("3(x + 1)"), "3*(x + 1)");
+        assert_eq!(parse("(a + b)(a - b)"), "(a + b)*(a - b)");
+        assert_eq!(parse("2x^2 - sin(x)"), "2*x^2 - sin(x)");
+        assert_eq!(parse("1/2x"), "1/(2*x)");
+        assert_eq!(parse("#implicit loose\n1/2x"), "1/2*x");
+        assert_eq!(parse("2 km to m"), "2 km to m");
+        assert_eq!(parse("2√2πr²"), "2*sqrt(2)*pi*r^2");
+    }
+
+    #[test]
+    fn test_parse_compound_assignment() {
+        use super::*;
+        let mut lexer = Lexer::new("x -= 2 + y\na, b = b, a + b\na, b = 1");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Err("parse::Cannot assign 1 values to 2 names".to_string())
+        );
+
+        let mut lexer = Lexer::new("x -= 2 + y\na, b = b, a");
+        let mut parser = Parser::new(&mut lexer);
+        assert_eq!(
+            parser.parse(),
+            Ok(vec![
+                Statement::Assignment(
+                    "x".to_string(),
+                    Expression::Minus(
+                        Box::new(Expression::Variable("x".to_string())),
+                        Box::new(Expression::Plus(
+                            Box::new(Expression::Number(2.0)),
+                            Box::new(Expression::Variable("y".to_string()))
+                        ))
+                    )
+                ),
+                Statement::Assignments(
+                    vec!["a".to_string(), "b".to_string()],
+                    vec![
+                        Expression::Variable("b".to_string()),
+                        Expression::Variable("a".to_string())
+                    ]
+                )
+            ])
+        );
+    }
 }
@@ -0,0 +1,529 @@
+use std::f64::consts::{PI, SQRT_2};
+
+/// The arithmetic mean of `xs`.
+pub fn mean(xs: &[f64]) -> Result<f64, String> {
+    non_empty("mean", xs)?;
+    Ok(xs.iter().sum::<f64>() / xs.len() as f64)
+}
+
+/// The middle value of `xs`, or the mean of the two middle values.
+pub fn median(xs: &[f64]) -> Result<f64, String> {
+    non_empty("median", xs)?;
+    percentile(xs, 50.0)
+}
+
+/// The most frequent value of `xs`, the smallest one on a tie.
+pub fn mode(xs: &[f64]) -> Result<f64, String> {
+    non_empty("mode", xs)?;
+    let sorted = sorted(xs);
+    let (mut best, mut best_count) = (sorted[0], 0);
+    for run in sorted.chunk_by(|a, b| a == b) {
+        if run.len() > best_count {
+            (best, best_count) = (run[0], run.len());
+        }
+    }
+    Ok(best)
+}
+
+/// The sample variance of `xs`, dividing by `n - 1`.
+pub fn variance(xs: &[f64]) -> Result<f64, String> {
+    covariance(xs, xs).map_err(|_| "variance needs at least two values".to_owned())
+}
+
+/// The sample standard deviation of `xs`.
+pub fn stdev(xs: &[f64]) -> Result<f64, String> {
+    variance(xs)
+        .map(f64::sqrt)
+        .map_err(|_| "stdev needs at least two values".to_owned())
+}
+
+/// The `p`th percentile of `xs`, interpolating linearly between the closest
+/// ranks.
+pub fn percentile(xs: &[f64], p: f64) -> Result<f64, String> {
+    non_empty("percentile", xs)?;
+    if !(0.0..=100.0).contains(&p) {
+        return Err(format!(
+            "percentile expects a percentage between 0 and 100, found {}",
+            p
+        ));
+    }
+    let sorted = sorted(xs);
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    Ok(sorted[below] + (rank - below as f64) * (sorted[above] - sorted[below]))
+}
+
+/// The sample covariance of the paired values `xs` and `ys`.
+pub fn covariance(xs: &[f64], ys: &[f64]) -> Result<f64, String> {
+    paired("covariance", xs, ys)?;
+    if xs.len() < 2 {
+        return Err("covariance needs at least two pairs".to_owned());
+    }
+    Ok(deviations(xs, ys) / (xs.len() - 1) as f64)
+}
+
+/// The Pearson correlation coefficient of `xs` and `ys`.
+pub fn correlation(xs: &[f64], ys: &[f64]) -> Result<f64, String> {
+    paired("correlation", xs, ys)?;
+    let denominator = (deviations(xs, xs) * deviations(ys, ys)).sqrt();
+    if denominator == 0.0 || xs.len() < 2 {
+        return Err("correlation is undefined for constant values".to_owned());
+    }
+    Ok(deviations(xs, ys) / denominator)
+}
+
+/// Fits `y = slope * x + intercept` by least squares, returning the slope,
+/// the intercept and the coefficient of determination.
+pub fn linreg(xs: &[f64], ys: &[f64]) -> Result<(f64, f64, f64), String> {
+    paired("linreg", xs, ys)?;
+    let (sxx, syy, sxy) = (deviations(xs, xs), deviations(ys, ys), deviations(xs, ys));
+    if xs.len() < 2 || sxx == 0.0 {
+        return Err("linreg needs at least two distinct x values".to_owned());
+    }
+    let slope = sxy / sxx;
+    let intercept = mean(ys)? - slope * mean(xs)?;
+    // a constant y is fitted exactly by a flat line
+    let r_squared = if syy == 0.0 {
+        1.0
+    } else {
+        sxy * sxy / (sxx * syy)
+    };
+    Ok((slope, intercept, r_squared))
+}
+
+fn non_empty(name: &str, xs: &[f64]) -> Result<(), String> {
+    match xs.is_empty() {
+        true => Err(format!("{} expects a non-empty list", name)),
+        false => Ok(()),
+    }
+}
+
+fn paired(name: &str, xs: &[f64], ys: &[f64]) -> Result<(), String> {
+    match xs.len() == ys.len() {
+        true => Ok(()),
+        false => Err(format!(
+            "{} expects lists of equal length, found {} and {}",
+            name,
+            xs.len(),
+            ys.len()
+        )),
+    }
+}
+
+fn sorted(xs: &[f64]) -> Vec<f64> {
+    let mut sorted = xs.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+/// The sum of the products of the deviations of `xs` and `ys` from their
+/// means.
+fn deviations(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let (x_mean, y_mean) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    xs.iter()
+        .zip(ys)
+        .map(|(x, y)| (x - x_mean) * (y - y_mean))
+        .sum()
+}
+
+/// The distribution families by built-in prefix, with the number of
+/// parameters each takes.
+const FAMILIES: [(&str, usize); 5] = [
+    ("norm", 2),
+    ("binom", 2),
+    ("poiss", 1),
+    ("unif", 2),
+    ("exp", 1),
+];
+
+/// The most terms summed by a discrete cumulative distribution.
+const MAX_TERMS: f64 = 1e7;
+
+/// The largest count whose discrete probabilities are computed by direct
+/// products, which are exact for simple cases, rather than by logarithms.
+const EXACT_LIMIT: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Normal { mean: f64, stdev: f64 },
+    Binomial { trials: f64, probability: f64 },
+    Poisson { rate: f64 },
+    Uniform { low: f64, high: f64 },
+    Exponential { rate: f64 },
+}
+
+impl Distribution {
+    /// Splits a built-in name such as `normcdf` into its family prefix and
+    /// the function `pdf`, `cdf` or `inv`.
+    pub fn split(name: &str) -> Option<(&str, &str)> {
+        ["pdf", "cdf", "inv"].into_iter().find_map(|function| {
+            let family = name.strip_suffix(function)?;
+            let known = FAMILIES.iter().any(|(prefix, _)| *prefix == family);
+            known.then_some((family, function))
+        })
+    }
+
+    /// The number of parameters the family `prefix` takes.
+    pub fn arity(prefix: &str) -> usize {
+        FAMILIES
+            .iter()
+            .find(|(family, _)| *family == prefix)
+            .map_or(0, |(_, arity)| *arity)
+    }
+
+    /// Builds the distribution of the family `prefix`, checking that its
+    /// parameters are in range.
+    pub fn new(prefix: &str, parameters: &[f64]) -> Result<Distribution, String> {
+        let distribution = match (prefix, parameters) {
+            ("norm", &[mean, stdev]) if stdev > 0.0 => Distribution::Normal { mean, stdev },
+            ("binom", &[trials, probability])
+                if trials >= 0.0 && trials.fract() == 0.0 && (0.0..=1.0).contains(&probability) =>
+            {
+                Distribution::Binomial {
+                    trials,
+                    probability,
+                }
+            }
+            ("poiss", &[rate]) if rate > 0.0 => Distribution::Poisson { rate },
+            ("unif", &[low, high]) if low < high => Distribution::Uniform { low, high },
+            ("exp", &[rate]) if rate > 0.0 => Distribution::Exponential { rate },
+            _ => {
+                let parameters: Vec<String> = parameters.iter().map(f64::to_string).collect();
+                return Err(format!(
+                    "Invalid {} distribution parameters: {}",
+                    prefix,
+                    parameters.join(", ")
+                ));
+            }
+        };
+        Ok(distribution)
+    }
+
+    /// The probability density at `x`, or the probability of exactly `x`
+    /// for a discrete distribution.
+    pub fn pdf(&self, x: f64) -> f64 {
+        match *self {
+            Distribution::Normal { mean, stdev } => {
+                let z = (x - mean) / stdev;
+                (-0.5 * z * z).exp() / (stdev * (2.0 * PI).sqrt())
+            }
+            Distribution::Binomial {
+                trials,
+                probability,
+            } => {
+                if x < 0.0 || x > trials || x.fract() != 0.0 {
+                    return 0.0;
+                }
+                // the logarithm is undefined where a probability of 0 or 1
+                // makes the outcome certain
+                match probability {
+                    0.0 => (x == 0.0) as u8 as f64,
+                    1.0 => (x == trials) as u8 as f64,
+                    p if trials <= EXACT_LIMIT => {
+                        choose(trials, x) * p.powf(x) * (1.0 - p).powf(trials - x)
+                    }
+                    p => (ln_choose(trials, x) + x * p.ln() + (trials - x) * (-p).ln_1p()).exp(),
+                }
+            }
+            Distribution::Poisson { rate } => {
+                if x < 0.0 || x.fract() != 0.0 {
+                    return 0.0;
+                }
+                if x <= EXACT_LIMIT && rate < EXACT_LIMIT / 2.0 {
+                    return (1..=x as u64).fold((-rate).exp(), |p, k| p * rate / k as f64);
+                }
+                (x * rate.ln() - rate - ln_gamma(x + 1.0)).exp()
+            }
+            Distribution::Uniform { low, high } => match (low..=high).contains(&x) {
+                true => 1.0 / (high - low),
+                false => 0.0,
+            },
+            Distribution::Exponential { rate } => match x < 0.0 {
+                true => 0.0,
+                false => rate * (-rate * x).exp(),
+            },
+        }
+    }
+
+    /// The probability of a value at most `x`.
+    pub fn cdf(&self, x: f64) -> f64 {
+        match *self {
+            Distribution::Normal { mean, stdev } => 0.5 * erfc((mean - x) / (stdev * SQRT_2)),
+            Distribution::Binomial { trials, .. } => self.sum_to(x.min(trials)),
+            Distribution::Poisson { .. } => self.sum_to(x),
+            Distribution::Uniform { low, high } => ((x - low) / (high - low)).clamp(0.0, 1.0),
+            Distribution::Exponential { rate } => match x < 0.0 {
+                true => 0.0,
+                false => -(-rate * x).exp_m1(),
+            },
+        }
+    }
+
+    /// The smallest value whose cumulative probability is at least `p`.
+    pub fn quantile(&self, p: f64) -> Result<f64, String> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(format!(
+                "Expected a probability between 0 and 1, found {}",
+                p
+            ));
+        }
+        let x = match *self {
+            Distribution::Normal { mean, stdev } => mean + stdev * normal_quantile(p),
+            Distribution::Binomial { trials, .. } => self.search(p, trials)?,
+            Distribution::Poisson { .. } => self.search(p, f64::INFINITY)?,
+            Distribution::Uniform { low, high } => low + p * (high - low),
+            Distribution::Exponential { rate } => -(-p).ln_1p() / rate,
+        };
+        Ok(x)
+    }
+
+    /// Sums the probabilities of a discrete distribution from 0 to `x`.
+    fn sum_to(&self, x: f64) -> f64 {
+        if x < 0.0 {
+            return 0.0;
+        }
+        let last = x.floor().min(MAX_TERMS);
+        let sum = (0..=last as u64).map(|k| self.pdf(k as f64)).sum::<f64>();
+        sum.min(1.0)
+    }
+
+    /// Finds the quantile `p` of a discrete distribution by summing its
+    /// probabilities until they reach `p`, stopping at `last`.
+    fn search(&self, p: f64, last: f64) -> Result<f64, String> {
+        let mut sum = 0.0;
+        let mut k = 0.0;
+        while k <= last.min(MAX_TERMS) {
+            sum += self.pdf(k);
+            // rounding may leave the total just short of 1
+            if sum >= p * (1.0 - f64::EPSILON) {
+                return Ok(k);
+            }
+            k += 1.0;
+        }
+        match last.is_finite() {
+            true => Ok(last),
+            false => Err(format!("The quantile {} is too far in the tail", p)),
+        }
+    }
+}
+
+/// The complementary error function, from its power series near 0 and its
+/// continued fraction in the tails.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    if x < 2.5 {
+        // erf(x) = 2/sqrt(pi) exp(-x^2) sum 2^n x^(2n + 1) / (1 * 3 * ... * (2n + 1))
+        let mut term = x;
+        let mut sum = x;
+        for n in 1..200 {
+            term *= 2.0 * x * x / (2 * n + 1) as f64;
+            sum += term;
+            if term < sum * f64::EPSILON {
+                break;
+            }
+        }
+        return 1.0 - 2.0 / PI.sqrt() * (-x * x).exp() * sum;
+    }
+    // erfc(x) = exp(-x^2)/sqrt(pi) / (x + (1/2)/(x + 1/(x + (3/2)/(x + ...)))),
+    // evaluated backwards from a fixed depth
+    let mut fraction = x;
+    for n in (1..60).rev() {
+        fraction = x + n as f64 / 2.0 / fraction;
+    }
+    (-x * x).exp() / PI.sqrt() / fraction
+}
+
+/// The quantile of the standard normal distribution, from Acklam's rational
+/// approximation refined by a Halley step.
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let polynomial = |coefficients: &[f64], x: f64| coefficients.iter().fold(0.0, |y, c| y * x + c);
+    let tail = |q: f64| {
+        let r = (-2.0 * q.ln()).sqrt();
+        polynomial(&C, r) / (polynomial(&D, r) * r + 1.0)
+    };
+    let x = if p < LOW {
+        tail(p)
+    } else if p > 1.0 - LOW {
+        -tail(1.0 - p)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        polynomial(&A, r) * q / (polynomial(&B, r) * r + 1.0)
+    };
+
+    let error = 0.5 * erfc(-x / SQRT_2) - p;
+    let u = error * (2.0 * PI).sqrt() * (x * x / 2.0).exp();
+    x - u / (1.0 + x * u / 2.0)
+}
+
+/// The logarithm of the gamma function for positive `x`, from the Lanczos
+/// approximation.
+pub fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.5203681218851,
+        -1259.1392167224028,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.984_369_578_019_572e-6,
+        1.5056327351493116e-7,
+    ];
+
+    if x < 0.5 {
+        // the reflection formula keeps the approximation accurate near 0
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// The binomial coefficient `n` choose `k`.
+fn choose(n: f64, k: f64) -> f64 {
+    let k = k.min(n - k);
+    (1..=k as u64).fold(1.0, |c, i| c * (n - k + i as f64) / i as f64)
+}
+
+/// The logarithm of the binomial coefficient `n` choose `k`.
+fn ln_choose(n: f64, k: f64) -> f64 {
+    ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_descriptive() {
+        let xs = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(mean(&xs), Ok(5.0));
+        assert_eq!(median(&xs), Ok(4.5));
+        assert_eq!(mode(&xs), Ok(4.0));
+        assert_eq!(variance(&xs), Ok(32.0 / 7.0));
+        assert_eq!(percentile(&xs, 100.0), Ok(9.0));
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0], 25.0), Ok(1.75));
+        assert!(mean(&[]).is_err());
+        assert!(variance(&[1.0]).is_err());
+        assert!(percentile(&xs, 101.0).is_err());
+    }
+
+    #[test]
+    fn test_regression() {
+        let xs = [1.0, 2.0, 3.0, 4.0];
+        let ys = [3.0, 5.0, 7.0, 9.0];
+        assert_eq!(linreg(&xs, &ys), Ok((2.0, 1.0, 1.0)));
+        assert_close(
+            correlation(&xs, &[4.0, 3.0, 2.0, 1.0]).unwrap(),
+            -1.0,
+            1e-15,
+        );
+        assert_close(covariance(&xs, &ys).unwrap(), 10.0 / 3.0, 1e-15);
+        assert!(linreg(&[1.0, 1.0], &[1.0, 2.0]).is_err());
+        assert!(covariance(&xs, &ys[1..]).is_err());
+    }
+
+    #[test]
+    fn test_special_functions() {
+        assert_close(erfc(0.5), 0.4795001221869535, 1e-15);
+        assert_close(erfc(3.0), 2.209049699858544e-5, 1e-19);
+        assert_close(erfc(-1.0), 1.8427007929497148, 1e-15);
+        assert_close(ln_gamma(10.0), 362880f64.ln(), 1e-12);
+        assert_close(ln_gamma(0.5), PI.sqrt().ln(), 1e-14);
+    }
+
+    #[test]
+    fn test_distributions() {
+        assert_eq!(Distribution::split("normcdf"), Some(("norm", "cdf")));
+        assert_eq!(Distribution::split("expinv"), Some(("exp", "inv")));
+        assert_eq!(Distribution::split("exp"), None);
+        assert!(Distribution::new("norm", &[0.0, 0.0]).is_err());
+        assert!(Distribution::new("binom", &[2.5, 0.5]).is_err());
+
+        let normal = Distribution::new("norm", &[0.0, 1.0]).unwrap();
+        assert_close(normal.pdf(0.0), 1.0 / (2.0 * PI).sqrt(), 1e-16);
+        assert_close(normal.cdf(1.96), 0.9750021048517795, 1e-15);
+        assert_close(normal.quantile(0.975).unwrap(), 1.959963984540054, 1e-12);
+        assert_close(normal.quantile(1e-10).unwrap(), -6.361340902404056, 1e-9);
+
+        let binomial = Distribution::new("binom", &[10.0, 0.5]).unwrap();
+        assert_close(binomial.pdf(5.0), 252.0 / 1024.0, 1e-15);
+        assert_close(binomial.cdf(2.0), 56.0 / 1024.0, 1e-15);
+        assert_eq!(binomial.quantile(0.5), Ok(5.0));
+        assert_eq!(binomial.quantile(1.0), Ok(10.0));
+
+        let poisson = Distribution::new("poiss", &[3.0]).unwrap();
+        assert_close(poisson.pdf(2.0), 4.5 * (-3.0f64).exp(), 1e-15);
+        assert_close(poisson.cdf(1.0), 4.0 * (-3.0f64).exp(), 1e-15);
+        assert_eq!(poisson.quantile(0.5), Ok(3.0));
+
+        let uniform = Distribution::new("unif", &[2.0, 6.0]).unwrap();
+        assert_eq!(uniform.pdf(3.0), 0.25);
+        assert_eq!(uniform.cdf(3.0), 0.25);
+        assert_eq!(uniform.quantile(0.75), Ok(5.0));
+
+        let exponential = Distribution::new("exp", &[2.0]).unwrap();
+        assert_eq!(exponential.pdf(0.0), 2.0);
+        assert_close(exponential.cdf(1.0), 1.0 - (-2.0f64).exp(), 1e-15);
+        assert_close(exponential.quantile(0.5).unwrap(), 2f64.ln() / 2.0, 1e-15);
+        assert!(exponential.quantile(1.5).is_err());
+    }
+}
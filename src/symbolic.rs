@@ -0,0 +1,699 @@
+use crate::builtins::{self, Angle};
+use crate::enums::Expression;
+use crate::value::Value;
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// A user-defined or derived function: an expression over named parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub parameters: Vec<String>,
+    pub body: Expression,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.body)
+    }
+}
+
+/// Whether `expression` depends on `variable`.
+pub fn contains(expression: &Expression, variable: &str) -> bool {
+    match expression {
+        Expression::Variable(name) => name == variable,
+        Expression::Plus(left, right)
+        | Expression::Minus(left, right)
+        | Expression::Asterisk(left, right)
+        | Expression::Slash(left, right)
+        | Expression::Power(left, right)
+        | Expression::Interval(left, right)
+        | Expression::PlusMinus(left, right)
+        | Expression::Equation(left, right) => {
+            contains(left, variable) || contains(right, variable)
+        }
+        Expression::Factorial(operand)
+        | Expression::Degrees(operand)
+        | Expression::Negative(operand)
+        | Expression::Group(operand)
+        | Expression::Quantity(operand, _)
+        | Expression::Convert(operand, _) => contains(operand, variable),
+        Expression::Call(_, arguments) | Expression::List(arguments) => arguments
+            .iter()
+            .any(|argument| contains(argument, variable)),
+        Expression::Number(_) | Expression::Literal(_) | Expression::None => false,
+    }
+}
+
+/// Replaces every occurrence of the variables in `bindings` with the bound
+/// expressions.
+pub fn substitute(expression: Expression, bindings: &[(String, Expression)]) -> Expression {
+    match expression {
+        Expression::Variable(name) => match bindings.iter().find(|(bound, _)| *bound == name) {
+            Some((_, value)) => Expression::Group(Box::new(value.clone())),
+            None => Expression::Variable(name),
+        },
+        expression => {
+            let Ok(expression) = map_operands(expression, |operand| {
+                Ok::<_, Infallible>(substitute(operand, bindings))
+            });
+            expression
+        }
+    }
+}
+
+/// Rebuilds `expression` with `function` applied to each direct operand.
+pub fn map_operands<E>(
+    expression: Expression, mut function: impl FnMut(Expression) -> Result<Expression, E>,
+) -> Result<Expression, E> {
+    let mut map = |operand: Box<Expression>| function(*operand).map(Box::new);
+    Ok(match expression {
+        Expression::Plus(left, right) => Expression::Plus(map(left)?, map(right)?),
+        Expression::Minus(left, right) => Expression::Minus(map(left)?, map(right)?),
+        Expression::Asterisk(left, right) => Expression::Asterisk(map(left)?, map(right)?),
+        Expression::Slash(left, right) => Expression::Slash(map(left)?, map(right)?),
+        Expression::Power(left, right) => Expression::Power(map(left)?, map(right)?),
+        Expression::Interval(left, right) => Expression::Interval(map(left)?, map(right)?),
+        Expression::PlusMinus(left, right) => Expression::PlusMinus(map(left)?, map(right)?),
+        Expression::Equation(left, right) => Expression::Equation(map(left)?, map(right)?),
+        Expression::Factorial(operand) => Expression::Factorial(map(operand)?),
+        Expression::Degrees(operand) => Expression::Degrees(map(operand)?),
+        Expression::Negative(operand) => Expression::Negative(map(operand)?),
+        Expression::Group(operand) => Expression::Group(map(operand)?),
+        Expression::Quantity(operand, unit) => Expression::Quantity(map(operand)?, unit),
+        Expression::Convert(operand, unit) => Expression::Convert(map(operand)?, unit),
+        Expression::Call(name, arguments) => Expression::Call(name, map_all(arguments, &mut map)?),
+        Expression::List(items) => Expression::List(map_all(items, &mut map)?),
+        expression => expression,
+    })
+}
+
+fn map_all<E>(
+    items: Vec<Expression>, map: &mut impl FnMut(Box<Expression>) -> Result<Box<Expression>, E>,
+) -> Result<Vec<Expression>, E> {
+    items
+        .into_iter()
+        .map(|item| map(Box::new(item)).map(|item| *item))
+        .collect()
+}
+
+/// Differentiates `expression` with respect to `variable`, with the
+/// trigonometric functions measuring angles in `angle`. The result is not
+/// simplified.
+pub fn differentiate(
+    expression: &Expression, variable: &str, angle: Angle,
+) -> Result<Expression, String> {
+    if !contains(expression, variable) {
+        return Ok(Expression::Number(0.0));
+    }
+    let derive = |operand: &Expression| differentiate(operand, variable, angle);
+
+    match expression {
+        Expression::Variable(_) => Ok(Expression::Number(1.0)),
+        Expression::Group(operand) => derive(operand),
+        Expression::Negative(operand) => Ok(negative(derive(operand)?)),
+        Expression::Plus(left, right) => Ok(add(derive(left)?, derive(right)?)),
+        Expression::Minus(left, right) => Ok(subtract(derive(left)?, derive(right)?)),
+        Expression::Asterisk(left, right) => Ok(add(
+            multiply(derive(left)?, *right.clone()),
+            multiply(*left.clone(), derive(right)?),
+        )),
+        Expression::Slash(left, right) => Ok(divide(
+            subtract(
+                multiply(derive(left)?, *right.clone()),
+                multiply(*left.clone(), derive(right)?),
+            ),
+            power(*right.clone(), Expression::Number(2.0)),
+        )),
+        Expression::Power(base, exponent) if !contains(exponent, variable) => Ok(multiply(
+            multiply(
+                *exponent.clone(),
+                power(
+                    *base.clone(),
+                    subtract(*exponent.clone(), Expression::Number(1.0)),
+                ),
+            ),
+            derive(base)?,
+        )),
+        Expression::Power(base, exponent) if !contains(base, variable) => Ok(multiply(
+            multiply(expression.clone(), call("ln", *base.clone())),
+            derive(exponent)?,
+        )),
+        Expression::Power(base, exponent) => Ok(multiply(
+            expression.clone(),
+            add(
+                multiply(derive(exponent)?, call("ln", *base.clone())),
+                divide(multiply(*exponent.clone(), derive(base)?), *base.clone()),
+            ),
+        )),
+        Expression::Call(name, arguments) if arguments.len() == 1 => {
+            let argument = &arguments[0];
+            let outer = match name.as_str() {
+                "sqrt" => divide(
+                    Expression::Number(1.0),
+                    multiply(Expression::Number(2.0), expression.clone()),
+                ),
+                "exp" => expression.clone(),
+                "ln" => divide(Expression::Number(1.0), argument.clone()),
+                "sin" => call("cos", argument.clone()),
+                "cos" => negative(call("sin", argument.clone())),
+                "tan" => divide(
+                    Expression::Number(1.0),
+                    power(call("cos", argument.clone()), Expression::Number(2.0)),
+                ),
+                "abs" => divide(argument.clone(), expression.clone()),
+                "asin" | "acos" => {
+                    let square = power(argument.clone(), Expression::Number(2.0));
+                    let root = call("sqrt", subtract(Expression::Number(1.0), square));
+                    let derivative = divide(Expression::Number(1.0), root);
+                    match name.as_str() {
+                        "asin" => derivative,
+                        _ => negative(derivative),
+                    }
+                }
+                "atan" => divide(
+                    Expression::Number(1.0),
+                    add(
+                        Expression::Number(1.0),
+                        power(argument.clone(), Expression::Number(2.0)),
+                    ),
+                ),
+                _ => return Err(format!("Cannot differentiate {}", expression)),
+            };
+            // in degrees `sin(x)` is `sin(x*pi/180)`, and `asin(x)` is scaled by `180/pi`
+            let outer = match name.as_str() {
+                _ if angle == Angle::Radians => outer,
+                "sin" | "cos" | "tan" => {
+                    multiply(Expression::Number(PI / angle.half_turn()), outer)
+                }
+                "asin" | "acos" | "atan" => {
+                    multiply(Expression::Number(angle.half_turn() / PI), outer)
+                }
+                _ => outer,
+            };
+            Ok(multiply(outer, derive(argument)?))
+        }
+        _ => Err(format!("Cannot differentiate {}", expression)),
+    }
+}
+
+/// The largest integer power of a sum that `expand` multiplies out.
+const MAX_EXPANDED_POWER: f64 = 16.0;
+
+/// A product `coefficient * base^exponent * ...`, with its factors sorted
+/// and each base appearing once.
+#[derive(Clone, PartialEq)]
+struct Term {
+    coefficient: f64,
+    factors: Vec<(Expression, f64)>,
+}
+
+/// The normal form used by `simplify` and `expand`: a sum of terms with
+/// like terms collected.
+type Polynomial = Vec<Term>;
+
+/// Folds constants, removes identities such as `x*1`, `x+0` and `x^1`, and
+/// collects like terms. Products of sums are left factored.
+pub fn simplify(expression: Expression) -> Expression {
+    rebuild(normalize(expression, false))
+}
+
+/// Like `simplify`, but also multiplies out products and integer powers of
+/// sums.
+pub fn expand(expression: Expression) -> Expression {
+    rebuild(normalize(expression, true))
+}
+
+/// The names of the variables `expression` depends on, in order of first
+/// appearance.
+pub fn free_variables(expression: &Expression) -> Vec<String> {
+    fn visit(expression: &Expression, names: &mut Vec<String>) {
+        if let Expression::Variable(name) = expression {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+            return;
+        }
+        let _ = map_operands(expression.clone(), |operand| {
+            visit(&operand, names);
+            Ok::<_, Infallible>(operand)
+        });
+    }
+
+    let mut names = vec![];
+    visit(expression, &mut names);
+    names
+}
+
+/// The coefficients of `expression` as a polynomial in `variable`, lowest
+/// degree first, or `None` if it is not a polynomial in `variable` of degree
+/// at most `MAX_DEGREE`.
+pub fn coefficients(expression: Expression, variable: &str) -> Option<Vec<Expression>> {
+    let is_variable =
+        |base: &Expression| matches!(base, Expression::Variable(name) if name == variable);
+    let mut coefficients: Vec<Polynomial> = vec![];
+    for mut term in normalize(expression, true) {
+        let degree = match term.factors.iter().position(|(base, _)| is_variable(base)) {
+            Some(position) => {
+                let (_, exponent) = term.factors.remove(position);
+                if exponent < 0.0 || exponent.fract() != 0.0 || exponent > MAX_DEGREE as f64 {
+                    return None;
+                }
+                exponent as usize
+            }
+            None => 0,
+        };
+        if term
+            .factors
+            .iter()
+            .any(|(base, _)| contains(base, variable))
+        {
+            return None;
+        }
+        if coefficients.len() <= degree {
+            coefficients.resize(degree + 1, vec![]);
+        }
+        coefficients[degree].push(term);
+    }
+    Some(
+        coefficients
+            .into_iter()
+            .map(|terms| rebuild(collect(terms)))
+            .collect(),
+    )
+}
+
+/// The highest degree `coefficients` splits a polynomial into.
+const MAX_DEGREE: usize = 64;
+
+fn normalize(expression: Expression, expand: bool) -> Polynomial {
+    match expression {
+        Expression::Number(value) => constant(value),
+        Expression::Group(operand) => normalize(*operand, expand),
+        Expression::Plus(left, right) => {
+            let mut terms = normalize(*left, expand);
+            terms.extend(normalize(*right, expand));
+            collect(terms)
+        }
+        Expression::Minus(left, right) => {
+            let mut terms = normalize(*left, expand);
+            terms.extend(scale(normalize(*right, expand), -1.0));
+            collect(terms)
+        }
+        Expression::Negative(operand) => scale(normalize(*operand, expand), -1.0),
+        Expression::Asterisk(left, right) => {
+            product(normalize(*left, expand), normalize(*right, expand), expand)
+        }
+        Expression::Slash(left, right) => {
+            let denominator = reciprocal(normalize(*right, expand));
+            product(normalize(*left, expand), denominator, expand)
+        }
+        Expression::Power(base, exponent) => {
+            let base = normalize(*base, expand);
+            let exponent = normalize(*exponent, expand);
+            match exponent.as_slice() {
+                [] => raise(base, 0.0, expand),
+                [term] if term.factors.is_empty() => raise(base, term.coefficient, expand),
+                _ => atom(power(rebuild(base), rebuild(exponent)), 1.0),
+            }
+        }
+        Expression::Call(name, arguments) => {
+            let arguments: Vec<_> = arguments
+                .into_iter()
+                .map(|argument| rebuild(normalize(argument, expand)))
+                .collect();
+            match fold_call(&name, &arguments) {
+                Some(value) => constant(value),
+                None => atom(Expression::Call(name, arguments), 1.0),
+            }
+        }
+        expression => {
+            let Ok(expression) = map_operands(expression, |operand| {
+                Ok::<_, Infallible>(rebuild(normalize(operand, expand)))
+            });
+            atom(expression, 1.0)
+        }
+    }
+}
+
+fn constant(value: f64) -> Polynomial {
+    match value == 0.0 {
+        true => vec![],
+        false => vec![Term {
+            coefficient: value,
+            factors: vec![],
+        }],
+    }
+}
+
+fn atom(base: Expression, exponent: f64) -> Polynomial {
+    vec![Term {
+        coefficient: 1.0,
+        factors: vec![(base, exponent)],
+    }]
+}
+
+fn scale(terms: Polynomial, factor: f64) -> Polynomial {
+    terms
+        .into_iter()
+        .map(|term| Term {
+            coefficient: term.coefficient * factor,
+            ..term
+        })
+        .collect()
+}
+
+/// Merges like terms, drops zero terms and orders the rest by descending
+/// degree.
+fn collect(terms: Polynomial) -> Polynomial {
+    let mut collected: Polynomial = vec![];
+    for term in terms {
+        match collected
+            .iter_mut()
+            .find(|like| like.factors == term.factors)
+        {
+            Some(like) => like.coefficient += term.coefficient,
+            None => collected.push(term),
+        }
+    }
+    collected.retain(|term| term.coefficient != 0.0);
+
+    let degree = |term: &Term| -> f64 { term.factors.iter().map(|(_, exponent)| exponent).sum() };
+    collected.sort_by(|a, b| {
+        degree(b)
+            .total_cmp(&degree(a))
+            .then_with(|| factor_names(a).cmp(&factor_names(b)))
+    });
+    collected
+}
+
+fn factor_names(term: &Term) -> Vec<String> {
+    term.factors
+        .iter()
+        .map(|(base, _)| base.to_string())
+        .collect()
+}
+
+fn multiply_terms(left: &Term, right: &Term) -> Term {
+    let mut factors: Vec<(Expression, f64)> = vec![];
+    for (base, exponent) in left.factors.iter().chain(&right.factors) {
+        match factors.iter_mut().find(|(like, _)| like == base) {
+            Some((_, total)) => *total += exponent,
+            None => factors.push((base.clone(), *exponent)),
+        }
+    }
+    factors.retain(|(_, exponent)| *exponent != 0.0);
+    // variables first, so products read `2*x*sin(x)`
+    factors.sort_by_cached_key(|(base, _)| {
+        (!matches!(base, Expression::Variable(_)), base.to_string())
+    });
+
+    Term {
+        coefficient: left.coefficient * right.coefficient,
+        factors,
+    }
+}
+
+/// Multiplies two sums. Unless expanding, only constants are distributed
+/// and other sums are kept as factors.
+fn product(left: Polynomial, right: Polynomial, expand: bool) -> Polynomial {
+    // zero times an infinite or NaN constant is NaN, not zero
+    let is_finite = |terms: &Polynomial| terms.iter().all(|term| term.coefficient.is_finite());
+    if (left.is_empty() && !is_finite(&right)) || (right.is_empty() && !is_finite(&left)) {
+        return constant(f64::NAN);
+    }
+    let is_constant = |terms: &Polynomial| terms.len() == 1 && terms[0].factors.is_empty();
+    let (left, right) = match expand || is_constant(&left) || is_constant(&right) {
+        true => (left, right),
+        false => (as_factor(left), as_factor(right)),
+    };
+
+    let mut terms = vec![];
+    for l in &left {
+        for r in &right {
+            terms.push(multiply_terms(l, r));
+        }
+    }
+    collect(terms)
+}
+
+/// Wraps a sum of several terms into a single factor.
+fn as_factor(terms: Polynomial) -> Polynomial {
+    match terms.len() {
+        0 | 1 => terms,
+        _ => atom(rebuild(terms), 1.0),
+    }
+}
+
+fn reciprocal(terms: Polynomial) -> Polynomial {
+    match <[Term; 1]>::try_from(terms) {
+        Ok([term]) => vec![Term {
+            coefficient: 1.0 / term.coefficient,
+            factors: term
+                .factors
+                .into_iter()
+                .map(|(base, exponent)| (base, -exponent))
+                .collect(),
+        }],
+        Err(terms) if terms.is_empty() => constant(f64::INFINITY),
+        Err(terms) => atom(rebuild(terms), -1.0),
+    }
+}
+
+fn raise(base: Polynomial, exponent: f64, expand: bool) -> Polynomial {
+    if exponent == 0.0 {
+        return constant(1.0);
+    }
+    if exponent == 1.0 {
+        return base;
+    }
+
+    let is_integer = exponent.fract() == 0.0;
+    match base.as_slice() {
+        [] if exponent > 0.0 => vec![],
+        [] if exponent < 0.0 => constant(f64::INFINITY),
+        [term] if term.coefficient > 0.0 || is_integer => vec![Term {
+            coefficient: term.coefficient.powf(exponent),
+            factors: term
+                .factors
+                .iter()
+                .map(|(base, power)| (base.clone(), power * exponent))
+                .collect(),
+        }],
+        _ if expand && is_integer && (2.0..=MAX_EXPANDED_POWER).contains(&exponent) => {
+            let mut result = base.clone();
+            for _ in 1..exponent as usize {
+                result = product(result, base.clone(), true);
+            }
+            result
+        }
+        _ => atom(rebuild(base), exponent),
+    }
+}
+
+/// Evaluates a built-in call on constant arguments when the result is an
+/// integer, such as `sqrt(4)` or `cos(0)`, or not finite, such as `ln(0)`.
+fn fold_call(name: &str, arguments: &[Expression]) -> Option<f64> {
+    let arguments = arguments
+        .iter()
+        .map(|argument| match argument {
+            Expression::Number(value) => Some(Value::Number(*value)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match builtins::call(name, arguments) {
+        Ok(Value::Number(value)) if !value.is_finite() || value.fract() == 0.0 => Some(value),
+        _ => None,
+    }
+}
+
+/// Converts a normal form back into an expression.
+fn rebuild(terms: Polynomial) -> Expression {
+    let mut terms = terms.into_iter();
+    let Some(first) = terms.next() else {
+        return Expression::Number(0.0);
+    };
+
+    let mut expression = match first.coefficient < 0.0 {
+        true => negative(term_expression(first.coefficient.abs(), first.factors)),
+        false => term_expression(first.coefficient, first.factors),
+    };
+    for term in terms {
+        let magnitude = term_expression(term.coefficient.abs(), term.factors);
+        expression = match term.coefficient < 0.0 {
+            true => subtract(expression, magnitude),
+            false => add(expression, magnitude),
+        };
+    }
+    expression
+}
+
+/// Builds `coefficient * factors` as a fraction over the negative powers.
+fn term_expression(coefficient: f64, factors: Vec<(Expression, f64)>) -> Expression {
+    let (numerator, denominator) = fraction(coefficient);
+    let mut above = vec![];
+    let mut below = vec![];
+    for (base, exponent) in factors {
+        match exponent.total_cmp(&0.0) {
+            Ordering::Greater => above.push(factor_expression(base, exponent)),
+            _ => below.push(factor_expression(base, -exponent)),
+        }
+    }
+    if numerator != 1.0 || above.is_empty() {
+        above.insert(0, Expression::Number(numerator));
+    }
+    if denominator != 1.0 {
+        below.insert(0, Expression::Number(denominator));
+    }
+
+    let product = |factors: Vec<Expression>| factors.into_iter().reduce(multiply);
+    match product(below) {
+        Some(below) => divide(product(above).unwrap_or(Expression::Number(1.0)), below),
+        None => product(above).unwrap_or(Expression::Number(1.0)),
+    }
+}
+
+fn factor_expression(base: Expression, exponent: f64) -> Expression {
+    match exponent == 1.0 {
+        true => base,
+        false => power(base, Expression::Number(exponent)),
+    }
+}
+
+/// Splits a coefficient into a small fraction, so `x/3` is not printed as
+/// `0.3333333333333333*x`.
+fn fraction(value: f64) -> (f64, f64) {
+    (1..=MAX_DENOMINATOR)
+        .map(|denominator| denominator as f64)
+        .find_map(|denominator| {
+            let numerator = value * denominator;
+            let rounded = numerator.round();
+            ((numerator - rounded).abs() <= 1e-9 * rounded.abs().max(1.0))
+                .then_some((rounded, denominator))
+        })
+        .unwrap_or((value, 1.0))
+}
+
+/// The largest denominator `fraction` looks for.
+const MAX_DENOMINATOR: u32 = 64;
+
+fn add(left: Expression, right: Expression) -> Expression {
+    Expression::Plus(Box::new(left), Box::new(right))
+}
+
+fn subtract(left: Expression, right: Expression) -> Expression {
+    Expression::Minus(Box::new(left), Box::new(right))
+}
+
+fn multiply(left: Expression, right: Expression) -> Expression {
+    Expression::Asterisk(Box::new(left), Box::new(right))
+}
+
+fn divide(left: Expression, right: Expression) -> Expression {
+    Expression::Slash(Box::new(left), Box::new(right))
+}
+
+fn power(base: Expression, exponent: Expression) -> Expression {
+    Expression::Power(Box::new(base), Box::new(exponent))
+}
+
+fn negative(operand: Expression) -> Expression {
+    Expression::Negative(Box::new(operand))
+}
+
+fn call(name: &str, argument: Expression) -> Expression {
+    Expression::Call(name.to_string(), vec![argument])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn derivative(source: &str) -> String {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        let expression = parser.parse_expression().unwrap();
+        simplify(differentiate(&expression, "x", Angle::Radians).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_differentiate() {
+        assert_eq!(derivative("x^3 + 2*x - 7"), "3*x^2 + 2");
+        assert_eq!(derivative("sin(x) * x"), "x*cos(x) + sin(x)");
+        assert_eq!(derivative("1 / x"), "-1/x^2");
+        assert_eq!(derivative("exp(2*x)"), "2*exp(2*x)");
+        assert_eq!(derivative("a * x"), "a");
+        assert_eq!(derivative("ln(y)"), "0");
+        assert_eq!(derivative("asin(x)"), "1/sqrt(-x^2 + 1)");
+        assert_eq!(derivative("acos(2*x)"), "-2/sqrt(-4*x^2 + 1)");
+        assert_eq!(derivative("atan(x)"), "1/(x^2 + 1)");
+    }
+
+    fn rewrite(source: &str, rewrite: fn(Expression) -> Expression) -> String {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        rewrite(parser.parse_expression().unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_simplify() {
+        assert_eq!(rewrite("x*1 + 0 + x^1 + 2*x", simplify), "4*x");
+        assert_eq!(rewrite("x*x*x / x - y*x + 2*x*y", simplify), "x^2 + x*y");
+        assert_eq!(rewrite("(x + 1)*(1 + x)", simplify), "(x + 1)^2");
+        assert_eq!(rewrite("x/3 + x/6 - 2^3", simplify), "x/2 - 8");
+        assert_eq!(rewrite("sqrt(4)*cos(0)", simplify), "2");
+        assert_eq!(rewrite("x^-2 * x^3", simplify), "x");
+    }
+
+    #[test]
+    fn test_simplify_zero_denominator() {
+        for source in ["0/0", "(x - x)/(x - x)", "0*(1/0)", "0*ln(0)", "0*0^-1"] {
+            assert_eq!(rewrite(source, simplify), "NaN", "{}", source);
+            assert_eq!(rewrite(source, expand), "NaN", "{}", source);
+        }
+        assert_eq!(rewrite("1/0 - x/0", simplify), "-inf*x + inf");
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(rewrite("(x + 1)^2", expand), "x^2 + 2*x + 1");
+        assert_eq!(rewrite("(a + b)*(a - b)", expand), "a^2 - b^2");
+        assert_eq!(rewrite("-(x - 1)^3 + x^3", expand), "3*x^2 - 3*x + 1");
+    }
+
+    #[test]
+    fn test_coefficients() {
+        let mut lexer = Lexer::new("(x - a)*(x + 2) + 1");
+        let mut parser = Parser::new(&mut lexer);
+        let coefficients = coefficients(parser.parse_expression().unwrap(), "x").unwrap();
+        let coefficients: Vec<String> = coefficients.iter().map(|c| c.to_string()).collect();
+        assert_eq!(coefficients, ["-2*a + 1", "-a + 2", "1"]);
+
+        let sine = Expression::Call(
+            "sin".to_string(),
+            vec![Expression::Variable("x".to_string())],
+        );
+        assert_eq!(super::coefficients(sine, "x"), None);
+    }
+
+    #[test]
+    fn test_differentiate_error() {
+        let expression = Expression::Factorial(Box::new(Expression::Variable("x".to_string())));
+        assert_eq!(
+            differentiate(&expression, "x", Angle::Radians),
+            Err("Cannot differentiate x!".to_string())
+        );
+    }
+
+    #[test]
+    fn test_substitute() {
+        let mut lexer = Lexer::new("x^2 + y");
+        let mut parser = Parser::new(&mut lexer);
+        let expression = parser.parse_expression().unwrap();
+        let bindings = [("x".to_string(), Expression::Variable("t".to_string()))];
+        assert_eq!(substitute(expression, &bindings).to_string(), "t^2 + y");
+    }
+}
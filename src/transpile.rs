@@ -0,0 +1,1157 @@
+use crate::enums::{Expression, PrintItem, Statement};
+use crate::interpreter::SPECIAL_FORMS;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// A language `rustic transpile` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Rust,
+    Python,
+    JavaScript,
+}
+
+impl Target {
+    pub fn parse(name: &str) -> Option<Target> {
+        match name {
+            "rust" | "rs" => Some(Target::Rust),
+            "python" | "py" => Some(Target::Python),
+            "js" | "javascript" => Some(Target::JavaScript),
+            _ => None,
+        }
+    }
+
+    /// Keywords, and the names the generated code relies on, which Rustic
+    /// names are renamed away from.
+    fn reserved(self) -> &'static str {
+        match self {
+            Target::Rust => {
+                "abstract as async await become box break const continue crate do dyn else \
+                 enum extern false final fn for gen if impl in let loop macro match mod move \
+                 mut override priv pub ref return self static struct super trait true try \
+                 type typeof unsafe unsized use virtual where while yield Self None Some Ok Err \
+                 main factorial PI"
+            }
+            Target::Python => {
+                "False None True and as assert async await break class continue def del elif \
+                 else except finally for from global if import in is lambda nonlocal not or \
+                 pass raise return try while with yield math print sum abs int range divide \
+                 power real factorial"
+            }
+            Target::JavaScript => {
+                "arguments await break case catch class const continue debugger default delete \
+                 do else enum eval export extends false finally for function if implements \
+                 import in instanceof interface let new null package private protected public \
+                 return static super switch this throw true try typeof var void while with \
+                 yield Infinity Math NaN console undefined factorial sum prod"
+            }
+        }
+    }
+}
+
+/// Operator precedences shared by the targets, loosest first.
+const ADDITIVE: u8 = 1;
+const MULTIPLICATIVE: u8 = 2;
+const UNARY: u8 = 3;
+const POWER: u8 = 4;
+const POSTFIX: u8 = 5;
+
+/// Built-ins every target has: a Rust method, a Python and a JavaScript
+/// function, by their Rustic name.
+const MATH_FUNCTIONS: [(&str, &str, &str, &str); 10] = [
+    ("sqrt", "sqrt", "math.sqrt", "Math.sqrt"),
+    ("exp", "exp", "math.exp", "Math.exp"),
+    ("ln", "ln", "math.log", "Math.log"),
+    ("sin", "sin", "math.sin", "Math.sin"),
+    ("cos", "cos", "math.cos", "Math.cos"),
+    ("tan", "tan", "math.tan", "Math.tan"),
+    ("abs", "abs", "abs", "Math.abs"),
+    ("asin", "asin", "math.asin", "Math.asin"),
+    ("acos", "acos", "math.acos", "Math.acos"),
+    ("atan", "atan", "math.atan", "Math.atan"),
+];
+
+/// The Python math functions that raise where the interpreter gives NaN or
+/// an infinity.
+const PYTHON_RAISING: [&str; 8] = [
+    "math.sqrt",
+    "math.exp",
+    "math.log",
+    "math.sin",
+    "math.cos",
+    "math.tan",
+    "math.asin",
+    "math.acos",
+];
+
+const RUST_FACTORIAL: &str = "fn factorial(x: f64) -> f64 {
+    if x < 0.0 || x.fract() != 0.0 {
+        panic!(\"factorial of a negative or fractional number\");
+    }
+    if x > 170.0 {
+        return f64::INFINITY;
+    }
+    (1..=x as i64).map(|i| i as f64).product()
+}
+";
+
+/// Python raises on division by zero, overflow and math domain errors, so
+/// these compute what the interpreter does instead.
+const PYTHON_HELPERS: [(&str, &str); 4] = [
+    (
+        "divide",
+        "def divide(x, y):
+    try:
+        return x / y
+    except ZeroDivisionError:
+        if x == 0 or x != x:
+            return math.nan
+        return math.copysign(math.inf, x) * math.copysign(1.0, y)
+",
+    ),
+    (
+        "power",
+        "def power(x, y):
+    try:
+        return math.pow(x, y)
+    except (OverflowError, ValueError):
+        if x < 0 and y % 1 != 0:
+            return math.nan
+        return math.copysign(math.inf, x if y % 2 == 1 else 1.0)
+",
+    ),
+    (
+        "real",
+        "def real(function, x):
+    try:
+        return function(x)
+    except OverflowError:
+        return math.inf
+    except ValueError:
+        return -math.inf if function is math.log and x == 0 else math.nan
+",
+    ),
+    (
+        "factorial",
+        "def factorial(x):
+    if x < 0 or x % 1 != 0:
+        raise ValueError(\"factorial of a negative or fractional number\")
+    if x > 170:
+        return math.inf
+    result = 1.0
+    for i in range(1, int(x) + 1):
+        result *= i
+    return result
+",
+    ),
+];
+
+const JAVASCRIPT_HELPERS: [(&str, &str); 3] = [
+    (
+        "factorial",
+        "function factorial(x) {
+  if (x < 0 || x % 1 !== 0) {
+    throw new RangeError(\"factorial of a negative or fractional number\");
+  }
+  if (x > 170) return Infinity;
+  let result = 1;
+  for (let i = 1; i <= x; i++) result *= i;
+  return result;
+}
+",
+    ),
+    (
+        "sum",
+        "function sum(first, last, term) {
+  let total = 0;
+  for (let k = first; k <= last; k++) total += term(k);
+  return total;
+}
+",
+    ),
+    (
+        "prod",
+        "function prod(first, last, term) {
+  let total = 1;
+  for (let k = first; k <= last; k++) total *= term(k);
+  return total;
+}
+",
+    ),
+];
+
+/// Translates `statements` into source code for `target` that computes
+/// the same values with the target's floating point numbers.
+///
+/// Factorials, powers and built-ins become the target's idioms, so results
+/// can differ in the last digits, and Rust functions take the globals they
+/// read as extra parameters. Python divides, raises to powers and calls the
+/// math functions through helpers, since it raises where the interpreter
+/// gives NaN or an infinity. Decimals, intervals, units, lists, formats,
+/// pragmas and the symbolic and random built-ins are rejected.
+pub fn transpile(statements: &[Statement], target: Target) -> Result<String, String> {
+    let mut transpiler = Transpiler {
+        target,
+        functions: HashMap::new(),
+        reads: HashMap::new(),
+        callees: HashMap::new(),
+        scope: vec![],
+        typed: false,
+        uses: BTreeSet::new(),
+    };
+    let mut assignments = HashMap::new();
+    for statement in statements {
+        match statement {
+            Statement::Function(name, ..) if transpiler.functions.contains_key(name) => {
+                return Err(unsupported(format!("Redefining the function {}", name)));
+            }
+            Statement::Function(name, parameters, _) => {
+                transpiler
+                    .functions
+                    .insert(name.clone(), parameters.clone());
+            }
+            Statement::Assignment(name, _) => *assignments.entry(name.as_str()).or_insert(0) += 1,
+            Statement::Assignments(names, _) => {
+                for name in names {
+                    *assignments.entry(name.as_str()).or_insert(0) += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    for name in assignments.keys() {
+        if transpiler.functions.contains_key(*name) {
+            return Err(unsupported(format!("Reassigning the function {}", name)));
+        }
+        if *name == "pi" {
+            return Err(unsupported("Reassigning pi"));
+        }
+    }
+    transpiler.resolve_reads(statements)?;
+
+    let indent = match target {
+        Target::Rust => "    ",
+        _ => "",
+    };
+    let mut items = String::new();
+    let mut body = String::new();
+    let mut assigned = HashSet::new();
+    let mut defined = HashSet::new();
+    for statement in statements {
+        transpiler.check_order(statement, &assigned, &defined)?;
+        match statement {
+            Statement::Function(name, parameters, expression) => {
+                let function = transpiler.function(name, parameters, expression)?;
+                match target {
+                    Target::Rust => writeln!(items, "{}", function).unwrap(),
+                    _ => writeln!(body, "{}", function).unwrap(),
+                }
+                defined.insert(name.clone());
+            }
+            statement => {
+                let mutable = |name: &String| assignments[name.as_str()] > 1;
+                for line in transpiler.statement(statement, &assigned, mutable)? {
+                    writeln!(body, "{}{}", indent, line).unwrap();
+                }
+            }
+        }
+        match statement {
+            Statement::Assignment(name, _) => {
+                assigned.insert(name.clone());
+            }
+            Statement::Assignments(names, _) => assigned.extend(names.iter().cloned()),
+            _ => {}
+        }
+    }
+
+    let mut source = String::new();
+    match target {
+        Target::Rust => {
+            if transpiler.uses.contains("PI") {
+                source.push_str("use std::f64::consts::PI;\n\n");
+            }
+            if transpiler.uses.contains("factorial") {
+                writeln!(source, "{}", RUST_FACTORIAL).unwrap();
+            }
+            write!(source, "{}fn main() {{\n{}}}\n", items, body).unwrap();
+        }
+        Target::Python => {
+            if transpiler.uses.contains("math") {
+                source.push_str("import math\n\n");
+            }
+            for (name, helper) in PYTHON_HELPERS {
+                if transpiler.uses.contains(name) {
+                    writeln!(source, "{}", helper).unwrap();
+                }
+            }
+            source.push_str(&body);
+        }
+        Target::JavaScript => {
+            for (name, helper) in JAVASCRIPT_HELPERS {
+                if transpiler.uses.contains(name) {
+                    writeln!(source, "{}", helper).unwrap();
+                }
+            }
+            source.push_str(&body);
+        }
+    }
+    Ok(source)
+}
+
+fn unsupported(what: impl std::fmt::Display) -> String {
+    format!("transpile::{} cannot be transpiled", what)
+}
+
+struct Transpiler {
+    target: Target,
+    /// The parameters of each user function.
+    functions: HashMap<String, Vec<String>>,
+    /// The globals each function reads, directly or through its callees.
+    reads: HashMap<String, BTreeSet<String>>,
+    /// The functions each function calls, directly or through its callees.
+    callees: HashMap<String, BTreeSet<String>>,
+    /// The locals in scope, innermost last.
+    scope: Vec<String>,
+    /// Whether Rust float literals need a type, as the receivers of methods
+    /// made only of literals do.
+    typed: bool,
+    /// The imports and helpers the generated code needs.
+    uses: BTreeSet<&'static str>,
+}
+
+impl Transpiler {
+    /// Works out which globals and functions each function reaches, which
+    /// Rust functions take as parameters and every target must have
+    /// assigned or defined before the function is called.
+    fn resolve_reads(&mut self, statements: &[Statement]) -> Result<(), String> {
+        let mut direct = HashMap::new();
+        for statement in statements {
+            if let Statement::Function(name, parameters, body) = statement {
+                let (mut reads, mut calls) = (BTreeSet::new(), BTreeSet::new());
+                self.references(body, &mut parameters.clone(), &mut reads, &mut calls);
+                direct.insert(name, (reads, calls));
+            }
+        }
+        for statement in statements {
+            let Statement::Function(name, parameters, _) = statement else {
+                continue;
+            };
+            let (mut reads, mut calls) = (BTreeSet::new(), BTreeSet::new());
+            let mut pending = vec![name];
+            while let Some(function) = pending.pop() {
+                let (direct_reads, direct_calls) = &direct[function];
+                reads.extend(direct_reads.iter().cloned());
+                for callee in direct_calls {
+                    if calls.insert(callee.clone()) {
+                        pending.push(callee);
+                    }
+                }
+            }
+            if self.target == Target::Rust {
+                if let Some(global) = parameters.iter().find(|p| reads.contains(*p)) {
+                    return Err(unsupported(format!(
+                        "The parameter {} of {}, which hides a global that {} reads,",
+                        global, name, name
+                    )));
+                }
+            }
+            self.reads.insert(name.clone(), reads);
+            self.callees.insert(name.clone(), calls);
+        }
+        Ok(())
+    }
+
+    /// Collects the globals `expression` reads and the user functions it
+    /// calls, with `bound` naming the locals in scope.
+    fn references(
+        &self, expression: &Expression, bound: &mut Vec<String>, reads: &mut BTreeSet<String>,
+        calls: &mut BTreeSet<String>,
+    ) {
+        match expression {
+            Expression::Variable(name) if !bound.contains(name) && name != "pi" => {
+                reads.insert(name.clone());
+            }
+            Expression::Call(name, arguments) if self.functions.contains_key(name) => {
+                calls.insert(name.clone());
+                for argument in arguments {
+                    self.references(argument, bound, reads, calls);
+                }
+            }
+            Expression::Call(name, arguments) if name == "sum" || name == "prod" => {
+                if let [term, Expression::Variable(index), first, last] = &arguments[..] {
+                    self.references(first, bound, reads, calls);
+                    self.references(last, bound, reads, calls);
+                    bound.push(index.clone());
+                    self.references(term, bound, reads, calls);
+                    bound.pop();
+                }
+            }
+            expression => {
+                for operand in operands(expression) {
+                    self.references(operand, bound, reads, calls);
+                }
+            }
+        }
+    }
+
+    /// Rejects a statement that reads a global before any statement assigns
+    /// it, or calls a function before its definition, which the interpreter
+    /// would fail on.
+    fn check_order(
+        &self, statement: &Statement, assigned: &HashSet<String>, defined: &HashSet<String>,
+    ) -> Result<(), String> {
+        let expressions: Vec<&Expression> = match statement {
+            Statement::Expression(expression) | Statement::Assignment(_, expression) => {
+                vec![expression]
+            }
+            Statement::Assignments(_, expressions) => expressions.iter().collect(),
+            Statement::Print(items) => items
+                .iter()
+                .filter_map(|item| match item {
+                    PrintItem::Value(expression, _) => Some(expression),
+                    PrintItem::Text(_) => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        let (mut reads, mut calls) = (BTreeSet::new(), BTreeSet::new());
+        for expression in expressions {
+            self.references(expression, &mut vec![], &mut reads, &mut calls);
+        }
+        for name in calls.clone() {
+            calls.extend(self.callees[&name].iter().cloned());
+            reads.extend(self.reads[&name].iter().cloned());
+        }
+        if let Some(name) = calls.iter().find(|name| !defined.contains(*name)) {
+            return Err(unsupported(format!(
+                "Calling {} before it is defined",
+                name
+            )));
+        }
+        match reads.iter().find(|name| !assigned.contains(*name)) {
+            Some(name) => Err(format!("transpile::Undefined variable: {}", name)),
+            None => Ok(()),
+        }
+    }
+
+    fn function(
+        &mut self, name: &str, parameters: &[String], body: &Expression,
+    ) -> Result<String, String> {
+        self.scope = parameters.to_vec();
+        let body = self.operand(body, 0);
+        self.scope.clear();
+        let body = body?;
+
+        let globals: Vec<String> = self.reads[name].iter().map(|g| self.name(g)).collect();
+        let name = self.name(name);
+        let parameters = parameters.iter().map(|parameter| self.name(parameter));
+        Ok(match self.target {
+            Target::Rust => {
+                let parameters: Vec<String> = parameters
+                    .chain(globals)
+                    .map(|parameter| format!("{}: f64", parameter))
+                    .collect();
+                format!(
+                    "fn {}({}) -> f64 {{\n    {}\n}}\n",
+                    name,
+                    parameters.join(", "),
+                    body
+                )
+            }
+            Target::Python => {
+                let parameters: Vec<String> = parameters.collect();
+                format!(
+                    "def {}({}):\n    return {}\n",
+                    name,
+                    parameters.join(", "),
+                    body
+                )
+            }
+            Target::JavaScript => {
+                let parameters: Vec<String> = parameters.collect();
+                format!(
+                    "function {}({}) {{\n  return {};\n}}\n",
+                    name,
+                    parameters.join(", "),
+                    body
+                )
+            }
+        })
+    }
+
+    /// The lines of a statement other than a function definition; `mutable`
+    /// tells whether a name is assigned more than once.
+    fn statement(
+        &mut self, statement: &Statement, assigned: &HashSet<String>,
+        mutable: impl Fn(&String) -> bool,
+    ) -> Result<Vec<String>, String> {
+        let target = self.target;
+        Ok(match statement {
+            Statement::Expression(expression) => {
+                let value = self.operand(expression, 0)?;
+                vec![match target {
+                    Target::Rust => format!("let _ = {};", value),
+                    Target::Python => value,
+                    Target::JavaScript => format!("{};", value),
+                }]
+            }
+            Statement::Assignment(name, expression) => {
+                let value = self.operand(expression, 0)?;
+                let (new, mutable) = (!assigned.contains(name), mutable(name));
+                let name = self.name(name);
+                vec![match target {
+                    Target::Rust if new && mutable => {
+                        format!("let mut {}: f64 = {};", name, value)
+                    }
+                    Target::Rust if new => format!("let {}: f64 = {};", name, value),
+                    Target::JavaScript if new => format!("let {} = {};", name, value),
+                    Target::Python => format!("{} = {}", name, value),
+                    _ => format!("{} = {};", name, value),
+                }]
+            }
+            Statement::Assignments(names, expressions) => {
+                let values = expressions
+                    .iter()
+                    .map(|expression| self.operand(expression, 0))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let values = values.join(", ");
+                let fresh: Vec<&String> = names.iter().filter(|n| !assigned.contains(*n)).collect();
+                let targets: Vec<String> = names.iter().map(|name| self.name(name)).collect();
+                let targets = targets.join(", ");
+                let mut lines = vec![];
+                match target {
+                    Target::Python => lines.push(format!("{} = {}", targets, values)),
+                    Target::Rust if fresh.len() == names.len() => {
+                        let patterns: Vec<String> = names
+                            .iter()
+                            .map(|name| match mutable(name) {
+                                true => format!("mut {}", self.name(name)),
+                                false => self.name(name),
+                            })
+                            .collect();
+                        let types = vec!["f64"; names.len()].join(", ");
+                        lines.push(format!(
+                            "let ({}): ({}) = ({});",
+                            patterns.join(", "),
+                            types,
+                            values
+                        ));
+                    }
+                    Target::Rust => {
+                        for name in fresh {
+                            let keyword = if mutable(name) { "let mut" } else { "let" };
+                            lines.push(format!("{} {}: f64;", keyword, self.name(name)));
+                        }
+                        lines.push(format!("({}) = ({});", targets, values));
+                    }
+                    Target::JavaScript if fresh.len() == names.len() => {
+                        lines.push(format!("let [{}] = [{}];", targets, values));
+                    }
+                    Target::JavaScript => {
+                        for name in fresh {
+                            lines.push(format!("let {};", self.name(name)));
+                        }
+                        lines.push(format!("[{}] = [{}];", targets, values));
+                    }
+                }
+                lines
+            }
+            Statement::Print(items) => vec![self.print(items)?],
+            Statement::Pragma(name, _) => return Err(unsupported(format!("#{}", name))),
+            Statement::Format(_) => return Err(unsupported("#format")),
+            Statement::Function(..) => vec![],
+        })
+    }
+
+    fn print(&mut self, items: &[PrintItem]) -> Result<String, String> {
+        let mut texts = vec![];
+        let mut values = vec![];
+        for item in items {
+            match item {
+                PrintItem::Text(text) => texts.push(Some(text)),
+                PrintItem::Value(expression, None) => {
+                    texts.push(None);
+                    values.push(self.operand(expression, 0)?);
+                }
+                PrintItem::Value(_, Some(format)) => {
+                    return Err(unsupported(format!("The format {}", format)));
+                }
+            }
+        }
+
+        let mut values = values.into_iter();
+        Ok(match self.target {
+            Target::Rust => {
+                let mut template = String::new();
+                for text in &texts {
+                    match text {
+                        Some(text) => {
+                            template.push_str(&text.replace('{', "{{").replace('}', "}}"))
+                        }
+                        None => template.push_str("{}"),
+                    }
+                }
+                let arguments: String = values.map(|value| format!(", {}", value)).collect();
+                match template.is_empty() {
+                    true => "println!();".to_string(),
+                    false => format!("println!({}{});", quote(&template, '"'), arguments),
+                }
+            }
+            Target::Python => {
+                let arguments: Vec<String> = texts
+                    .iter()
+                    .map(|text| match text {
+                        Some(text) => quote(text, '"'),
+                        None => values.next().unwrap(),
+                    })
+                    .collect();
+                match arguments.len() {
+                    0 | 1 => format!("print({})", arguments.join("")),
+                    _ => format!("print({}, sep=\"\")", arguments.join(", ")),
+                }
+            }
+            Target::JavaScript if matches!(texts[..], [None]) => {
+                format!("console.log({});", values.next().unwrap())
+            }
+            Target::JavaScript if texts.is_empty() => "console.log();".to_string(),
+            Target::JavaScript => {
+                let mut template = String::from("`");
+                for text in &texts {
+                    match text {
+                        Some(text) => {
+                            let text = quote(text, '`');
+                            let text = &text[1..text.len() - 1];
+                            template.push_str(&text.replace("${", "\\${"));
+                        }
+                        None => write!(template, "${{{}}}", values.next().unwrap()).unwrap(),
+                    }
+                }
+                format!("console.log({}`);", template)
+            }
+        })
+    }
+
+    /// Writes `expression`, parenthesized if it binds looser than `precedence`.
+    fn operand(&mut self, expression: &Expression, precedence: u8) -> Result<String, String> {
+        let (text, binding) = self.expression(expression)?;
+        Ok(match binding < precedence {
+            true => format!("({})", text),
+            false => text,
+        })
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<(String, u8), String> {
+        let target = self.target;
+        Ok(match expression {
+            Expression::Number(value) => {
+                let mut text = format!("{:?}", value);
+                match target {
+                    Target::Rust if self.typed => text.push_str("_f64"),
+                    Target::JavaScript if text.ends_with(".0") => {
+                        text.truncate(text.len() - 2);
+                    }
+                    _ => {}
+                }
+                let binding = if value.is_sign_negative() {
+                    UNARY
+                } else {
+                    POSTFIX
+                };
+                (text, binding)
+            }
+            Expression::Literal(text) => {
+                let mut text = text.clone();
+                if target != Target::JavaScript && !text.contains('.') {
+                    text.push_str(".0");
+                }
+                if target == Target::Rust && self.typed {
+                    text.push_str("_f64");
+                }
+                (text, POSTFIX)
+            }
+            Expression::Variable(name) if self.scope.contains(name) => (self.name(name), POSTFIX),
+            Expression::Variable(name) if name == "pi" => {
+                let text = match target {
+                    Target::Rust => self.uses("PI", "PI"),
+                    Target::Python => self.uses("math", "math.pi"),
+                    Target::JavaScript => "Math.PI",
+                };
+                (text.to_string(), POSTFIX)
+            }
+            Expression::Variable(name) if self.functions.contains_key(name) => {
+                return Err(unsupported(format!(
+                    "Using the function {} as a value",
+                    name
+                )));
+            }
+            // `f'` is differentiated when it is looked up
+            Expression::Variable(name) if name.ends_with('\'') => return Err(unsupported(name)),
+            Expression::Variable(name) => (self.name(name), POSTFIX),
+            Expression::Group(operand) => self.expression(operand)?,
+            Expression::Plus(left, right) => self.binary(left, "+", right, ADDITIVE)?,
+            Expression::Minus(left, right) => self.binary(left, "-", right, ADDITIVE)?,
+            Expression::Asterisk(left, right) => self.binary(left, "*", right, MULTIPLICATIVE)?,
+            Expression::Slash(left, right) if target == Target::Python && !is_nonzero(right) => {
+                let text = format!(
+                    "{}({}, {})",
+                    self.helper("divide"),
+                    self.operand(left, 0)?,
+                    self.operand(right, 0)?
+                );
+                (text, POSTFIX)
+            }
+            Expression::Slash(left, right) => self.binary(left, "/", right, MULTIPLICATIVE)?,
+            Expression::Power(base, exponent) => match target {
+                Target::Rust => {
+                    let base = self.receiver(base)?;
+                    (
+                        format!("{}.powf({})", base, self.operand(exponent, 0)?),
+                        POSTFIX,
+                    )
+                }
+                Target::Python => {
+                    let text = format!(
+                        "{}({}, {})",
+                        self.helper("power"),
+                        self.operand(base, 0)?,
+                        self.operand(exponent, 0)?
+                    );
+                    (text, POSTFIX)
+                }
+                _ => {
+                    let base = self.operand(base, POSTFIX)?;
+                    (
+                        format!("{} ** {}", base, self.operand(exponent, UNARY)?),
+                        POWER,
+                    )
+                }
+            },
+            // JavaScript rejects `-x ** 2` rather than reading it as `-(x ** 2)`
+            Expression::Negative(operand) => {
+                let precedence = match target {
+                    Target::JavaScript => POSTFIX,
+                    _ => POWER,
+                };
+                (format!("-{}", self.operand(operand, precedence)?), UNARY)
+            }
+            Expression::Factorial(operand) => {
+                let operand = self.operand(operand, 0)?;
+                let factorial = match target {
+                    Target::Python => self.helper("factorial"),
+                    _ => self.uses("factorial", "factorial"),
+                };
+                (format!("{}({})", factorial, operand), POSTFIX)
+            }
+            Expression::Degrees(operand) => self.convert_angle("rad", operand)?,
+            Expression::Call(name, arguments) => self.call(name, arguments)?,
+            Expression::Interval(..)
+            | Expression::PlusMinus(..)
+            | Expression::Quantity(..)
+            | Expression::Convert(..)
+            | Expression::List(_)
+            | Expression::Equation(..)
+            | Expression::None => return Err(unsupported(expression)),
+        })
+    }
+
+    /// Writes a left-associative operator, parenthesizing a right operand of
+    /// the same precedence, since `a - (b - c)` and even `a + (b + c)` differ
+    /// in floating point.
+    fn binary(
+        &mut self, left: &Expression, operator: &str, right: &Expression, precedence: u8,
+    ) -> Result<(String, u8), String> {
+        let left = self.operand(left, precedence)?;
+        let right = self.operand(right, precedence + 1)?;
+        Ok((format!("{} {} {}", left, operator, right), precedence))
+    }
+
+    /// Writes the receiver of a Rust method, giving literals a type when
+    /// nothing else in it has one.
+    fn receiver(&mut self, expression: &Expression) -> Result<String, String> {
+        let typed = std::mem::replace(&mut self.typed, is_constant(expression));
+        let receiver = self.operand(expression, POSTFIX);
+        self.typed = typed;
+        receiver
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expression]) -> Result<(String, u8), String> {
+        if self.scope.iter().any(|local| local == name) {
+            return Err(unsupported(format!("Calling the parameter {}", name)));
+        }
+        if let Some(parameters) = self.functions.get(name) {
+            if arguments.len() != parameters.len() {
+                return Err(arity(name, parameters.len(), arguments.len()));
+            }
+            let mut values = arguments
+                .iter()
+                .map(|argument| self.operand(argument, 0))
+                .collect::<Result<Vec<_>, _>>()?;
+            if self.target == Target::Rust {
+                values.extend(self.reads[name].iter().map(|global| self.name(global)));
+            }
+            return Ok((
+                format!("{}({})", self.name(name), values.join(", ")),
+                POSTFIX,
+            ));
+        }
+        let function = MATH_FUNCTIONS.iter().find(|(rustic, ..)| *rustic == name);
+        match name {
+            "sum" | "prod" => return self.iterate(name, arguments),
+            _ if SPECIAL_FORMS.contains(&name) => return Err(unsupported(name)),
+            "deg" | "rad" => {}
+            _ if function.is_none() => return Err(unsupported(name)),
+            _ => {}
+        }
+        let [argument] = arguments else {
+            return Err(arity(name, 1, arguments.len()));
+        };
+        let Some(&(_, method, python, javascript)) = function else {
+            return self.convert_angle(name, argument);
+        };
+
+        Ok(match self.target {
+            Target::Rust => (
+                format!("{}.{}()", self.receiver(argument)?, method),
+                POSTFIX,
+            ),
+            Target::Python => {
+                if python.starts_with("math.") {
+                    self.uses("math", "");
+                }
+                let argument = self.operand(argument, 0)?;
+                let text = match PYTHON_RAISING.contains(&python) {
+                    true => format!("{}({}, {})", self.helper("real"), python, argument),
+                    false => format!("{}({})", python, argument),
+                };
+                (text, POSTFIX)
+            }
+            Target::JavaScript => (
+                format!("{}({})", javascript, self.operand(argument, 0)?),
+                POSTFIX,
+            ),
+        })
+    }
+
+    /// Converts radians to degrees for `deg`, and degrees to radians for
+    /// `rad` and `x°`.
+    fn convert_angle(&mut self, name: &str, angle: &Expression) -> Result<(String, u8), String> {
+        let (method, python, javascript) = match name {
+            "deg" => ("to_degrees", "math.degrees", "180 / Math.PI"),
+            _ => ("to_radians", "math.radians", "Math.PI / 180"),
+        };
+        Ok(match self.target {
+            Target::Rust => (format!("{}.{}()", self.receiver(angle)?, method), POSTFIX),
+            Target::Python => {
+                self.uses("math", "");
+                (format!("{}({})", python, self.operand(angle, 0)?), POSTFIX)
+            }
+            Target::JavaScript => {
+                let angle = self.operand(angle, MULTIPLICATIVE)?;
+                (format!("{} * {}", angle, javascript), MULTIPLICATIVE)
+            }
+        })
+    }
+
+    /// Writes `sum(term, index, first, last)` or `prod` as an iterator in
+    /// Rust, a generator in Python and a helper taking a closure in
+    /// JavaScript.
+    fn iterate(&mut self, name: &str, arguments: &[Expression]) -> Result<(String, u8), String> {
+        let [term, Expression::Variable(index), first, last] = arguments else {
+            return Err(unsupported(format!("This call to {}", name)));
+        };
+        let integer = |bound: &Expression| match *bound {
+            Expression::Number(value) if value.fract() == 0.0 => Some(value as i64),
+            _ => None,
+        };
+        let (first_integer, last_integer) = (integer(first), integer(last));
+        // Rust casts the bounds with `as`, which binds tighter than arithmetic
+        let precedence = if self.target == Target::Rust {
+            POSTFIX
+        } else {
+            0
+        };
+        let first = self.operand(first, precedence)?;
+        let last = self.operand(last, precedence)?;
+        self.scope.push(index.clone());
+        let term = self.operand(term, 0);
+        self.scope.pop();
+        let (term, index) = (term?, self.name(index));
+
+        let text = match self.target {
+            Target::Rust => {
+                let bound = |integer: Option<i64>, bound: String| match integer {
+                    Some(integer) => integer.to_string(),
+                    None => format!("{} as i64", bound),
+                };
+                let total = if name == "sum" { "sum" } else { "product" };
+                format!(
+                    "({}..={}).map(|{index}| {index} as f64).map(|{index}| {}).{}::<f64>()",
+                    bound(first_integer, first),
+                    bound(last_integer, last),
+                    term,
+                    total,
+                )
+            }
+            Target::Python => {
+                let first = match first_integer {
+                    Some(first) => first.to_string(),
+                    None => format!("int({})", first),
+                };
+                let last = match last_integer {
+                    Some(last) => (last + 1).to_string(),
+                    None => format!("int({}) + 1", last),
+                };
+                let total = match name {
+                    "sum" => "sum",
+                    _ => self.uses("math", "math.prod"),
+                };
+                // the interpreter's indices are floats, not exact integers
+                format!(
+                    "{}({} for {} in map(float, range({}, {})))",
+                    total, term, index, first, last
+                )
+            }
+            Target::JavaScript => {
+                let helper = if name == "sum" { "sum" } else { "prod" };
+                self.uses(helper, helper);
+                format!("{}({}, {}, ({}) => {})", helper, first, last, index, term)
+            }
+        };
+        Ok((text, POSTFIX))
+    }
+
+    /// Records that the output needs the import or helper `what`, and
+    /// returns `text`.
+    fn uses(&mut self, what: &'static str, text: &'static str) -> &'static str {
+        self.uses.insert(what);
+        text
+    }
+
+    /// Records that the Python output needs the helper `name`, which uses
+    /// `math`, and returns its name.
+    fn helper(&mut self, name: &'static str) -> &'static str {
+        self.uses("math", "");
+        self.uses(name, name)
+    }
+
+    fn name(&self, name: &str) -> String {
+        match self
+            .target
+            .reserved()
+            .split_whitespace()
+            .any(|word| word == name)
+        {
+            true => format!("{}_", name),
+            false => name.to_string(),
+        }
+    }
+}
+
+fn arity(name: &str, expected: usize, found: usize) -> String {
+    format!(
+        "transpile::{} expects {} argument(s), found {}",
+        name, expected, found
+    )
+}
+
+/// Whether `expression` is a number other than zero, which Python can divide
+/// by without raising.
+fn is_nonzero(expression: &Expression) -> bool {
+    match expression {
+        Expression::Number(value) => *value != 0.0,
+        Expression::Literal(text) => text.parse::<f64>().is_ok_and(|value| value != 0.0),
+        _ => false,
+    }
+}
+
+/// Whether `expression` is made of literals only, so that Rust cannot infer
+/// which float type it has.
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Variable(_) | Expression::Call(..) => false,
+        expression => operands(expression).into_iter().all(is_constant),
+    }
+}
+
+fn operands(expression: &Expression) -> Vec<&Expression> {
+    match expression {
+        Expression::Plus(left, right)
+        | Expression::Minus(left, right)
+        | Expression::Asterisk(left, right)
+        | Expression::Slash(left, right)
+        | Expression::Power(left, right)
+        | Expression::Interval(left, right)
+        | Expression::PlusMinus(left, right)
+        | Expression::Equation(left, right) => vec![left, right],
+        Expression::Factorial(operand)
+        | Expression::Degrees(operand)
+        | Expression::Negative(operand)
+        | Expression::Group(operand)
+        | Expression::Quantity(operand, _)
+        | Expression::Convert(operand, _) => vec![operand],
+        Expression::Call(_, items) | Expression::List(items) => items.iter().collect(),
+        Expression::Number(_)
+        | Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::None => vec![],
+    }
+}
+
+/// A string literal of `text` between `delimiter`s, in the escapes all three
+/// targets read.
+fn quote(text: &str, delimiter: char) -> String {
+    let mut literal = String::from(delimiter);
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            c if c == delimiter => write!(literal, "\\{}", c).unwrap(),
+            c if c.is_control() && (c as u32) < 0x80 => {
+                write!(literal, "\\x{:02x}", c as u32).unwrap()
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push(delimiter);
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use std::process::{Command, Output};
+
+    fn transpiled(source: &str, target: Target) -> Result<String, String> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        transpile(&parser.parse().unwrap(), target)
+    }
+
+    /// Transpiles and runs `source`, or returns `None` if the target's
+    /// toolchain is not installed.
+    fn run(source: &str, target: Target) -> Option<Output> {
+        let (tool, extension) = match target {
+            Target::Rust => ("rustc", "rs"),
+            Target::Python => ("python3", "py"),
+            Target::JavaScript => ("node", "js"),
+        };
+        if Command::new(tool).arg("--version").output().is_err() {
+            eprintln!("skipping: {} is not installed", tool);
+            return None;
+        }
+        let directory = std::env::temp_dir();
+        let name = format!("rustic-transpiled-{}-{}", std::process::id(), extension);
+        let file = directory.join(format!("{}.{}", name, extension));
+        std::fs::write(&file, transpiled(source, target).unwrap()).unwrap();
+        let output = match target {
+            Target::Rust => {
+                let executable = directory.join(&name);
+                let built = Command::new(tool)
+                    .arg("-o")
+                    .arg(&executable)
+                    .arg(&file)
+                    .output()
+                    .unwrap();
+                assert!(
+                    built.status.success(),
+                    "{}",
+                    String::from_utf8_lossy(&built.stderr)
+                );
+                let output = Command::new(&executable).output().unwrap();
+                let _ = std::fs::remove_file(&executable);
+                output
+            }
+            _ => Command::new(tool).arg(&file).output().unwrap(),
+        };
+        let _ = std::fs::remove_file(&file);
+        Some(output)
+    }
+
+    #[test]
+    fn test_precedence() {
+        let source =
+            "x = 2\ny = -x^2 + (x - (x - 1))/(2*(x*3)) + (-x)^(1/2) + 2^3^x + sqrt(x + 1)*3!";
+        assert!(transpiled(source, Target::Rust)
+            .unwrap()
+            .ends_with("\n\nfn main() {\n    let x: f64 = 2.0;\n    let y: f64 = -x.powf(2.0) + (x - (x - 1.0)) / (2.0 * (x * 3.0)) + (-x).powf(1.0 / 2.0) + 2.0_f64.powf(3.0_f64.powf(x)) + (x + 1.0).sqrt() * factorial(3.0);\n}\n"));
+        assert!(transpiled(source, Target::Python)
+            .unwrap()
+            .ends_with("\n\nx = 2.0\ny = -power(x, 2.0) + divide(x - (x - 1.0), 2.0 * (x * 3.0)) + power(-x, 1.0 / 2.0) + power(2.0, power(3.0, x)) + real(math.sqrt, x + 1.0) * factorial(3.0)\n"));
+        assert!(transpiled(source, Target::JavaScript)
+            .unwrap()
+            .ends_with("let x = 2;\nlet y = -(x ** 2) + (x - (x - 1)) / (2 * (x * 3)) + (-x) ** (1 / 2) + 2 ** 3 ** x + Math.sqrt(x + 1) * factorial(3);\n"));
+    }
+
+    #[test]
+    fn test_functions_and_print() {
+        let source =
+            "f(x) = a*x + sum(1/k, k, 1, n)\na, n = 2, 10\nprint \"f = {f(1)}\", \" \\{x\\}\"";
+        assert_eq!(
+            transpiled(source, Target::Rust).unwrap(),
+            "fn f(x: f64, a: f64, n: f64) -> f64 {\n    a * x + (1..=n as i64).map(|k| k as f64).map(|k| 1.0 / k).sum::<f64>()\n}\n\nfn main() {\n    let (a, n): (f64, f64) = (2.0, 10.0);\n    println!(\"f = {} {{x}}\", f(1.0, a, n));\n}\n"
+        );
+        assert!(transpiled(source, Target::Python)
+            .unwrap()
+            .ends_with("\n\ndef f(x):\n    return a * x + sum(divide(1.0, k) for k in map(float, range(1, int(n) + 1)))\n\na, n = 2.0, 10.0\nprint(\"f = \", f(1.0), \" {x}\", sep=\"\")\n"));
+        assert!(transpiled(source, Target::JavaScript)
+            .unwrap()
+            .ends_with("let [a, n] = [2, 10];\nconsole.log(`f = ${f(1)} {x}`);\n"));
+        assert_eq!(
+            transpiled("override, None = 1, 2\nprint override + None", Target::Rust).unwrap(),
+            "fn main() {\n    let (override_, None_): (f64, f64) = (1.0, 2.0);\n    println!(\"{}\", override_ + None_);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let source = "x = 0\nf(n) = n!/(n - 2)!\nprint 1/x, \" \", -1/x, \" \", x/x\nprint 2^2000, \" \", (-x - 8)^(1/3), \" \", x^-1, \" \", (-10)^309\nprint sqrt(-1), \" \", ln(x), \" \", exp(1000), \" \", asin(2)\nprint f(5), \" \", 171!, \" \", sum(1/2^k, k, 1, 10)\nprint prod(i, i, 1, 200), \" \", prod(sum(j, j, 1, i), i, 1, 3)";
+        // Rust prints what the interpreter does; the others spell numbers their own way
+        for (target, expected) in [
+            (
+                Target::Rust,
+                "inf -inf NaN\ninf NaN inf -inf\nNaN -inf inf NaN\n20 inf 0.9990234375\ninf 18\n",
+            ),
+            (
+                Target::Python,
+                "inf -inf nan\ninf nan inf -inf\nnan -inf inf nan\n20.0 inf 0.9990234375\ninf 18.0\n",
+            ),
+            (
+                Target::JavaScript,
+                "Infinity -Infinity NaN\nInfinity NaN Infinity -Infinity\nNaN -Infinity Infinity NaN\n20 Infinity 0.9990234375\nInfinity 18\n",
+            ),
+        ] {
+            let Some(output) = run(source, target) else {
+                continue;
+            };
+            assert!(output.status.success(), "{:?}", target);
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+
+            let output = run("print (-1)!", target).unwrap();
+            assert!(!output.status.success(), "{:?}", target);
+            assert!(String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("factorial of a negative or fractional number"));
+        }
+    }
+
+    #[test]
+    fn test_rejected() {
+        for source in [
+            "x = 2 km",
+            "print y",
+            "y = f(1)\nf(x) = x",
+            "#precision 20",
+            "d = diff(x^2, x)",
+        ] {
+            assert!(transpiled(source, Target::Python).is_err(), "{}", source);
+        }
+        assert_eq!(
+            transpiled("f(a) = a*b\ng(b) = f(b)", Target::Rust),
+            Err("transpile::The parameter b of g, which hides a global that g reads, cannot be transpiled".to_string())
+        );
+    }
+}
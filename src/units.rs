@@ -0,0 +1,484 @@
+use crate::value::{Operator, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The SI base units, in the order their exponents are stored.
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/// Named units as (name, factor to SI, exponents of the base units, whether
+/// the unit accepts an SI prefix).
+const UNITS: [(&str, f64, [i32; 7], bool); 31] = [
+    ("m", 1.0, [0, 1, 0, 0, 0, 0, 0], true),
+    ("g", 1e-3, [1, 0, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [1, -1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [1, 2, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [1, 2, -3, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [1, 2, -3, -1, 0, 0, 0], true),
+    ("ohm", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+    ("Ω", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+    ("F", 1.0, [-1, -2, 4, 2, 0, 0, 0], true),
+    ("T", 1.0, [1, 0, -2, -1, 0, 0, 0], true),
+    ("Wb", 1.0, [1, 2, -2, -1, 0, 0, 0], true),
+    ("H", 1.0, [1, 2, -2, -2, 0, 0, 0], true),
+    ("L", 1e-3, [0, 3, 0, 0, 0, 0, 0], true),
+    ("eV", 1.602176634e-19, [1, 2, -2, 0, 0, 0, 0], true),
+    ("cal", 4.184, [1, 2, -2, 0, 0, 0, 0], true),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("t", 1000.0, [1, 0, 0, 0, 0, 0, 0], false),
+    ("bar", 1e5, [1, -1, -2, 0, 0, 0, 0], false),
+    ("atm", 101325.0, [1, -1, -2, 0, 0, 0, 0], false),
+    ("ft", 0.3048, [0, 1, 0, 0, 0, 0, 0], false),
+    ("mi", 1609.344, [0, 1, 0, 0, 0, 0, 0], false),
+];
+
+const PREFIXES: [(&str, f64); 20] = [
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("µ", 1e-6),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+];
+
+/// The exponents of the SI base units that make up a physical dimension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dimension([i32; 7]);
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0; 7]);
+
+    pub fn is_none(&self) -> bool {
+        *self == Dimension::NONE
+    }
+
+    /// Multiplies (`sign` 1) or divides (`sign` -1) two dimensions.
+    fn combine(&self, other: &Dimension, sign: i32) -> Dimension {
+        let mut exponents = self.0;
+        for (exponent, other) in exponents.iter_mut().zip(other.0) {
+            *exponent += sign * other;
+        }
+        Dimension(exponents)
+    }
+
+    /// Raises the dimension to a power, if every exponent stays integral.
+    fn scale(&self, power: f64) -> Option<Dimension> {
+        let mut exponents = self.0;
+        for exponent in exponents.iter_mut() {
+            let scaled = *exponent as f64 * power;
+            if scaled.fract() != 0.0 || !scaled.is_finite() {
+                return None;
+            }
+            *exponent = scaled as i32;
+        }
+        Some(Dimension(exponents))
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .map(|(name, exponent)| (*name, exponent));
+        write!(f, "{}", product(terms))
+    }
+}
+
+/// Writes symbols raised to integer powers as a product, such as
+/// `kg m/s^2`, skipping zero powers.
+fn product<'a>(terms: impl Iterator<Item = (&'a str, i32)> + Clone) -> String {
+    let power = |name: &str, exponent: i32| match exponent {
+        1 => name.to_string(),
+        _ => format!("{}^{}", name, exponent),
+    };
+    let mut numerator = vec![];
+    let mut denominator = vec![];
+    for (name, exponent) in terms.clone() {
+        match exponent.cmp(&0) {
+            Ordering::Greater => numerator.push(power(name, exponent)),
+            Ordering::Less => denominator.push(power(name, -exponent)),
+            Ordering::Equal => {}
+        }
+    }
+
+    match (numerator.is_empty(), denominator.len()) {
+        (_, 0) => numerator.join(" "),
+        (true, _) => {
+            let inverse = terms
+                .filter(|&(_, exponent)| exponent < 0)
+                .map(|(name, exponent)| power(name, exponent));
+            inverse.collect::<Vec<_>>().join(" ")
+        }
+        (false, 1) => format!("{}/{}", numerator.join(" "), denominator[0]),
+        (false, _) => format!("{}/({})", numerator.join(" "), denominator.join(" ")),
+    }
+}
+
+/// Splits a unit name written by `product`, such as `km/h^2`, into its
+/// symbols and their powers, or `None` if it has some other shape.
+fn terms(name: &str) -> Option<Vec<(String, i32)>> {
+    let term = |term: &str, sign: i32| -> Option<(String, i32)> {
+        let (symbol, exponent) = match term.split_once('^') {
+            Some((symbol, exponent)) => (symbol, exponent.parse::<i32>().ok()?),
+            None => (term, 1),
+        };
+        if symbol.is_empty() || symbol.contains(['(', ')', '/']) {
+            return None;
+        }
+        Some((symbol.to_string(), sign * exponent))
+    };
+
+    let (numerator, denominator) = match name.split_once('/') {
+        Some((numerator, denominator)) => {
+            let grouped = denominator
+                .strip_prefix('(')
+                .and_then(|denominator| denominator.strip_suffix(')'));
+            (numerator, Some(grouped.unwrap_or(denominator)))
+        }
+        None => (name, None),
+    };
+    let numerator = numerator.split(' ').map(|symbol| term(symbol, 1));
+    let denominator = denominator
+        .into_iter()
+        .flat_map(|denominator| denominator.split(' '))
+        .map(|symbol| term(symbol, -1));
+    numerator.chain(denominator).collect()
+}
+
+/// The name of the product of the unit named `left` and the one named
+/// `right` raised to `power`, with the powers of repeated symbols added up, as in `m^2` for
+/// `m m`.
+fn combine_names(left: &str, right: &str, power: i32) -> Option<String> {
+    let mut combined = terms(left)?;
+    for (symbol, exponent) in terms(right)? {
+        match combined.iter_mut().find(|(name, _)| *name == symbol) {
+            Some((_, total)) => *total += power * exponent,
+            None => combined.push((symbol, power * exponent)),
+        }
+    }
+    let terms = combined
+        .iter()
+        .map(|(name, exponent)| (name.as_str(), *exponent));
+    Some(product(terms)).filter(|name| !name.is_empty())
+}
+
+/// A unit of measurement, such as `km/h`, with its conversion factor to SI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    pub name: String,
+    pub factor: f64,
+    pub dimension: Dimension,
+}
+
+impl Unit {
+    /// Looks up a single unit name, optionally preceded by an SI prefix.
+    pub fn lookup(name: &str) -> Option<Unit> {
+        let unit = |&(symbol, factor, exponents, _): &(&str, f64, [i32; 7], bool)| Unit {
+            name: symbol.to_string(),
+            factor,
+            dimension: Dimension(exponents),
+        };
+        if let Some(found) = UNITS.iter().find(|(symbol, ..)| *symbol == name) {
+            return Some(unit(found));
+        }
+
+        PREFIXES.iter().find_map(|&(prefix, scale)| {
+            let base = name.strip_prefix(prefix)?;
+            let found = UNITS
+                .iter()
+                .find(|&&(symbol, _, _, prefixable)| prefixable && symbol == base)?;
+            let base = unit(found);
+            Some(Unit {
+                name: name.to_string(),
+                factor: scale * base.factor,
+                dimension: base.dimension,
+            })
+        })
+    }
+
+    pub fn multiply(&self, rhs: &Unit) -> Unit {
+        Unit {
+            name: combine_names(&self.name, &rhs.name, 1)
+                .unwrap_or_else(|| format!("{} {}", self.name, rhs.name)),
+            factor: self.factor * rhs.factor,
+            dimension: self.dimension.combine(&rhs.dimension, 1),
+        }
+    }
+
+    pub fn divide(&self, rhs: &Unit) -> Unit {
+        Unit {
+            name: combine_names(&self.name, &rhs.name, -1)
+                .unwrap_or_else(|| format!("{}/{}", self.name, rhs.name)),
+            factor: self.factor / rhs.factor,
+            dimension: self.dimension.combine(&rhs.dimension, -1),
+        }
+    }
+
+    pub fn powi(&self, exponent: i32) -> Unit {
+        let scaled = terms(&self.name).map(|terms| {
+            product(
+                terms
+                    .iter()
+                    .map(|(name, power)| (name.as_str(), power * exponent)),
+            )
+        });
+        let name = match scaled.filter(|name| !name.is_empty()) {
+            Some(name) => name,
+            None if self.name.contains([' ', '/']) => format!("({})^{}", self.name, exponent),
+            None => format!("{}^{}", self.name, exponent),
+        };
+        Unit {
+            name,
+            factor: self.factor.powi(exponent),
+            dimension: self
+                .dimension
+                .scale(exponent as f64)
+                .unwrap_or(Dimension::NONE),
+        }
+    }
+}
+
+/// A value with a physical dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    /// The magnitude in SI base units.
+    pub magnitude: Box<Value>,
+    pub dimension: Dimension,
+    /// The unit the quantity is displayed in, or `None` for SI base units.
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    /// Builds a value from a magnitude expressed in `unit`.
+    pub fn with_unit(magnitude: Value, unit: &Unit) -> Result<Value, String> {
+        let magnitude = (magnitude * Value::Number(unit.factor))?;
+        Ok(Quantity::from_parts(
+            magnitude,
+            unit.dimension,
+            Some(unit.clone()),
+        ))
+    }
+
+    /// Builds a value, collapsing dimensionless quantities to plain numbers.
+    pub fn from_parts(magnitude: Value, dimension: Dimension, unit: Option<Unit>) -> Value {
+        if dimension.is_none() {
+            return magnitude;
+        }
+        Value::Quantity(Quantity {
+            magnitude: Box::new(magnitude),
+            dimension,
+            unit,
+        })
+    }
+
+    pub fn dimensionless(magnitude: Value) -> Quantity {
+        Quantity {
+            magnitude: Box::new(magnitude),
+            dimension: Dimension::NONE,
+            unit: None,
+        }
+    }
+
+    /// Re-expresses a value in another unit of the same dimension.
+    pub fn convert(value: Value, unit: &Unit) -> Result<Value, String> {
+        let quantity = match value {
+            Value::Quantity(quantity) => quantity,
+            value => Quantity::dimensionless(value),
+        };
+        if quantity.dimension != unit.dimension {
+            return Err(format!(
+                "Cannot convert {} to {}",
+                quantity.dimension_name(),
+                unit.name
+            ));
+        }
+        Ok(Value::Quantity(Quantity {
+            unit: Some(unit.clone()),
+            ..quantity
+        }))
+    }
+
+    pub fn apply(self, operator: Operator, rhs: Quantity) -> Result<Value, String> {
+        match operator {
+            Operator::Add | Operator::Subtract => {
+                if self.dimension != rhs.dimension {
+                    return Err(format!(
+                        "Incompatible dimensions: {} and {}",
+                        self.dimension_name(),
+                        rhs.dimension_name()
+                    ));
+                }
+                let magnitude = self.magnitude.apply(operator, *rhs.magnitude)?;
+                Ok(Quantity::from_parts(
+                    magnitude,
+                    self.dimension,
+                    self.unit.or(rhs.unit),
+                ))
+            }
+            Operator::Multiply | Operator::Divide => {
+                let sign = if operator == Operator::Multiply {
+                    1
+                } else {
+                    -1
+                };
+                let dimension = self.dimension.combine(&rhs.dimension, sign);
+                let unit = match (self.unit, rhs.unit) {
+                    (Some(left), Some(right)) if operator == Operator::Multiply => {
+                        Some(left.multiply(&right))
+                    }
+                    (Some(left), Some(right)) => Some(left.divide(&right)),
+                    (left, None) if rhs.dimension.is_none() => left,
+                    (None, right) if self.dimension.is_none() && sign == 1 => right,
+                    _ => None,
+                };
+                let magnitude = self.magnitude.apply(operator, *rhs.magnitude)?;
+                Ok(Quantity::from_parts(magnitude, dimension, unit))
+            }
+            Operator::Power => {
+                if !rhs.dimension.is_none() {
+                    return Err("Exponents must be dimensionless".to_owned());
+                }
+                let exponent = rhs.magnitude.to_f64();
+                let dimension = self.dimension.scale(exponent).ok_or_else(|| {
+                    format!(
+                        "Cannot raise {} to the power {}",
+                        self.dimension_name(),
+                        exponent
+                    )
+                })?;
+                let unit = match exponent.fract() == 0.0 {
+                    true => self.unit.map(|unit| unit.powi(exponent as i32)),
+                    false => None,
+                };
+                let magnitude = self.magnitude.pow(*rhs.magnitude)?;
+                Ok(Quantity::from_parts(magnitude, dimension, unit))
+            }
+        }
+    }
+
+    /// Applies a function to the magnitude, raising the dimension to `power`.
+    pub fn map(
+        self, power: f64, function: impl FnOnce(Value) -> Result<Value, String>,
+    ) -> Result<Value, String> {
+        let dimension = self.dimension.scale(power).ok_or_else(|| {
+            format!(
+                "Cannot raise {} to the power {}",
+                self.dimension_name(),
+                power
+            )
+        })?;
+        let unit = if power == 1.0 { self.unit } else { None };
+        Ok(Quantity::from_parts(
+            function(*self.magnitude)?,
+            dimension,
+            unit,
+        ))
+    }
+
+    fn dimension_name(&self) -> String {
+        match self.dimension.is_none() {
+            true => "a dimensionless number".to_string(),
+            false => self.dimension.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => {
+                let magnitude = (*self.magnitude).clone() / Value::Number(unit.factor);
+                match magnitude {
+                    Ok(magnitude) => write!(f, "{} {}", magnitude, unit.name),
+                    Err(_) => write!(f, "{} {}", self.magnitude, self.dimension),
+                }
+            }
+            None => write!(f, "{} {}", self.magnitude, self.dimension),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let kilometre = Unit::lookup("km").unwrap();
+        assert_eq!(kilometre.factor, 1000.0);
+        assert_eq!(kilometre.dimension, Unit::lookup("m").unwrap().dimension);
+        assert_eq!(Unit::lookup("kg").unwrap().factor, 1.0);
+        assert_eq!(Unit::lookup("min").unwrap().factor, 60.0);
+        assert_eq!(Unit::lookup("kh"), None);
+        assert_eq!(Unit::lookup("x"), None);
+    }
+
+    #[test]
+    fn test_dimension_display() {
+        let newton = Unit::lookup("N").unwrap();
+        assert_eq!(newton.dimension.to_string(), "kg m/s^2");
+        let hertz = Unit::lookup("Hz").unwrap();
+        assert_eq!(hertz.dimension.to_string(), "s^-1");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let metre = Unit::lookup("m").unwrap();
+        let kilometre = Unit::lookup("km").unwrap();
+        let distance = (Quantity::with_unit(Value::Number(3.0), &kilometre).unwrap()
+            + Quantity::with_unit(Value::Number(200.0), &metre).unwrap())
+        .unwrap();
+        assert_eq!(distance.to_string(), "3.2 km");
+
+        let second = Unit::lookup("s").unwrap();
+        let time = Quantity::with_unit(Value::Number(2.0), &second).unwrap();
+        assert!((distance.clone() + time.clone()).is_err());
+
+        let speed = (distance / time).unwrap();
+        let per_hour = kilometre.divide(&Unit::lookup("h").unwrap());
+        let converted = Quantity::convert(speed, &per_hour).unwrap();
+        assert_eq!(converted.to_string(), "5760 km/h");
+    }
+
+    #[test]
+    fn test_unit_names() {
+        let unit = |name| Unit::lookup(name).unwrap();
+        let metre = unit("m");
+        assert_eq!(metre.multiply(&metre).name, "m^2");
+        assert_eq!(metre.powi(2).divide(&metre).name, "m");
+        assert_eq!(unit("N").multiply(&metre).name, "N m");
+        assert_eq!(unit("km").divide(&unit("h")).powi(2).name, "km^2/h^2");
+        assert_eq!(
+            unit("kg").divide(&unit("s")).divide(&metre).name,
+            "kg/(s m)"
+        );
+        assert_eq!(
+            unit("s").powi(-1).multiply(&unit("s").powi(-1)).name,
+            "s^-2"
+        );
+    }
+}
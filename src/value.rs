@@ -0,0 +1,285 @@
+use crate::decimal::Decimal;
+use crate::interval::Interval;
+use crate::numeric;
+use crate::symbolic::Function;
+use crate::units::Quantity;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A runtime value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Decimal(Decimal),
+    Interval(Interval),
+    Quantity(Quantity),
+    Function(Function),
+    List(Vec<Value>),
+}
+
+const FUNCTION_OPERAND: &str = "Functions must be called before use in arithmetic";
+
+/// A binary arithmetic operator, applied to every kind of value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+impl Operator {
+    fn apply_float(self, left: f64, right: f64) -> f64 {
+        match self {
+            Operator::Add => left + right,
+            Operator::Subtract => left - right,
+            Operator::Multiply => left * right,
+            Operator::Divide => left / right,
+            Operator::Power => left.powf(right),
+        }
+    }
+
+    fn apply_decimal(self, left: &Decimal, right: &Decimal) -> Result<Decimal, String> {
+        match self {
+            Operator::Add => Ok(left + right),
+            Operator::Subtract => Ok(left - right),
+            Operator::Multiply => Ok(left * right),
+            Operator::Divide => left
+                .checked_div(right)
+                .ok_or_else(|| "Division by zero".to_owned()),
+            Operator::Power => left.pow(right),
+        }
+    }
+}
+
+impl Value {
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Number(value) => *value,
+            Value::Decimal(value) => value.to_f64(),
+            Value::Interval(value) => value.midpoint(),
+            Value::Quantity(value) => value.magnitude.to_f64(),
+            Value::Function(_) | Value::List(_) => f64::NAN,
+        }
+    }
+
+    /// Converts to an interval, widening decimals that have no exact `f64`
+    /// and floats that are not exactly the decimal they print as.
+    pub fn to_interval(&self) -> Interval {
+        match self {
+            Value::Number(value) => Interval::enclosing(*value),
+            Value::Decimal(value) => Interval::around(value.to_f64()),
+            Value::Interval(value) => *value,
+            Value::Quantity(value) => value.magnitude.to_interval(),
+            Value::Function(_) | Value::List(_) => Interval::point(f64::NAN),
+        }
+    }
+
+    /// Applies a binary operator. Mixed operands are promoted to the wider
+    /// kind: floats to decimals, and anything to an interval. Quantities
+    /// carry their magnitude through the same promotion, and lists apply
+    /// the operator element by element.
+    pub fn apply(self, operator: Operator, rhs: Value) -> Result<Value, String> {
+        match (self, rhs) {
+            (Value::Number(left), Value::Number(right)) => {
+                Ok(Value::Number(operator.apply_float(left, right)))
+            }
+            (Value::Function(_), _) | (_, Value::Function(_)) => Err(FUNCTION_OPERAND.to_owned()),
+            (Value::List(left), Value::List(right)) => {
+                if left.len() != right.len() {
+                    return Err(format!(
+                        "Lists of length {} and {} cannot be combined",
+                        left.len(),
+                        right.len()
+                    ));
+                }
+                let pairs = left.into_iter().zip(right);
+                pairs
+                    .map(|(left, right)| left.apply(operator, right))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            (Value::List(left), right) => left
+                .into_iter()
+                .map(|left| left.apply(operator, right.clone()))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            (left, Value::List(right)) => right
+                .into_iter()
+                .map(|right| left.clone().apply(operator, right))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            (Value::Quantity(left), Value::Quantity(right)) => left.apply(operator, right),
+            (Value::Quantity(left), right) => left.apply(operator, Quantity::dimensionless(right)),
+            (left, Value::Quantity(right)) => Quantity::dimensionless(left).apply(operator, right),
+            (left @ Value::Interval(_), right) | (left, right @ Value::Interval(_)) => left
+                .to_interval()
+                .apply(operator, &right.to_interval())
+                .map(Value::Interval),
+            (left, right) => {
+                let precision = left.decimal_precision().max(right.decimal_precision());
+                let (left, right) = (left.to_decimal(precision)?, right.to_decimal(precision)?);
+                operator.apply_decimal(&left, &right).map(Value::Decimal)
+            }
+        }
+    }
+
+    pub fn pow(self, rhs: Value) -> Result<Value, String> {
+        self.apply(Operator::Power, rhs)
+    }
+
+    pub fn factorial(self) -> Result<Value, String> {
+        match self {
+            Value::Number(value) => numeric::factorial(value).map(Value::Number),
+            Value::Decimal(value) => value.factorial().map(Value::Decimal),
+            Value::Interval(value) => value.factorial().map(Value::Interval),
+            Value::Quantity(_) => Err("factorial expects a dimensionless argument".to_owned()),
+            Value::Function(_) => Err(FUNCTION_OPERAND.to_owned()),
+            Value::List(values) => values
+                .into_iter()
+                .map(Value::factorial)
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+        }
+    }
+
+    fn decimal_precision(&self) -> usize {
+        match self {
+            Value::Decimal(value) => value.precision(),
+            _ => 0,
+        }
+    }
+
+    fn to_decimal(&self, precision: usize) -> Result<Decimal, String> {
+        match self {
+            Value::Decimal(value) => Ok(value.clone()),
+            _ => {
+                let value = self.to_f64();
+                Decimal::from_f64(value, precision)
+                    .ok_or_else(|| format!("Cannot represent {} as a decimal", value))
+            }
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Result<Value, String>;
+
+    fn add(self, rhs: Value) -> Self::Output {
+        self.apply(Operator::Add, rhs)
+    }
+}
+
+impl Sub for Value {
+    type Output = Result<Value, String>;
+
+    fn sub(self, rhs: Value) -> Self::Output {
+        self.apply(Operator::Subtract, rhs)
+    }
+}
+
+impl Mul for Value {
+    type Output = Result<Value, String>;
+
+    fn mul(self, rhs: Value) -> Self::Output {
+        self.apply(Operator::Multiply, rhs)
+    }
+}
+
+impl Div for Value {
+    type Output = Result<Value, String>;
+
+    fn div(self, rhs: Value) -> Self::Output {
+        self.apply(Operator::Divide, rhs)
+    }
+}
+
+impl Neg for Value {
+    type Output = Result<Value, String>;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::Number(value) => Ok(Value::Number(-value)),
+            Value::Decimal(value) => Ok(Value::Decimal(-&value)),
+            Value::Interval(value) => Ok(Value::Interval(-value)),
+            Value::Quantity(value) => value.map(1.0, |magnitude| -magnitude),
+            Value::Function(_) => Err(FUNCTION_OPERAND.to_owned()),
+            Value::List(values) => values
+                .into_iter()
+                .map(|value| -value)
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Decimal(value) => write!(f, "{}", value),
+            Value::Interval(value) => write!(f, "{}", value),
+            Value::Quantity(value) => write!(f, "{}", value),
+            Value::Function(value) => write!(f, "{}", value),
+            Value::List(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_arithmetic() {
+        let third = (Value::Decimal(Decimal::from_integer(1, 20)) / Value::Number(3.0)).unwrap();
+        assert_eq!(third.to_string(), "0.33333333333333333333");
+        assert_eq!(
+            (Value::Number(0.5) + Value::Number(0.25)).unwrap(),
+            Value::Number(0.75)
+        );
+    }
+
+    #[test]
+    fn test_interval_promotion() {
+        let interval = Value::Interval(Interval::new(1.0, 2.0).unwrap());
+        let sum = (Value::Number(1.0) + interval).unwrap();
+        assert_eq!(sum, Value::Interval(Interval::new(2.0, 3.0).unwrap()));
+
+        // 0.1 is not an f64, so the interval must reach past the nearest one
+        let tenth = Value::Number(0.1).to_interval();
+        assert!(tenth.lo < 0.1 && tenth.hi > 0.1);
+    }
+
+    #[test]
+    fn test_decimal_division_by_zero() {
+        let zero = Value::Decimal(Decimal::from_integer(0, 20));
+        assert!((Value::Number(1.0) / zero).is_err());
+    }
+
+    #[test]
+    fn test_list_arithmetic() {
+        let list = Value::List(vec![Value::Number(1.0), Value::Number(2.0)]);
+        let doubled = (list.clone() * Value::Number(2.0)).unwrap();
+        assert_eq!(doubled.to_string(), "[2, 4]");
+        assert_eq!((list.clone() + doubled).unwrap().to_string(), "[3, 6]");
+        assert!((list + Value::List(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_factorial() {
+        let factorial = |x: f64| Value::Number(x).factorial();
+        assert_eq!(factorial(5.0), Ok(Value::Number(120.0)));
+        assert_eq!(factorial(1e9), Ok(Value::Number(f64::INFINITY)));
+        for x in [-1.0, 3.7, f64::NAN] {
+            assert_eq!(
+                factorial(x),
+                Err("factorial of a negative or fractional number".to_string())
+            );
+        }
+    }
+}
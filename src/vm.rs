@@ -0,0 +1,311 @@
+use crate::bytecode::{Chunk, Instruction, Program};
+use crate::interpreter::Interpreter;
+use crate::interval::Interval;
+use crate::symbolic::Function;
+use crate::units::Quantity;
+use crate::value::Value;
+
+/// Runs a compiled program against the interpreter's settings and
+/// variables, which it reads on entry and updates on exit.
+pub fn run(interpreter: &mut Interpreter, program: &Program) -> Result<(), String> {
+    let mut vm = Vm {
+        interpreter,
+        program,
+        globals: vec![None; program.globals.len()],
+        compiled: vec![None; program.globals.len()],
+        stack: vec![],
+        line: String::new(),
+    };
+    vm.load_globals();
+    let mut locals = vec![Value::Number(0.0); program.main.locals];
+    let result = vm.execute(&program.main, &mut locals);
+    vm.store_globals();
+    result
+}
+
+struct Vm<'i, 'a> {
+    interpreter: &'i mut Interpreter<'a>,
+    program: &'i Program,
+    globals: Vec<Option<Value>>,
+    /// The compiled function bound to each global slot, if it still holds
+    /// the function its `Define` put there.
+    compiled: Vec<Option<usize>>,
+    stack: Vec<Value>,
+    /// The line `print` is building.
+    line: String,
+}
+
+impl Vm<'_, '_> {
+    fn execute(&mut self, chunk: &Chunk, locals: &mut [Value]) -> Result<(), String> {
+        let program = self.program;
+        let mut pc = 0;
+        while let Some(&instruction) = chunk.code.get(pc) {
+            pc += 1;
+            match instruction {
+                Instruction::Number(index) => {
+                    let value = self.interpreter.number(program.numbers[index]);
+                    self.stack.push(value);
+                }
+                Instruction::Literal(index) => {
+                    let value = self.interpreter.literal(&program.strings[index])?;
+                    self.stack.push(value);
+                }
+                Instruction::Load(slot) => match &self.globals[slot] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(format!("Undefined variable: {}", program.globals[slot])),
+                },
+                Instruction::Store(slot) => {
+                    self.globals[slot] = Some(self.pop());
+                    self.compiled[slot] = None;
+                }
+                Instruction::LoadLocal(slot) => self.stack.push(locals[slot].clone()),
+                Instruction::StoreLocal(slot) => locals[slot] = self.pop(),
+                Instruction::Add => self.binary(|left, right| left + right)?,
+                Instruction::Subtract => self.binary(|left, right| left - right)?,
+                Instruction::Multiply => self.binary(|left, right| left * right)?,
+                Instruction::Divide => self.binary(|left, right| left / right)?,
+                Instruction::Power => self.binary(Value::pow)?,
+                Instruction::Interval => self.binary(|lower, upper| {
+                    Interval::new(lower.to_interval().lo, upper.to_interval().hi)
+                        .map(Value::Interval)
+                })?,
+                Instruction::PlusMinus => self.binary(|midpoint, radius| {
+                    Interval::from_midpoint(&midpoint.to_interval(), radius.to_interval().hi)
+                        .map(Value::Interval)
+                })?,
+                Instruction::Negate => {
+                    let value = (-self.pop())?;
+                    self.stack.push(value);
+                }
+                Instruction::Factorial => {
+                    let value = self.pop().factorial()?;
+                    self.stack.push(value);
+                }
+                Instruction::Degrees => {
+                    let value = self.pop();
+                    let value = self.interpreter.degrees(value)?;
+                    let value = self.interpreter.float_unless_integer(value);
+                    self.stack.push(value);
+                }
+                Instruction::Quantity(index) => {
+                    let value = Quantity::with_unit(self.pop(), &program.units[index])?;
+                    self.stack.push(value);
+                }
+                Instruction::Convert(index) => {
+                    let value = Quantity::convert(self.pop(), &program.units[index])?;
+                    self.stack.push(value);
+                }
+                Instruction::List(length) => {
+                    let items = self.stack.split_off(self.stack.len() - length);
+                    self.stack.push(Value::List(items));
+                }
+                Instruction::Call(slot, count) => {
+                    let arguments = self.stack.split_off(self.stack.len() - count);
+                    self.call(slot, arguments)?;
+                }
+                Instruction::Define(index) => {
+                    let function = &program.functions[index];
+                    self.globals[function.slot] = Some(Value::Function(Function {
+                        parameters: function.parameters.clone(),
+                        body: function.body.clone(),
+                    }));
+                    self.compiled[function.slot] = Some(index);
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Text(index) => self.line.push_str(&program.strings[index]),
+                Instruction::Write(format) => {
+                    let value = self.pop();
+                    let format = format.map(|index| &program.formats[index]);
+                    let text = self.interpreter.display(&value, format)?;
+                    self.line.push_str(&text);
+                }
+                Instruction::PrintLine => {
+                    println!("{}", self.line);
+                    self.line.clear();
+                }
+                Instruction::SetFormat(format) => {
+                    self.interpreter.format = format.map(|index| program.formats[index].clone());
+                }
+                Instruction::Pragma(index) => {
+                    // pragmas may evaluate their argument and redefine `pi`
+                    let (name, argument) = &program.pragmas[index];
+                    self.store_globals();
+                    self.interpreter.apply_pragma(name, argument.clone())?;
+                    self.load_globals();
+                }
+                Instruction::Bounds(index) => {
+                    let name = &program.strings[index];
+                    let last = self.pop();
+                    let first = self.pop();
+                    let first = self.interpreter.integer_bound(name, &first)?;
+                    let last = self.interpreter.integer_bound(name, &last)?;
+                    self.stack.push(Value::Number(first as f64));
+                    self.stack.push(Value::Number(last as f64));
+                }
+                Instruction::ForLoop {
+                    counter,
+                    last,
+                    index,
+                    exit,
+                } => {
+                    let counter = locals[counter].to_f64();
+                    if counter > locals[last].to_f64() {
+                        pc = exit;
+                    } else {
+                        locals[index] = self.interpreter.number(counter);
+                    }
+                }
+                Instruction::Next { counter, start } => {
+                    locals[counter] = Value::Number(locals[counter].to_f64() + 1.0);
+                    pc = start;
+                }
+                Instruction::Evaluate(index) => {
+                    // the tree walker reads globals from the interpreter
+                    let deferred = &program.deferred[index];
+                    let bindings = deferred
+                        .locals
+                        .iter()
+                        .map(|(name, slot)| (name.clone(), locals[*slot].clone()))
+                        .collect();
+                    self.store_globals();
+                    let value = self
+                        .interpreter
+                        .evaluate_with(bindings, deferred.expression.clone())?;
+                    self.stack.push(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, slot: usize, arguments: Vec<Value>) -> Result<(), String> {
+        let program = self.program;
+        let name = &program.globals[slot];
+        let result = match (&self.globals[slot], self.compiled[slot]) {
+            (Some(Value::Function(function)), Some(index)) => {
+                if arguments.len() != function.parameters.len() {
+                    return Err(format!(
+                        "{} expects {} argument(s), found {}",
+                        name,
+                        function.parameters.len(),
+                        arguments.len()
+                    ));
+                }
+                let chunk = &program.functions[index].chunk;
+                let mut locals = arguments;
+                locals.resize(chunk.locals, Value::Number(0.0));
+                return self.execute(chunk, &mut locals);
+            }
+            // a function made some other way, such as by `diff`, is walked
+            (Some(Value::Function(function)), None) => {
+                let function = function.clone();
+                self.store_globals();
+                self.interpreter.call_function(name, function, arguments)
+            }
+            (Some(_), _) => Err(format!("{} is not a function", name)),
+            (None, _) => self.interpreter.call_builtin(name, arguments),
+        }?;
+        let result = self.interpreter.float_unless_integer(result);
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn binary(
+        &mut self, operation: impl FnOnce(Value, Value) -> Result<Value, String>,
+    ) -> Result<(), String> {
+        let right = self.pop();
+        let left = self.pop();
+        let value = operation(left, right)?;
+        let value = self.interpreter.float_unless_integer(value);
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    fn load_globals(&mut self) {
+        for (slot, name) in self.program.globals.iter().enumerate() {
+            if let Some(value) = self.interpreter.variables.get(name) {
+                self.globals[slot] = Some(value.clone());
+            }
+        }
+    }
+
+    fn store_globals(&mut self) {
+        for (name, value) in self.program.globals.iter().zip(&self.globals) {
+            if let Some(value) = value {
+                self.interpreter
+                    .variables
+                    .insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::compile;
+    use crate::lexer::Lexer;
+    use crate::optimizer::{optimize, DEFAULT_LEVEL};
+    use crate::parser::Parser;
+
+    /// Runs `source` through the tree-walker and through the compiler and
+    /// virtual machine, and checks they agree.
+    fn differential(source: &str) -> Result<(), String> {
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        let mut walker = Interpreter::new(&mut parser);
+        let walked = walker.interpret();
+
+        let mut lexer = Lexer::new(source);
+        let mut parser = Parser::new(&mut lexer);
+        let statements = optimize(parser.parse()?, DEFAULT_LEVEL);
+        let program = compile(&statements).unwrap();
+        let mut lexer = Lexer::new("");
+        let mut parser = Parser::new(&mut lexer);
+        let mut machine = Interpreter::new(&mut parser);
+        let ran = run(&mut machine, &program);
+
+        assert_eq!(walked, ran, "{}", source);
+        assert_eq!(walker.variables, machine.variables, "{}", source);
+        ran
+    }
+
+    #[test]
+    fn test_differential() {
+        let programs = [
+            "a = 2 + 3*4^2 - -1\nb = 7!/(2 ± 0.5)\nc = [1, a, b]",
+            "f(x, y) = x*y + a\na = 2\nb = f(3, 4) + sqrt(16)\nc = 3 km + 200 m to m",
+            "s = sum(k^2, k, 1, 100)\np = prod(1 + 1/k, k, 1, 10)\nf(n) = sum(k*n, k, 1, n)\nt = f(4)",
+            "#precision 30\nx = pi/3\ny = 10000000000000000000001 + 1\n#angle deg\nz = sin(30) + 90°",
+            "a, b = 0, 1\na, b = b, a + b\na += 10\nb ^= 3\nf(x) = x^2\nc = f(a)\nh = a(2)",
+            "d = diff(x^3, x)\ne = d(2)\nf(x) = x^3\ng = f'(2)\nh = f'\nr(a) = solve(x^2 = a, x, [0 .. 10])\ns = r(4)",
+            "y = ode(dy/dt = -y, 1, 0, 1)\nt = sum(k, k, 1, 2.5)",
+            "n = 100000000000000000000000\na = n / 3 + 1°\nb = [sqrt(n), n + 1]",
+        ];
+        for source in programs {
+            let _ = differential(source);
+        }
+        assert_eq!(
+            differential("a = 1\nb = c + 1"),
+            Err("Undefined variable: c".to_string())
+        );
+        assert_eq!(
+            differential("f(x) = x\ny = f(1, 2)"),
+            Err("f expects 1 argument(s), found 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut lexer = Lexer::new("f(g) = g(2)");
+        let mut parser = Parser::new(&mut lexer);
+        let statements = parser.parse().unwrap();
+        assert!(compile(&statements).is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use rustic::compiled::CompiledExpr;
+use std::collections::HashMap;
+
+#[test]
+fn test_compiled() {
+    let expression = CompiledExpr::new("principal * (1 + rate)^years").unwrap();
+    assert_eq!(expression.variables(), ["principal", "rate", "years"]);
+    assert_eq!(
+        expression.eval(&HashMap::from([
+            ("principal", 100.0),
+            ("rate", 0.5),
+            ("years", 2.0)
+        ])),
+        Ok(225.0)
+    );
+    assert_eq!(
+        expression.eval_many(&[100.0, 0.5, 1.0, 100.0, 1.0, 3.0]),
+        Ok(vec![150.0, 800.0])
+    );
+    assert_eq!(
+        expression.eval_many(&[100.0, 0.5]),
+        Err("eval_many expects 3 value(s) per row, found 2 in total".to_string())
+    );
+}
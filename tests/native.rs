@@ -0,0 +1,50 @@
+use rustic::interpreter::Interpreter;
+use rustic::lexer::Lexer;
+use rustic::native::NativeFunction;
+use rustic::parser::Parser;
+use rustic::value::Value;
+
+struct Discount(f64);
+
+impl NativeFunction for Discount {
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, String> {
+        Ok(Value::Number(arguments[0].to_f64() * (1.0 - self.0)))
+    }
+}
+
+fn run(source: &str, compiled: bool) -> Result<Option<Value>, String> {
+    let mut lexer = Lexer::new(source);
+    let mut parser = Parser::new(&mut lexer);
+    let mut interpreter = Interpreter::new(&mut parser);
+    interpreter.register_fn("tax_rate", 1, |arguments| match arguments[0].to_f64() {
+        year if year >= 2000.0 => Ok(Value::Number(0.25)),
+        year => Err(format!("no rate for {}", year)),
+    });
+    interpreter.register("discount", 1, Discount(0.5));
+    let result = match compiled {
+        true => interpreter.interpret_compiled(),
+        false => interpreter.interpret(),
+    };
+    result.map(|_| interpreter.variables.get("total").cloned())
+}
+
+#[test]
+fn test_register() {
+    for compiled in [false, true] {
+        assert_eq!(
+            run(
+                "price = 100\ntotal = discount(price) * (1 + tax_rate(2024))",
+                compiled
+            ),
+            Ok(Some(Value::Number(62.5)))
+        );
+        assert_eq!(
+            run("total = tax_rate(1990)", compiled),
+            Err("tax_rate: no rate for 1990".to_string())
+        );
+        assert_eq!(
+            run("total = discount()", compiled),
+            Err("discount expects 1 argument(s), found 0".to_string())
+        );
+    }
+}